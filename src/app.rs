@@ -0,0 +1,166 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::compose::{Build, Command, Label, Mount, MountKind, Policy, Resources, Service};
+use crate::image::Image;
+use crate::resources::MIB;
+use crate::site::Site;
+
+/// Where `woors app` scaffolds the project, and where the `app` preset
+/// builds it from.
+pub const DIRECTORY: &str = "app";
+
+/// The WooCommerce Store API, relative to the site URL.
+const API_PATH: &str = "/wp-json/wc/store/v1";
+
+/// The project files, by path. `{{name}}` placeholders are replaced by JSON
+/// string literals, which are also valid JavaScript and TOML.
+const TEMPLATES: &[(&str, &str)] = &[
+    (".dockerignore", include_str!("../templates/app/dockerignore")),
+    (".gitignore", include_str!("../templates/app/gitignore")),
+    ("Dockerfile", include_str!("../templates/app/Dockerfile")),
+    ("README.md", include_str!("../templates/app/README.md")),
+    ("package.json", include_str!("../templates/app/package.json")),
+    ("mock/products.json", include_str!("../templates/app/mock/products.json")),
+    ("mock/server.mjs", include_str!("../templates/app/mock/server.mjs")),
+    ("src/api.js", include_str!("../templates/app/src/api.js")),
+    ("src/config.js", include_str!("../templates/app/src/config.js")),
+    ("src/index.html", include_str!("../templates/app/src/index.html")),
+    ("src/main.js", include_str!("../templates/app/src/main.js")),
+    ("src/style.css", include_str!("../templates/app/src/style.css")),
+    ("src-tauri/Cargo.toml", include_str!("../templates/app/src-tauri/Cargo.toml")),
+    ("src-tauri/build.rs", include_str!("../templates/app/src-tauri/build.rs")),
+    ("src-tauri/capabilities/default.json", include_str!("../templates/app/src-tauri/capabilities/default.json")),
+    ("src-tauri/src/main.rs", include_str!("../templates/app/src-tauri/src/main.rs")),
+    ("src-tauri/tauri.conf.json", include_str!("../templates/app/src-tauri/tauri.conf.json")),
+    ("test/api.test.mjs", include_str!("../templates/app/test/api.test.mjs")),
+];
+
+const ICON: (&str, &[u8]) = ("src-tauri/icons/icon.png", include_bytes!("../templates/app/src-tauri/icons/icon.png"));
+
+/// The npm and Cargo package name of the project, e.g. `my-shop-app`.
+pub fn package(site: &Site) -> String {
+    format!("{}-app", site.slug().replace('_', "-"))
+}
+
+/// The reverse domain bundle identifier of the application, e.g.
+/// `com.shop.app` for `shop.com`.
+pub fn identifier(site: &Site) -> String {
+    let host = site.host().split(':').next().unwrap_or_default();
+    let mut labels: Vec<String> = host
+        .rsplit('.')
+        .map(|label| {
+            label
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+                .collect::<String>()
+                .to_ascii_lowercase()
+        })
+        .filter(|label| !label.is_empty())
+        .collect();
+    labels.push("app".to_string());
+    labels.join(".")
+}
+
+/// The Store API endpoint of the site.
+pub fn api_url(site: &Site) -> String {
+    format!("{}{}", site.home_url(), API_PATH)
+}
+
+/// Writes the client project of `site` to `directory` and returns the
+/// created files. A non-empty directory is only overwritten with `force`.
+pub fn scaffold(site: &Site, directory: &Path, force: bool) -> io::Result<Vec<PathBuf>> {
+    let occupied = match fs::read_dir(directory) {
+        Ok(mut entries) => entries.next().is_some(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => false,
+        Err(err) => return Err(err),
+    };
+    if occupied && !force {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} is not empty; pass --force to overwrite it", directory.display()),
+        ));
+    }
+
+    let mut files = Vec::new();
+    for (path, template) in TEMPLATES {
+        files.push(write(directory, path, render(template, site).as_bytes())?);
+    }
+    files.push(write(directory, ICON.0, ICON.1)?);
+    Ok(files)
+}
+
+fn write(directory: &Path, path: &str, content: &[u8]) -> io::Result<PathBuf> {
+    let path = directory.join(path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, content)?;
+    Ok(path)
+}
+
+fn render(template: &str, site: &Site) -> String {
+    let values = [
+        ("package", package(site)),
+        ("identifier", identifier(site)),
+        ("site_title", site.title.clone()),
+        ("site_url", site.home_url()),
+        ("api_url", api_url(site)),
+    ];
+    let mut content = template.to_string();
+    for (name, value) in values {
+        let literal = serde_json::to_string(&value).unwrap_or_default();
+        content = content.replace(&format!("{{{{{}}}}}", name), &literal);
+    }
+    content
+}
+
+/// The one-shot `app` service of the `app` preset. Its image is built from
+/// the project scaffolded in [`DIRECTORY`], running the tests against the
+/// mock API on the way; the container only copies the Linux packages to
+/// `./app/bundle`, so its limits are small.
+pub fn service(site: &Site) -> Service {
+    Service {
+        image: Image {
+            repository: package(site),
+            tag: "latest".to_string(),
+            ..Default::default()
+        },
+        build: Some(Build {
+            context: format!("./{}", DIRECTORY)
+        }),
+        container_name: "app".to_string(),
+        ports: vec![],
+        volumes: vec![
+            Mount {
+                kind: MountKind::Bind,
+                source: format!("./{}/bundle", DIRECTORY),
+                target: "/out".to_string(),
+                read_only: false
+            }
+        ],
+        networks: vec![],
+        depends_on: vec![],
+        restart: Policy {
+            condition: "no".to_string()
+        },
+        environments: vec![],
+        command: Command {
+            value: "".to_string()
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "Native Application Build".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.25),
+            memory: Some(128 * MIB),
+            reservation_memory: Some(16 * MIB),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
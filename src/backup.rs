@@ -0,0 +1,561 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::compose::{
+    Command, Dependency, Environment, Healthcheck, Label, Mount, MountKind, NetworkAttachment, Policy, Port,
+    Resources, Service,
+};
+use crate::database::Engine;
+use crate::image::catalog_image;
+use crate::resources::MIB;
+use crate::secrets::Secrets;
+
+/// Default local directory of the archives, relative to the compose file.
+pub const DIRECTORY: &str = "./backups";
+
+/// Default cron expression: every night at 3.
+pub const SCHEDULE: &str = "0 3 * * *";
+
+/// Archive names, as `strftime` patterns; pruning only touches files with
+/// the prefix.
+const PREFIX: &str = "backup-";
+const FILENAME: &str = "backup-%Y-%m-%dT%H-%M-%S.tar.gz";
+
+/// Where the database container writes its dump, on the `backup-dumps`
+/// volume.
+const DUMPS_DIR: &str = "/dumps";
+const DUMP_FILE: &str = "wordpress.sql";
+
+/// Volumes that are rebuilt on every start and not worth archiving, and
+/// `vault-keys`: the unseal key and root token would open every secret of
+/// the archived Vault data to whoever reads the archive.
+const SKIPPED_VOLUMES: &[&str] = &["vault-secrets", "vault-auth", "backup-dumps", "vault-keys"];
+
+/// Bucket the `minio` target stores archives in.
+const MINIO_BUCKET: &str = "woors-backups";
+
+/// Where the `backup` service stores archives. Serialized as the string
+/// it is parsed from.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Target {
+    /// A directory on the host, relative to the compose file.
+    Local(String),
+    /// An S3 bucket, optionally with a path prefix: `s3://bucket/path`.
+    S3 { bucket: String, path: Option<String> },
+    /// A MinIO server generated in the stack.
+    Minio,
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Target::Local(DIRECTORY.to_string())
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    /// Parses `minio`, `s3://bucket[/path]` or a local directory.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value == "minio" {
+            return Ok(Target::Minio);
+        }
+        let Some(location) = value.strip_prefix("s3://") else {
+            return Ok(Target::Local(value.to_string()));
+        };
+        let (bucket, path) = match location.split_once('/') {
+            Some((bucket, path)) if !path.trim_matches('/').is_empty() => {
+                (bucket, Some(path.trim_matches('/').to_string()))
+            }
+            Some((bucket, _)) => (bucket, None),
+            None => (location, None),
+        };
+        if bucket.is_empty() {
+            return Err(format!("no bucket in `{}`", value));
+        }
+        Ok(Target::S3 {
+            bucket: bucket.to_string(),
+            path,
+        })
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Local(directory) => write!(f, "{}", directory),
+            Target::S3 { bucket, path: None } => write!(f, "s3://{}", bucket),
+            Target::S3 { bucket, path: Some(path) } => write!(f, "s3://{}/{}", bucket, path),
+            Target::Minio => write!(f, "minio"),
+        }
+    }
+}
+
+impl TryFrom<String> for Target {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Target> for String {
+    fn from(target: Target) -> String {
+        target.to_string()
+    }
+}
+
+/// Settings of the `backup` service.
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct BackupOptions {
+    /// Cron expression of the backups.
+    pub schedule: String,
+    /// Archives older than this are pruned.
+    pub retention_days: u32,
+    /// A local directory, `s3://bucket[/path]` or `minio`.
+    #[schema(value_type = String, example = "./backups")]
+    pub target: Target,
+    /// `host[:port]` of an S3-compatible server; AWS when `None`.
+    pub s3_endpoint: Option<String>,
+    pub s3_access_key: Option<String>,
+    pub s3_secret_key: Option<String>,
+}
+
+impl Default for BackupOptions {
+    fn default() -> Self {
+        BackupOptions {
+            schedule: SCHEDULE.to_string(),
+            retention_days: 7,
+            target: Target::default(),
+            s3_endpoint: None,
+            s3_access_key: None,
+            s3_secret_key: None,
+        }
+    }
+}
+
+/// The `backup` service. Volumes and the database dump are attached by
+/// [`wire`] once the selection is known.
+pub fn service(engine: Engine, options: &BackupOptions) -> Service {
+    Service {
+        image: catalog_image("backup"),
+        container_name: "backup".to_string(),
+        ports: vec![],
+        volumes: vec![
+            // Runs the dump in the database container.
+            Mount {
+                kind: MountKind::Bind,
+                source: "/var/run/docker.sock".to_string(),
+                target: "/var/run/docker.sock".to_string(),
+                read_only: true
+            }
+        ],
+        networks: vec![
+            NetworkAttachment {
+                network: "web".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        environments: vec![
+            Environment::new("BACKUP_CRON_EXPRESSION", &options.schedule),
+            Environment::new("BACKUP_FILENAME", FILENAME),
+            Environment::new("BACKUP_PRUNING_PREFIX", PREFIX),
+            Environment::new("BACKUP_RETENTION_DAYS", &options.retention_days.to_string()),
+        ],
+        restart: Policy {
+            condition: "always".to_string()
+        },
+        command: Command {
+            value: "".to_string()
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "Backup Server".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.5),
+            memory: Some(256 * MIB),
+            reservation_memory: Some(32 * MIB),
+            ..Default::default()
+        },
+        depends_on: vec![
+            Dependency {
+                service: engine.service().to_string(),
+                condition: None
+            }
+        ],
+        ..Default::default()
+    }
+}
+
+/// Attaches the volumes of the selected services to `backup`, has the
+/// database dump the site before every archive, and adds the archive
+/// target.
+pub fn wire(services: &mut Vec<Service>, engine: Engine, options: &BackupOptions, secrets: &mut Secrets) {
+    let mut archived: Vec<String> = vec![];
+    for service in services.iter() {
+        for mount in &service.volumes {
+            let skipped = service.container_name == engine.service() || SKIPPED_VOLUMES.contains(&mount.source.as_str());
+            if matches!(mount.kind, MountKind::Volume) && !skipped && !archived.contains(&mount.source) {
+                archived.push(mount.source.clone());
+            }
+        }
+    }
+
+    if let Some(database) = services.iter_mut().find(|service| service.container_name == engine.service()) {
+        database.volumes.push(Mount {
+            kind: MountKind::Volume,
+            source: "backup-dumps".to_string(),
+            target: DUMPS_DIR.to_string(),
+            read_only: false,
+        });
+        database.labels.push(Label {
+            key: "docker-volume-backup.archive-pre".to_string(),
+            value: format!("/bin/sh -c '{}'", dump_command(engine)),
+        });
+        archived.insert(0, "backup-dumps".to_string());
+    }
+
+    let mut extra = vec![];
+    let Some(backup) = services.iter_mut().find(|service| service.container_name == "backup") else {
+        return;
+    };
+    // Restores write through the same mounts, so they are not read-only.
+    for volume in archived {
+        backup.volumes.push(Mount {
+            kind: MountKind::Volume,
+            target: format!("/backup/{}", volume),
+            source: volume,
+            read_only: false,
+        });
+    }
+    match &options.target {
+        Target::Local(directory) => backup.volumes.push(Mount {
+            kind: MountKind::Bind,
+            source: directory.clone(),
+            target: "/archive".to_string(),
+            read_only: false,
+        }),
+        Target::S3 { bucket, path } => {
+            backup.environments.push(Environment::new("AWS_S3_BUCKET_NAME", bucket));
+            if let Some(path) = path {
+                backup.environments.push(Environment::new("AWS_S3_PATH", path));
+            }
+            if let Some(endpoint) = &options.s3_endpoint {
+                backup.environments.push(Environment::new("AWS_ENDPOINT", endpoint));
+            }
+            if let Some(access_key) = &options.s3_access_key {
+                backup.environments.push(Environment::new("AWS_ACCESS_KEY_ID", access_key));
+            }
+            if let Some(secret_key) = &options.s3_secret_key {
+                // Recorded with the generated secrets so renders mask it too.
+                secrets.insert("backup.s3_secret_key", secret_key);
+                backup.environments.push(Environment::new("AWS_SECRET_ACCESS_KEY", secret_key));
+            }
+        }
+        Target::Minio => {
+            let password = secrets.password("minio.root_password");
+            backup.environments.extend([
+                Environment::new("AWS_S3_BUCKET_NAME", MINIO_BUCKET),
+                Environment::new("AWS_ENDPOINT", "minio:9000"),
+                Environment::new("AWS_ENDPOINT_PROTO", "http"),
+                Environment::new("AWS_ACCESS_KEY_ID", "woors"),
+                Environment::new("AWS_SECRET_ACCESS_KEY", &password),
+            ]);
+            backup.depends_on.push(Dependency {
+                service: "minio-init".to_string(),
+                condition: Some("service_completed_successfully".to_string()),
+            });
+            extra.push(minio(&password));
+            extra.push(minio_init(&password));
+        }
+    }
+    services.extend(extra);
+}
+
+/// Dumps the site database, with `DROP TABLE` statements, into
+/// [`DUMPS_DIR`].
+fn dump_command(engine: Engine) -> String {
+    format!(
+        "{} {} -uroot --single-transaction --routines --triggers --events {} > {}/{}",
+        engine.as_root(),
+        engine.dump(),
+        engine.database_variable(),
+        DUMPS_DIR,
+        DUMP_FILE
+    )
+}
+
+/// Loads the dump of [`dump_command`] into the site database.
+fn load_command(engine: Engine) -> String {
+    format!(
+        "{} {} -uroot {} < {}/{}",
+        engine.as_root(),
+        engine.client(),
+        engine.database_variable(),
+        DUMPS_DIR,
+        DUMP_FILE
+    )
+}
+
+/// A local S3-compatible server for the `minio` target. The console is
+/// published for browsing the archives.
+fn minio(password: &str) -> Service {
+    Service {
+        image: catalog_image("minio"),
+        container_name: "minio".to_string(),
+        ports: vec![
+            Port {
+                host: "9001".to_string(),
+                container: "9001".to_string()
+            }
+        ],
+        volumes: vec![
+            Mount {
+                kind: MountKind::Volume,
+                source: "minio".to_string(),
+                target: "/data".to_string(),
+                read_only: false
+            }
+        ],
+        networks: vec![
+            NetworkAttachment {
+                network: "web".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        environments: vec![
+            Environment::new("MINIO_ROOT_USER", "woors"),
+            Environment::new("MINIO_ROOT_PASSWORD", password),
+        ],
+        restart: Policy {
+            condition: "always".to_string()
+        },
+        command: Command {
+            value: "server /data --console-address :9001".to_string()
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "MinIO Server".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.5),
+            memory: Some(512 * MIB),
+            reservation_memory: Some(128 * MIB),
+            ..Default::default()
+        },
+        healthcheck: Some(Healthcheck {
+            test: vec!["CMD".to_string(), "mc".to_string(), "ready".to_string(), "local".to_string()],
+            interval: "10s".to_string(),
+            timeout: "5s".to_string(),
+            retries: 5,
+            start_period: "10s".to_string()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Creates the bucket of the `minio` target.
+fn minio_init(password: &str) -> Service {
+    let script = [
+        "set -e",
+        "mc alias set local http://minio:9000 woors \"$MINIO_ROOT_PASSWORD\"",
+        &format!("mc mb --ignore-existing local/{}", MINIO_BUCKET),
+    ];
+    Service {
+        image: catalog_image("minio-init"),
+        container_name: "minio-init".to_string(),
+        ports: vec![],
+        volumes: vec![],
+        networks: vec![
+            NetworkAttachment {
+                network: "web".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        environments: vec![Environment::new("MINIO_ROOT_PASSWORD", password)],
+        restart: Policy {
+            condition: "no".to_string()
+        },
+        command: Command {
+            value: "".to_string()
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "MinIO Bucket Setup".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.25),
+            memory: Some(64 * MIB),
+            reservation_memory: Some(16 * MIB),
+            ..Default::default()
+        },
+        depends_on: vec![
+            Dependency {
+                service: "minio".to_string(),
+                condition: Some("service_healthy".to_string())
+            }
+        ],
+        entrypoint: vec!["/bin/sh".to_string(), "-c".to_string(), script.join("\n")],
+        ..Default::default()
+    }
+}
+
+/// Runs `docker compose -f <file> <args>`.
+fn compose(file: &Path, args: &[&str]) -> io::Result<()> {
+    let status = process::Command::new("docker")
+        .arg("compose")
+        .arg("-f")
+        .arg(file)
+        .args(args)
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("docker compose {} failed with {}", args.join(" "), status)));
+    }
+    Ok(())
+}
+
+/// The services of a generated compose file.
+fn services(file: &Path) -> io::Result<Vec<String>> {
+    let content = fs::read_to_string(file)?;
+    let document: serde_yaml::Value = serde_yaml::from_str(&content)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", file.display(), err)))?;
+    let Some(services) = document.get("services").and_then(|services| services.as_mapping()) else {
+        return Ok(vec![]);
+    };
+    Ok(services.keys().filter_map(|name| name.as_str().map(str::to_string)).collect())
+}
+
+/// The database engine of the stack of `file`, if any. The stack must have
+/// a `backup` service.
+fn inspect(file: &Path) -> io::Result<Option<Engine>> {
+    let services = services(file)?;
+    if !services.iter().any(|service| service == "backup") {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no `backup` service in {}", file.display()),
+        ));
+    }
+    Ok([Engine::Mysql, Engine::Mariadb]
+        .into_iter()
+        .find(|engine| services.iter().any(|service| service == engine.service())))
+}
+
+/// Archives the stack of `file` now, outside of the schedule.
+pub fn backup(file: &Path) -> io::Result<()> {
+    inspect(file)?;
+    compose(file, &["exec", "backup", "backup"])
+}
+
+/// The newest archive of a local backup `directory`.
+pub fn latest(directory: &Path) -> io::Result<PathBuf> {
+    let entries = fs::read_dir(directory)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", directory.display(), err)))?;
+    let mut archives: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(PREFIX) && name.ends_with(".tar.gz"))
+        })
+        .collect();
+    // The timestamp in the name sorts chronologically.
+    archives.sort();
+    archives.pop().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("no backup archive in {}", directory.display()))
+    })
+}
+
+/// Restores `archive` into the stack of `file`: the stack is stopped, the
+/// archived volumes are replaced, then the database is started and loaded
+/// from the dump before the whole stack comes back up.
+pub fn restore(file: &Path, archive: &Path) -> io::Result<()> {
+    let engine = inspect(file)?;
+    let archive = fs::canonicalize(archive)?;
+    let script = [
+        "set -e",
+        "mkdir -p /tmp/restore",
+        "tar -xzf /restore/archive.tar.gz -C /tmp/restore",
+        "for source in /tmp/restore/backup/*/; do",
+        "  target=/backup/$(basename \"$source\")",
+        "  [ -d \"$target\" ] || continue",
+        "  find \"$target\" -mindepth 1 -delete",
+        "  cp -a \"$source\". \"$target\"/",
+        "done",
+    ];
+    let mount = format!("{}:/restore/archive.tar.gz:ro", archive.display());
+
+    compose(file, &["stop"])?;
+    compose(
+        file,
+        &["run", "--rm", "--no-deps", "-v", &mount, "--entrypoint", "/bin/sh", "backup", "-c", &script.join("\n")],
+    )?;
+    if let Some(engine) = engine {
+        compose(file, &["up", "-d", "--wait", engine.service()])?;
+        compose(file, &["exec", "-T", engine.service(), "sh", "-c", &load_command(engine)])?;
+    }
+    compose(file, &["up", "-d"])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_parses_minio_s3_and_directories() {
+        assert_eq!("minio".parse(), Ok(Target::Minio));
+        assert_eq!("./backups".parse(), Ok(Target::Local("./backups".to_string())));
+        assert_eq!(
+            "s3://bucket".parse(),
+            Ok(Target::S3 {
+                bucket: "bucket".to_string(),
+                path: None
+            })
+        );
+        assert_eq!(
+            "s3://bucket/".parse(),
+            Ok(Target::S3 {
+                bucket: "bucket".to_string(),
+                path: None
+            })
+        );
+        assert_eq!(
+            "s3://bucket/site/daily/".parse(),
+            Ok(Target::S3 {
+                bucket: "bucket".to_string(),
+                path: Some("site/daily".to_string())
+            })
+        );
+    }
+
+    #[test]
+    fn target_needs_a_bucket() {
+        assert!("s3://".parse::<Target>().is_err());
+        assert!("s3:///path".parse::<Target>().is_err());
+    }
+
+    #[test]
+    fn target_round_trips() {
+        for target in ["minio", "backups", "s3://bucket", "s3://bucket/site"] {
+            assert_eq!(target.parse::<Target>().unwrap().to_string(), target);
+        }
+    }
+}
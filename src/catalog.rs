@@ -0,0 +1,1447 @@
+use std::io;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::compose::{
+    named_configs, named_volumes, Command, Compose, Dependency, Environment, Extension, Healthcheck, Label, Mount,
+    MountKind, Network, NetworkAttachment, Policy, Port, Resources, Service, Ulimit,
+};
+use crate::backup::BackupOptions;
+use crate::logging::LoggingOptions;
+use crate::image::catalog_image;
+use crate::resources::{format_size, GIB, MIB};
+use crate::secrets::Secrets;
+use crate::site::{Profile, Site};
+use crate::woocommerce::{self, Shop};
+use crate::database::{self, Engine};
+use crate::{app, backup, logging, monitoring, redis, template, traefik, vault, woors, wordpress, wpcli};
+
+/// Services only generated when selected: `vault` needs an unseal step in
+/// prod, `backup` a chosen target, `woors` a checkout to build from and
+/// `traefik` takes the ports of `nginx`.
+pub const OPT_IN: [&str; 4] = ["vault", "backup", "woors", "traefik"];
+
+/// What to generate.
+#[derive(Clone, Default, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct StackOptions {
+    /// Catalog services to include, together with their dependencies.
+    /// Empty means the whole catalog but the services of [`OPT_IN`].
+    pub services: Vec<String>,
+    pub site: Site,
+    pub database: Engine,
+    pub presets: Vec<Preset>,
+    /// Used by the `woocommerce` preset.
+    pub shop: Shop,
+    /// Store the generated secrets in Vault KV rather than in the service
+    /// environments, where the images support it.
+    pub vault_secrets: bool,
+    /// Used by the `backup` service.
+    pub backup: BackupOptions,
+    /// URL of the site a restored database comes from; the `wpcli` job
+    /// replaces it by the URL of this site.
+    pub replace_url: Option<String>,
+    /// Overrides the logging defaults of the site profile.
+    pub logging: LoggingOptions,
+}
+
+/// Ready-made additions on top of the catalog services.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// WordPress with WooCommerce installed by a one-shot WP-CLI job.
+    Woocommerce,
+    /// A one-shot job building the native client scaffolded by `woors app`.
+    App,
+    /// Prometheus and Grafana, with exporters for the selected services.
+    Monitoring,
+    /// Loki and Promtail collecting the logs of the stack containers.
+    Loki,
+}
+
+impl Preset {
+    /// Catalog services the preset builds on.
+    pub fn requires(&self) -> &'static [&'static str] {
+        match self {
+            Preset::Woocommerce => &["wordpress"],
+            Preset::App => &[],
+            Preset::Monitoring => &["cadvisor"],
+            Preset::Loki => &[],
+        }
+    }
+}
+
+/// Builds the stack described by `options`. Credentials are taken from
+/// `secrets`, and generated there when missing.
+pub fn stack(options: &StackOptions, secrets: &mut Secrets) -> io::Result<Compose> {
+    options.site.validate()?;
+    // The whole catalog is built to resolve the selection; only the secrets
+    // the selected services actually use are kept.
+    let mut generated = secrets.clone();
+    let catalog = services(options, &mut generated);
+    let mut selected = options.services.clone();
+    if selected.is_empty() {
+        selected = catalog
+            .iter()
+            .map(|service| service.container_name.clone())
+            .filter(|name| !OPT_IN.contains(&name.as_str()))
+            .collect();
+    }
+    for preset in &options.presets {
+        selected.extend(preset.requires().iter().map(|name| name.to_string()));
+    }
+    if options.vault_secrets {
+        selected.push("vault".to_string());
+    }
+    for template in template::installed() {
+        if selected.contains(&template.name) && !template.supports(options.site.profile) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("`{}` is not part of {} stacks", template.name, options.site.profile.as_str()),
+            ));
+        }
+    }
+    let mut services = select(catalog, &selected)?;
+    let proxies = ["nginx", "traefik"];
+    if proxies.iter().all(|proxy| services.iter().any(|service| &service.container_name == proxy)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "`nginx` and `traefik` both publish ports 80 and 443; select one of them",
+        ));
+    }
+    wire(&mut services, options, &mut generated);
+    logging::apply(&mut services, &options.logging.resolve(options.site.profile)?);
+    for (name, value) in generated.iter() {
+        if secrets.get(name).is_none() && uses(&services, value) {
+            secrets.insert(name, value);
+        }
+    }
+    let volumes = named_volumes(&services);
+    let configs = named_configs(&services);
+    let networks = networks()
+        .into_iter()
+        .filter(|network| {
+            services
+                .iter()
+                .any(|service| service.networks.iter().any(|attachment| attachment.network == network.title))
+        })
+        .collect();
+
+    Ok(Compose {
+        version: Some("3.8".to_string()),
+        services,
+        volumes,
+        networks,
+        configs,
+        environments: environments(),
+        extensions: extensions(),
+    })
+}
+
+/// Keeps the `selected` services and everything they depend on, in catalog
+/// order.
+pub fn select(services: Vec<Service>, selected: &[String]) -> io::Result<Vec<Service>> {
+    if selected.is_empty() {
+        return Ok(services);
+    }
+    for name in selected {
+        if !services.iter().any(|service| &service.container_name == name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no service named `{}` in the catalog", name),
+            ));
+        }
+    }
+
+    let mut wanted: Vec<String> = selected.to_vec();
+    let mut index = 0;
+    while index < wanted.len() {
+        let service = services.iter().find(|service| service.container_name == wanted[index]);
+        for dependency in service.map(|service| &service.depends_on[..]).unwrap_or(&[]) {
+            if !wanted.contains(&dependency.service) {
+                wanted.push(dependency.service.clone());
+            }
+        }
+        index += 1;
+    }
+    Ok(services
+        .into_iter()
+        .filter(|service| wanted.contains(&service.container_name))
+        .collect())
+}
+
+/// Connects services that integrate with each other when both are
+/// selected, and adds the services of the presets.
+fn wire(services: &mut Vec<Service>, options: &StackOptions, secrets: &mut Secrets) {
+    wire_wordpress(services, options, secrets);
+    if options.presets.contains(&Preset::App) {
+        services.push(app::service(&options.site));
+    }
+    if options.presets.contains(&Preset::Monitoring) {
+        monitoring::wire(services, options.database, secrets);
+    }
+    // Last, so the secrets of the jobs added above are moved to Vault too.
+    if services.iter().any(|service| service.container_name == "vault") {
+        vault::wire(services, &options.site, options.vault_secrets, secrets);
+    }
+    // After Vault, whose key volume is worth archiving.
+    if services.iter().any(|service| service.container_name == "backup") {
+        backup::wire(services, options.database, &options.backup, secrets);
+    }
+    // Last, so Promtail knows every container of the stack.
+    if options.presets.contains(&Preset::Loki) {
+        logging::wire(services);
+    }
+}
+
+fn wire_wordpress(services: &mut Vec<Service>, options: &StackOptions, secrets: &mut Secrets) {
+    let redis = services.iter().any(|service| service.container_name == "redis");
+    let Some(wordpress) = services.iter_mut().find(|service| service.container_name == "wordpress") else {
+        return;
+    };
+
+    let mut config = vec![];
+    let mut wpcli_environments = vec![];
+    let mut wpcli_steps = vec![];
+    if options.presets.contains(&Preset::Woocommerce) {
+        wpcli_environments.extend(woocommerce::environment(&options.site, &options.shop, secrets));
+        wpcli_steps.extend(woocommerce::steps(&options.site, &options.shop));
+    }
+    if let Some(url) = &options.replace_url {
+        if wpcli_steps.is_empty() {
+            wpcli_steps.push(wpcli::WAIT_FOR_INSTALL.to_string());
+        }
+        wpcli_steps.push(wpcli::search_replace(url, &options.site.home_url()));
+    }
+    if redis {
+        wordpress.environments.extend(redis::wordpress_environment(secrets));
+        config.extend(redis::wordpress_config());
+        if wpcli_steps.is_empty() {
+            wpcli_steps.push(wpcli::WAIT_FOR_INSTALL.to_string());
+        }
+        wpcli_steps.extend(redis::wpcli_steps());
+    }
+    wordpress.environments.push(Environment {
+        title: "WORDPRESS_CONFIG_EXTRA".to_string(),
+        value: wordpress::config_extra(&options.site, config)
+    });
+
+    if !wpcli_steps.is_empty() {
+        let wpcli = wpcli::service(wordpress, options.database, wpcli_environments, wpcli_steps);
+        services.push(wpcli);
+    }
+}
+
+/// Whether any service environment contains `value`.
+fn uses(services: &[Service], value: &str) -> bool {
+    services
+        .iter()
+        .flat_map(|service| &service.environments)
+        .any(|environment| environment.value.contains(value))
+}
+
+/// A variable of a catalog service.
+#[derive(Serialize, ToSchema)]
+pub struct Variable {
+    pub name: String,
+    /// The default value; `None` for secrets, which are generated for
+    /// every stack.
+    pub value: Option<String>,
+    pub secret: bool,
+}
+
+/// The limits and reservations of a catalog service, memory sizes in the
+/// compose notation, e.g. `512m`.
+#[derive(Serialize, ToSchema)]
+pub struct Needs {
+    pub cpus: Option<f64>,
+    pub memory: Option<String>,
+    pub reservation_cpus: Option<f64>,
+    pub reservation_memory: Option<String>,
+}
+
+/// A catalog service, as described by `GET /api/v1/catalog` and
+/// `woors catalog`. Ports, volumes and variables are those of the first
+/// profile the service is part of, the requested one first.
+#[derive(Serialize, ToSchema)]
+pub struct Entry {
+    pub name: String,
+    pub description: String,
+    /// Whether the service comes from a template rather than woors itself.
+    pub template: bool,
+    pub image: String,
+    /// Published ports, `host:container`.
+    pub ports: Vec<String>,
+    /// `source:target`, followed by `:ro` for read-only mounts; in-memory
+    /// mounts are only their target.
+    pub volumes: Vec<String>,
+    pub environment: Vec<Variable>,
+    /// Services started first, and added along with this one.
+    pub depends_on: Vec<String>,
+    /// Profiles of the stacks the service is part of.
+    pub profiles: Vec<Profile>,
+    pub resources: Needs,
+}
+
+impl Entry {
+    fn new(service: Service, profile: Profile, secrets: &Secrets) -> Entry {
+        let description = service
+            .labels
+            .iter()
+            .find(|label| label.key == "com.example.description")
+            .map_or(String::new(), |label| label.value.clone());
+        let volumes = service
+            .volumes
+            .iter()
+            .map(|mount| {
+                let mut volume = match mount.kind {
+                    MountKind::Tmpfs => mount.target.clone(),
+                    _ => format!("{}:{}", mount.source, mount.target),
+                };
+                if mount.read_only {
+                    volume.push_str(":ro");
+                }
+                volume
+            })
+            .collect();
+        let environment = service
+            .environments
+            .iter()
+            .map(|environment| {
+                let secret = secrets.mask(&environment.value) != environment.value;
+                Variable {
+                    name: environment.title.clone(),
+                    value: (!secret).then(|| environment.value.clone()),
+                    secret,
+                }
+            })
+            .collect();
+        let resources = &service.resources;
+        Entry {
+            template: template::installed().iter().any(|template| template.name == service.container_name),
+            image: service.image.to_string(),
+            ports: service.ports.iter().map(|port| format!("{}:{}", port.host, port.container)).collect(),
+            volumes,
+            environment,
+            depends_on: service.depends_on.iter().map(|dependency| dependency.service.clone()).collect(),
+            profiles: vec![profile],
+            resources: Needs {
+                cpus: resources.cpus,
+                memory: resources.memory.map(format_size),
+                reservation_cpus: resources.reservation_cpus,
+                reservation_memory: resources.reservation_memory.map(format_size),
+            },
+            name: service.container_name,
+            description,
+        }
+    }
+}
+
+/// The services of the catalog under every profile, built-in ones first.
+/// The profile of `options` is described first.
+pub fn entries(options: &StackOptions) -> Vec<Entry> {
+    let mut profiles = vec![options.site.profile];
+    profiles.extend(Profile::value_variants().iter().filter(|profile| **profile != options.site.profile));
+    let mut entries: Vec<Entry> = vec![];
+    for profile in profiles {
+        let mut options = options.clone();
+        options.site.profile = profile;
+        let mut secrets = Secrets::default();
+        for service in services(&options, &mut secrets) {
+            match entries.iter_mut().find(|entry| entry.name == service.container_name) {
+                Some(entry) => entry.profiles.push(profile),
+                None => entries.push(Entry::new(service, profile, &secrets)),
+            }
+        }
+    }
+    for entry in &mut entries {
+        entry.profiles.sort_by_key(|profile| profile.as_str());
+    }
+    entries
+}
+
+/// The catalog service named `name`.
+pub fn entry(options: &StackOptions, name: &str) -> io::Result<Entry> {
+    entries(options).into_iter().find(|entry| entry.name == name).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("no service named `{}` in the catalog", name))
+    })
+}
+
+/// Every service woors knows how to generate: the built-in ones, then
+/// those of the installed templates supporting the site profile.
+pub fn services(options: &StackOptions, secrets: &mut Secrets) -> Vec<Service> {
+    let site_database = wordpress::database(&options.site, secrets);
+    let mut services = vec![
+        database::service(options.database, &options.site, secrets),
+        Service {
+            image: catalog_image("redis"),
+            container_name: "redis".to_string(),
+            ports: vec![],
+            volumes: vec![
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "redis".to_string(),
+                    target: "/data".to_string(),
+                    read_only: false
+                }
+            ],
+            networks: vec![
+                NetworkAttachment {
+                    network: "backend".to_string(),
+                    aliases: vec!["cache".to_string()],
+                    ipv4_address: None
+                }
+            ],
+            environments: vec![],
+            restart: Policy {
+                condition: "always".to_string()
+            },
+            command: Command {
+                value: redis::command()
+            },
+            labels: vec![
+                Label {
+                    key: "com.example.description".to_string(),
+                    value: "Redis Server".to_string()
+                }
+            ],
+            resources: Resources {
+                cpus: Some(0.5),
+                memory: Some(redis::MEMORY_LIMIT),
+                reservation_cpus: None,
+                reservation_memory: Some(64 * MIB),
+                shm_size: None,
+                ulimits: vec![]
+            },
+            depends_on: vec![],
+            healthcheck: Some(Healthcheck {
+                test: vec![
+                    "CMD-SHELL".to_string(),
+                    redis::healthcheck()
+                ],
+                interval: "10s".to_string(),
+                timeout: "5s".to_string(),
+                retries: 5,
+                start_period: "10s".to_string()
+            }),
+            configs: vec![redis::config(secrets)],
+            ..Default::default()
+        },
+        Service {
+            image: catalog_image("wordpress"),
+            container_name: "wordpress".to_string(),
+            ports: vec![
+                Port {
+                    host: "8080".to_string(),
+                    container: "80".to_string()
+                }
+            ],
+            volumes: vec![
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "wordpress".to_string(),
+                    target: "/var/www/html".to_string(),
+                    read_only: false
+                }
+            ],
+            networks: vec![
+                NetworkAttachment {
+                    network: "web".to_string(),
+                    aliases: vec![],
+                    ipv4_address: None
+                },
+                NetworkAttachment {
+                    network: "backend".to_string(),
+                    aliases: vec![],
+                    ipv4_address: None
+                }
+            ],
+            environments: wordpress::environment(&options.site, options.database, secrets),
+            restart: Policy {
+                condition: "always".to_string()
+            },
+            command: Command {
+                value: "apache2-foreground".to_string()
+            },
+            labels: vec![
+                Label {
+                    key: "com.example.description".to_string(),
+                    value: "WordPress Server".to_string()
+                }
+            ],
+            resources: Resources {
+                cpus: Some(1.0),
+                memory: Some(512 * MIB),
+                reservation_cpus: Some(0.25),
+                reservation_memory: Some(256 * MIB),
+                shm_size: None,
+                ulimits: vec![]
+            },
+            depends_on: vec![
+                Dependency {
+                    service: options.database.service().to_string(),
+                    condition: Some("service_healthy".to_string())
+                }
+            ],
+            healthcheck: Some(Healthcheck {
+                test: vec![
+                    "CMD-SHELL".to_string(),
+                    "curl -fs -o /dev/null http://localhost/wp-login.php || exit 1".to_string()
+                ],
+                interval: "10s".to_string(),
+                timeout: "5s".to_string(),
+                retries: 5,
+                start_period: "30s".to_string()
+            }),
+            ..Default::default()
+        },
+        Service {
+            image: catalog_image("phpmyadmin"),
+            container_name: "phpmyadmin".to_string(),
+            ports: vec![
+                Port {
+                    host: "8081".to_string(),
+                    container: "80".to_string()
+                }
+            ],
+            volumes: vec![],
+            networks: vec![
+                NetworkAttachment {
+                    network: "web".to_string(),
+                    aliases: vec![],
+                    ipv4_address: None
+                },
+                NetworkAttachment {
+                    network: "backend".to_string(),
+                    aliases: vec![],
+                    ipv4_address: None
+                }
+            ],
+            environments: vec![
+                Environment {
+                    title: "PMA_HOST".to_string(),
+                    value: options.database.service().to_string()
+                },
+                // The site user rather than root: phpMyAdmin is exposed on
+                // the `web` network.
+                Environment {
+                    title: "PMA_USER".to_string(),
+                    value: site_database.user.clone()
+                },
+                Environment {
+                    title: "PMA_PASSWORD".to_string(),
+                    value: site_database.password.clone()
+                }
+            ],
+            restart: Policy {
+                condition: "always".to_string()
+            },
+            command: Command {
+                value: "apache2-foreground".to_string()
+            },
+            labels: vec![
+                Label {
+                    key: "com.example.description".to_string(),
+                    value: "PhpMyAdmin Server".to_string()
+                }
+            ],
+            resources: Resources {
+                cpus: Some(0.5),
+                memory: Some(256 * MIB),
+                reservation_cpus: None,
+                reservation_memory: Some(64 * MIB),
+                shm_size: None,
+                ulimits: vec![]
+            },
+            depends_on: vec![
+                Dependency {
+                    service: options.database.service().to_string(),
+                    condition: None
+                }
+            ],
+            ..Default::default()
+        },
+        Service {
+            image: catalog_image("portainer"),
+            container_name: "portainer".to_string(),
+            ports: vec![
+                Port {
+                    host: "9000".to_string(),
+                    container: "9000".to_string()
+                }
+            ],
+            volumes: vec![
+                Mount {
+                    kind: MountKind::Bind,
+                    source: "/var/run/docker.sock".to_string(),
+                    target: "/var/run/docker.sock".to_string(),
+                    read_only: true
+                },
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "portainer".to_string(),
+                    target: "/data".to_string(),
+                    read_only: false
+                }
+            ],
+            networks: vec![
+                NetworkAttachment {
+                    network: "web".to_string(),
+                    aliases: vec![],
+                    ipv4_address: None
+                }
+            ],
+            environments: vec![],
+            restart: Policy {
+                condition: "always".to_string()
+            },
+            command: Command {
+                value: "".to_string()
+            },
+            labels: vec![
+                Label {
+                    key: "com.example.description".to_string(),
+                    value: "Portainer Server".to_string()
+                }
+            ],
+            resources: Resources {
+                cpus: Some(0.5),
+                memory: Some(256 * MIB),
+                reservation_cpus: None,
+                reservation_memory: Some(64 * MIB),
+                shm_size: None,
+                ulimits: vec![]
+            },
+            depends_on: vec![],
+            ..Default::default()
+        },
+        Service {
+            image: catalog_image("cadvisor"),
+            container_name: "cadvisor".to_string(),
+            ports: vec![
+                Port {
+                    host: "8082".to_string(),
+                    container: "8080".to_string()
+                }
+            ],
+            volumes: vec![
+                Mount {
+                    kind: MountKind::Bind,
+                    source: "/".to_string(),
+                    target: "/rootfs".to_string(),
+                    read_only: true
+                },
+                Mount {
+                    kind: MountKind::Bind,
+                    source: "/var/run".to_string(),
+                    target: "/var/run".to_string(),
+                    read_only: true
+                },
+                Mount {
+                    kind: MountKind::Bind,
+                    source: "/sys".to_string(),
+                    target: "/sys".to_string(),
+                    read_only: true
+                },
+                Mount {
+                    kind: MountKind::Bind,
+                    source: "/var/lib/docker".to_string(),
+                    target: "/var/lib/docker".to_string(),
+                    read_only: true
+                }
+            ],
+            networks: vec![
+                NetworkAttachment {
+                    network: "web".to_string(),
+                    aliases: vec![],
+                    ipv4_address: None
+                }
+            ],
+            environments: vec![],
+            restart: Policy {
+                condition: "always".to_string()
+            },
+            command: Command {
+                value: "".to_string()
+            },
+            labels: vec![
+                Label {
+                    key: "com.example.description".to_string(),
+                    value: "Cadvisor Server".to_string()
+                }
+            ],
+            resources: Resources {
+                cpus: Some(0.5),
+                memory: Some(256 * MIB),
+                reservation_cpus: None,
+                reservation_memory: Some(64 * MIB),
+                shm_size: None,
+                ulimits: vec![]
+            },
+            depends_on: vec![],
+            ..Default::default()
+        },
+        Service {
+            image: catalog_image("certbot"),
+            container_name: "certbot".to_string(),
+            ports: vec![],
+            volumes: vec![
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "certbot-etc".to_string(),
+                    target: "/etc/letsencrypt".to_string(),
+                    read_only: false
+                },
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "certbot-lib".to_string(),
+                    target: "/var/lib/letsencrypt".to_string(),
+                    read_only: false
+                },
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "certbot-log".to_string(),
+                    target: "/var/log/letsencrypt".to_string(),
+                    read_only: false
+                }
+            ],
+            networks: vec![
+                NetworkAttachment {
+                    network: "web".to_string(),
+                    aliases: vec![],
+                    ipv4_address: None
+                }
+            ],
+            environments: vec![],
+            restart: Policy {
+                condition: "always".to_string()
+            },
+            command: Command {
+                value: "".to_string()
+            },
+            labels: vec![
+                Label {
+                    key: "com.example.description".to_string(),
+                    value: "Certbot Server".to_string()
+                }
+            ],
+            resources: Resources {
+                cpus: Some(0.25),
+                memory: Some(128 * MIB),
+                reservation_cpus: None,
+                reservation_memory: Some(32 * MIB),
+                shm_size: None,
+                ulimits: vec![]
+            },
+            depends_on: vec![],
+            ..Default::default()
+        },
+        Service {
+            image: catalog_image("nginx"),
+            container_name: "nginx".to_string(),
+            ports: vec![
+                Port {
+                    host: "80".to_string(),
+                    container: "80".to_string()
+                },
+                Port {
+                    host: "443".to_string(),
+                    container: "443".to_string()
+                }
+            ],
+            volumes: vec![
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "nginx".to_string(),
+                    target: "/etc/nginx".to_string(),
+                    read_only: false
+                }
+            ],
+            networks: vec![
+                NetworkAttachment {
+                    network: "web".to_string(),
+                    aliases: vec![],
+                    ipv4_address: None
+                }
+            ],
+            environments: vec![],
+            restart: Policy {
+                condition: "always".to_string()
+            },
+            command: Command {
+                value: "".to_string()
+            },
+            labels: vec![
+                Label {
+                    key: "com.example.description".to_string(),
+                    value: "Nginx Server".to_string()
+                }
+            ],
+            resources: Resources {
+                cpus: Some(0.5),
+                memory: Some(256 * MIB),
+                reservation_cpus: None,
+                reservation_memory: Some(64 * MIB),
+                shm_size: None,
+                ulimits: vec![]
+            },
+            depends_on: vec![
+                Dependency {
+                    service: "wordpress".to_string(),
+                    condition: None
+                },
+                Dependency {
+                    service: "phpmyadmin".to_string(),
+                    condition: None
+                },
+                Dependency {
+                    service: "portainer".to_string(),
+                    condition: None
+                },
+                Dependency {
+                    service: "cadvisor".to_string(),
+                    condition: None
+                },
+                Dependency {
+                    service: "certbot".to_string(),
+                    condition: None
+                }
+            ],
+            ..Default::default()
+        },
+        Service {
+            image: catalog_image("gitlab"),
+            container_name: "gitlab".to_string(),
+            ports: vec![
+                Port {
+                    host: "8083".to_string(),
+                    container: "80".to_string()
+                },
+                Port {
+                    host: "2222".to_string(),
+                    container: "22".to_string()
+                }
+            ],
+            volumes: vec![
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "gitlab-config".to_string(),
+                    target: "/etc/gitlab".to_string(),
+                    read_only: false
+                },
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "gitlab-logs".to_string(),
+                    target: "/var/log/gitlab".to_string(),
+                    read_only: false
+                },
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "gitlab-data".to_string(),
+                    target: "/var/opt/gitlab".to_string(),
+                    read_only: false
+                }
+            ],
+            networks: vec![
+                NetworkAttachment {
+                    network: "web".to_string(),
+                    aliases: vec![],
+                    ipv4_address: None
+                }
+            ],
+            environments: vec![
+                Environment {
+                    title: "GITLAB_OMNIBUS_CONFIG".to_string(),
+                    value: "external_url 'https://gitlab.example.com'; gitlab_rails['gitlab_shell_ssh_port'] = 2222;".to_string()
+                }
+            ],
+            restart: Policy {
+                condition: "always".to_string()
+            },
+            command: Command {
+                value: "".to_string()
+            },
+            labels: vec![
+                Label {
+                    key: "com.example.description".to_string(),
+                    value: "Gitlab Server".to_string()
+                }
+            ],
+            resources: Resources {
+                cpus: Some(2.0),
+                memory: Some(4 * GIB),
+                reservation_cpus: Some(1.0),
+                reservation_memory: Some(3 * GIB),
+                shm_size: Some(256 * MIB),
+                ulimits: vec![]
+            },
+            depends_on: vec![],
+            ..Default::default()
+        }, 
+        Service {
+            image: catalog_image("sonarqube"),
+            container_name: "sonarqube".to_string(),
+            ports: vec![
+                Port {
+                    host: "8084".to_string(),
+                    container: "9000".to_string()
+                }
+            ],
+            volumes: vec![
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "sonarqube-conf".to_string(),
+                    target: "/opt/sonarqube/conf".to_string(),
+                    read_only: false
+                },
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "sonarqube-data".to_string(),
+                    target: "/opt/sonarqube/data".to_string(),
+                    read_only: false
+                },
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "sonarqube-logs".to_string(),
+                    target: "/opt/sonarqube/logs".to_string(),
+                    read_only: false
+                },
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "sonarqube-extensions".to_string(),
+                    target: "/opt/sonarqube/extensions".to_string(),
+                    read_only: false
+                }
+            ],
+            networks: vec![
+                NetworkAttachment {
+                    network: "web".to_string(),
+                    aliases: vec![],
+                    ipv4_address: None
+                }
+            ],
+            environments: vec![],
+            restart: Policy {
+                condition: "always".to_string()
+            },
+            command: Command {
+                value: "".to_string()
+            },
+            labels: vec![
+                Label {
+                    key: "com.example.description".to_string(),
+                    value: "Sonarqube Server".to_string()
+                }
+            ],
+            resources: Resources {
+                cpus: Some(2.0),
+                memory: Some(2 * GIB),
+                reservation_cpus: Some(0.5),
+                reservation_memory: Some(GIB),
+                shm_size: None,
+                ulimits: vec![
+                    Ulimit {
+                        name: "nofile".to_string(),
+                        soft: 65536,
+                        hard: 65536
+                    },
+                    Ulimit {
+                        name: "nproc".to_string(),
+                        soft: 4096,
+                        hard: 4096
+                    }
+                ]
+            },
+            depends_on: vec![],
+            ..Default::default()
+        }, 
+        Service {
+            image: catalog_image("code-server"),
+            container_name: "code-server".to_string(),
+            ports: vec![
+                Port {
+                    host: "8085".to_string(),
+                    container: "8080".to_string()
+                }
+            ],
+            volumes: vec![
+                Mount {
+                    kind: MountKind::Volume,
+                    source: "code-server".to_string(),
+                    target: "/home/coder/project".to_string(),
+                    read_only: false
+                }
+            ],
+            networks: vec![
+                NetworkAttachment {
+                    network: "web".to_string(),
+                    aliases: vec![],
+                    ipv4_address: None
+                }
+            ],
+            environments: vec![
+                Environment {
+                    title: "PASSWORD".to_string(),
+                    value: secrets.password("code_server.password")
+                }
+            ],
+            restart: Policy {
+                condition: "always".to_string()
+            },
+            command: Command {
+                value: "".to_string()
+            },
+            labels: vec![
+                Label {
+                    key: "com.example.description".to_string(),
+                    value: "Code Server".to_string()
+                }
+            ],
+            resources: Resources {
+                cpus: Some(1.0),
+                memory: Some(GIB),
+                reservation_cpus: None,
+                reservation_memory: Some(256 * MIB),
+                shm_size: None,
+                ulimits: vec![]
+            },
+            depends_on: vec![],
+            ..Default::default()
+        },
+        Service {
+            image: catalog_image("mailhog"),
+            container_name: "mailhog".to_string(),
+            ports: vec![
+                Port {
+                    host: "8086".to_string(),
+                    container: "8025".to_string()
+                }
+            ],
+            volumes: vec![],
+            networks: vec![
+                NetworkAttachment {
+                    network: "web".to_string(),
+                    aliases: vec![],
+                    ipv4_address: None
+                }
+            ],
+            environments: vec![],
+            restart: Policy {
+                condition: "always".to_string()
+            },
+            command: Command {
+                value: "".to_string()
+            },
+            labels: vec![
+                Label {
+                    key: "com.example.description".to_string(),
+                    value: "Mailhog Server".to_string()
+                }
+            ],
+            resources: Resources {
+                cpus: Some(0.25),
+                memory: Some(128 * MIB),
+                reservation_cpus: None,
+                reservation_memory: Some(32 * MIB),
+                shm_size: None,
+                ulimits: vec![]
+            },
+            depends_on: vec![],
+            ..Default::default()
+        }, 
+        Service {
+            image: catalog_image("graphviz"),
+            container_name: "graphviz".to_string(),
+            ports: vec![
+                Port {
+                    host: "8087".to_string(),
+                    container: "8080".to_string()
+                }
+            ],
+            volumes: vec![
+                Mount {
+                    kind: MountKind::Bind,
+                    source: "/var/run/docker.sock".to_string(),
+                    target: "/var/run/docker.sock".to_string(),
+                    read_only: true
+                }
+            ],
+            networks: vec![
+                NetworkAttachment {
+                    network: "web".to_string(),
+                    aliases: vec![],
+                    ipv4_address: None
+                }
+            ],
+            environments: vec![],
+            restart: Policy {
+                condition: "always".to_string()
+            },
+            command: Command {
+                value: "".to_string()
+            },
+            labels: vec![
+                Label {
+                    key: "com.example.description".to_string(),
+                    value: "Graphviz Server".to_string()
+                }
+            ],
+            resources: Resources {
+                cpus: Some(0.25),
+                memory: Some(128 * MIB),
+                reservation_cpus: None,
+                reservation_memory: Some(32 * MIB),
+                shm_size: None,
+                ulimits: vec![]
+            },
+            depends_on: vec![],
+            ..Default::default()
+        },
+        vault::service(&options.site, secrets),
+        backup::service(options.database, &options.backup),
+        woors::service(&options.site),
+        traefik::service(&options.site)
+    ];
+    for template in template::installed() {
+        if template.supports(options.site.profile) {
+            services.push(template.service(options, secrets));
+        }
+    }
+    services
+}
+
+fn networks() -> Vec<Network> {
+    vec![
+        Network {
+            title: "web".to_string(),
+            driver: "bridge".to_string(),
+            driver_opts: vec![],
+            external: false,
+            internal: false,
+            subnets: vec![]
+        },
+        Network {
+            title: "backend".to_string(),
+            driver: "bridge".to_string(),
+            driver_opts: vec![],
+            external: false,
+            internal: true,
+            subnets: vec![]
+        }
+    ]
+}
+
+fn environments() -> Vec<Environment> {
+    vec![
+        Environment {
+            title: "VIRTUAL_HOST".to_string(),
+            value: "example.com".to_string()
+        },
+        Environment {
+            title: "LETSENCRYPT_HOST".to_string(),
+            value: "example.com".to_string()
+        },
+        Environment {
+            title: "LETSENCRYPT_EMAIL".to_string(),
+            value: "ydev@tuta.io".to_string()
+        }
+    ]
+}
+
+fn extensions() -> Vec<Extension> {
+    vec![
+        Extension {
+            title: "traefik.enable".to_string(),
+            value: "example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.rule".to_string(),
+            value: "HostRegexp(`{any:.+}`)".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.entrypoints".to_string(),
+            value: "web".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.middlewares".to_string(),
+            value: "redirect-to-https".to_string()
+        },
+        Extension {
+            title: "traefik.http.middlewares.redirect-to-https.redirectscheme.scheme".to_string(),
+            value: "https".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.priority".to_string(),
+            value: "1".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls".to_string(),
+            value: "true".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.certresolver".to_string(),
+            value: "letsencrypt".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].main".to_string(),
+            value: "example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[0]".to_string(),
+            value: "www.example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[1]".to_string(),
+            value: "gitlab.example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[2]".to_string(),
+            value: "sonarqube.example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[3]".to_string(),
+            value: "code-server.example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[4]".to_string(),
+            value: "mailhog.example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[5]".to_string(),
+            value: "graphviz.example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[6]".to_string(),
+            value: "portainer.example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[7]".to_string(),
+            value: "cadvisor.example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[8]".to_string(),
+            value: "phpmyadmin.example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[9]".to_string(),
+            value: "wordpress.example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[10]".to_string(),
+            value: "redis.example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[11]".to_string(),
+            value: "mysql.example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[12]".to_string(),
+            value: "nginx.example.com".to_string()
+        },
+        Extension {
+            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[13]".to_string(),
+            value: "hub.example.com".to_string()
+        },
+        Extension {
+            title: "nginx.http.routers.nginx.rule".to_string(),
+            value: "Host(`nginx.example.com`)".to_string()
+        },
+        Extension {
+            title: "nginx.http.routers.nginx.entrypoints".to_string(),
+            value: "web".to_string()
+        },
+        Extension {
+            title: "nginx.http.routers.nginx.tls".to_string(),
+            value: "true".to_string()
+        },
+        Extension {
+            title: "nginx.http.routers.nginx.tls.certresolver".to_string(),
+            value: "letsencrypt".to_string()
+        },
+        Extension {
+            title: "nginx.http.routers.nginx.tls.domains[0].main".to_string(),
+            value: "nginx.example.com".to_string()
+        },
+        Extension {
+            title: "nginx.http.routers.nginx.tls.domains[0].sans[0]".to_string(),
+            value: "www.nginx.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.rule".to_string(),
+            value: "Host(`hub.example.com`)".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.entrypoints".to_string(),
+            value: "web".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls".to_string(),
+            value: "true".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.certresolver".to_string(),
+            value: "letsencrypt".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].main".to_string(),
+            value: "hub.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].sans[0]".to_string(),
+            value: "www.hub.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].sans[1]".to_string(),
+            value: "gitlab.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].sans[2]".to_string(),
+            value: "sonarqube.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].sans[3]".to_string(),
+            value: "code-server.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].sans[4]".to_string(),
+            value: "mailhog.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].sans[5]".to_string(),
+            value: "graphviz.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].sans[6]".to_string(),
+            value: "portainer.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].sans[7]".to_string(),
+            value: "cadvisor.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].sans[8]".to_string(),
+            value: "phpmyadmin.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].sans[9]".to_string(),
+            value: "wordpress.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].sans[10]".to_string(),
+            value: "redis.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].sans[11]".to_string(),
+            value: "mysql.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].sans[12]".to_string(),
+            value: "nginx.example.com".to_string()
+        },
+        Extension {
+            title: "hub.http.routers.hub.tls.domains[0].sans[13]".to_string(),
+            value: "hub.example.com".to_string()
+        }
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(options: &StackOptions) -> Vec<String> {
+        let compose = stack(options, &mut Secrets::default()).unwrap();
+        compose.services.into_iter().map(|service| service.container_name).collect()
+    }
+
+    #[test]
+    fn default_stack_leaves_out_opt_in_services() {
+        let names = names(&StackOptions::default());
+        assert!(names.contains(&"wordpress".to_string()));
+        assert!(!names.iter().any(|name| name.starts_with("vault")));
+        assert!(!names.contains(&"backup".to_string()));
+        assert!(!names.contains(&"woors".to_string()));
+        assert!(!names.contains(&"traefik".to_string()));
+    }
+
+    #[test]
+    fn monitoring_scrapes_a_selected_traefik() {
+        let options = StackOptions {
+            services: vec!["traefik".to_string(), "woors".to_string()],
+            presets: vec![Preset::Monitoring],
+            ..Default::default()
+        };
+        let compose = stack(&options, &mut Secrets::default()).unwrap();
+        let prometheus = compose.services.iter().find(|service| service.container_name == "prometheus").unwrap();
+        let scrape = &prometheus.configs[0].content;
+        assert!(scrape.contains("job_name: traefik"));
+        assert!(scrape.contains("\"traefik:8080\""));
+        assert!(scrape.contains("\"woors:8888\""));
+    }
+
+    #[test]
+    fn nginx_and_traefik_cannot_share_a_stack() {
+        let options = StackOptions {
+            services: vec!["nginx".to_string(), "traefik".to_string()],
+            ..Default::default()
+        };
+        let error = stack(&options, &mut Secrets::default()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn selected_woors_is_built_from_the_checkout() {
+        let options = StackOptions {
+            services: vec!["woors".to_string()],
+            ..Default::default()
+        };
+        let compose = stack(&options, &mut Secrets::default()).unwrap();
+        assert_eq!(compose.services.len(), 1);
+        assert_eq!(compose.services[0].build.as_ref().map(|build| build.context.as_str()), Some("."));
+    }
+
+    #[test]
+    fn selected_backup_brings_its_dependencies() {
+        let options = StackOptions {
+            services: vec!["backup".to_string()],
+            ..Default::default()
+        };
+        let names = names(&options);
+        assert!(names.contains(&"backup".to_string()));
+        assert!(names.contains(&"mysql".to_string()));
+        assert!(!names.contains(&"redis".to_string()));
+    }
+
+    #[test]
+    fn presets_add_the_services_they_build_on() {
+        let options = StackOptions {
+            services: vec!["redis".to_string()],
+            presets: vec![Preset::Woocommerce],
+            ..Default::default()
+        };
+        assert_eq!(names(&options), vec!["mysql", "redis", "wordpress", "wpcli"]);
+    }
+
+    #[test]
+    fn vault_secrets_adds_vault() {
+        let options = StackOptions {
+            vault_secrets: true,
+            ..Default::default()
+        };
+        let names = names(&options);
+        for name in ["vault", "vault-init", "vault-agent", "wordpress"] {
+            assert!(names.contains(&name.to_string()), "{} missing", name);
+        }
+    }
+
+    #[test]
+    fn selected_vault_comes_without_the_rest() {
+        let options = StackOptions {
+            services: vec!["vault".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(names(&options), vec!["vault".to_string()]);
+    }
+}
@@ -0,0 +1,174 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::backup;
+use crate::catalog::{self, StackOptions};
+use crate::compose::parse_compose;
+use crate::database::Engine;
+use crate::secrets::Secrets;
+use crate::site::{Profile, Site};
+use crate::wordpress;
+
+/// The name of the compose file written in the target directory.
+pub const COMPOSE_FILE: &str = "docker-compose.yml";
+
+/// What `woors clone` needs to know about the stack it copies, read back
+/// from its compose file.
+pub struct Source {
+    pub file: PathBuf,
+    /// Catalog services of the stack; generated jobs are left out.
+    pub services: Vec<String>,
+    pub database: Engine,
+    /// Name of the site database, the slug of the site title.
+    pub database_name: Option<String>,
+    /// `WP_HOME` of the site.
+    pub home_url: String,
+    pub profile: Profile,
+    pub table_prefix: Option<String>,
+    /// Local directory of the archives, relative to the current directory.
+    pub backups: Option<PathBuf>,
+}
+
+fn invalid(file: &Path, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", file.display(), message))
+}
+
+/// The value of `name` in the environment of `service`, as rendered by
+/// `parse_compose`.
+fn environment(service: &serde_yaml::Value, name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    service
+        .get("environment")?
+        .as_sequence()?
+        .iter()
+        .filter_map(|entry| entry.as_str())
+        .find_map(|entry| entry.strip_prefix(&prefix))
+        .map(|value| value.replace("$$", "$"))
+}
+
+/// The value of a `define('NAME', '...')` of `WORDPRESS_CONFIG_EXTRA`.
+fn constant(config: &str, name: &str) -> Option<String> {
+    let start = format!("define('{}', '", name);
+    let mut chars = config[config.find(&start)? + start.len()..].chars();
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            '\\' => value.push(chars.next()?),
+            '\'' => return Some(value),
+            c => value.push(c),
+        }
+    }
+}
+
+impl Source {
+    /// Reads a compose file generated by woors.
+    pub fn read(file: &Path) -> io::Result<Source> {
+        let content = fs::read_to_string(file)?;
+        let document: serde_yaml::Value =
+            serde_yaml::from_str(&content).map_err(|err| invalid(file, &err.to_string()))?;
+        let services = document
+            .get("services")
+            .and_then(|services| services.as_mapping())
+            .ok_or_else(|| invalid(file, "no services"))?;
+        let wordpress = services
+            .get("wordpress")
+            .ok_or_else(|| invalid(file, "no `wordpress` service to clone"))?;
+        let config = environment(wordpress, "WORDPRESS_CONFIG_EXTRA").unwrap_or_default();
+        let home_url = constant(&config, "WP_HOME").ok_or_else(|| invalid(file, "no `WP_HOME` in the WordPress config"))?;
+        let profile = if config.contains("FORCE_SSL_ADMIN") { Profile::Prod } else { Profile::Dev };
+        let backups = services.get("backup").and_then(|backup| archive_directory(backup, file));
+
+        let names: Vec<String> = services
+            .keys()
+            .filter_map(|name| name.as_str().map(str::to_string))
+            .collect();
+        let database = if names.iter().any(|name| name == Engine::Mariadb.service()) {
+            Engine::Mariadb
+        } else {
+            Engine::Mysql
+        };
+        let options = StackOptions {
+            database,
+            ..StackOptions::default()
+        };
+        let catalog: Vec<String> = catalog::services(&options, &mut Secrets::default())
+            .into_iter()
+            .map(|service| service.container_name)
+            .collect();
+        let services = names.into_iter().filter(|name| catalog.contains(name)).collect();
+        Ok(Source {
+            file: file.to_path_buf(),
+            services,
+            database,
+            database_name: environment(wordpress, "WORDPRESS_DB_NAME"),
+            home_url,
+            profile,
+            table_prefix: environment(wordpress, "WORDPRESS_TABLE_PREFIX"),
+            backups,
+        })
+    }
+}
+
+/// The host directory bound to `/archive` in the `backup` service.
+fn archive_directory(backup: &serde_yaml::Value, file: &Path) -> Option<PathBuf> {
+    let volumes = backup.get("volumes")?.as_sequence()?;
+    let directory = volumes
+        .iter()
+        .find(|volume| volume.get("target").and_then(|target| target.as_str()) == Some("/archive"))?
+        .get("source")?
+        .as_str()?;
+    Some(file.parent().unwrap_or(Path::new("")).join(directory.trim_start_matches("./")))
+}
+
+/// Generates the stack of `site` as a copy of `source`, with new secrets,
+/// into `<directory>/docker-compose.yml`, and returns the written file.
+///
+/// The table prefix of the source is kept so its database can be imported,
+/// and the `wpcli` job replaces the source URL by the new one.
+pub fn generate(source: &Source, site: Site, directory: &Path, force: bool) -> io::Result<PathBuf> {
+    let file = directory.join(COMPOSE_FILE);
+    if file.exists() && !force {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists; pass --force to overwrite it", file.display()),
+        ));
+    }
+
+    let mut secrets = Secrets::default();
+    if let Some(prefix) = &source.table_prefix {
+        if !wordpress::reuse_table_prefix(&mut secrets, prefix) {
+            return Err(invalid(&source.file, &format!("unsupported table prefix `{}`", prefix)));
+        }
+    }
+    let mut services = source.services.clone();
+    if !services.iter().any(|service| service == "backup") {
+        services.push("backup".to_string());
+    }
+    let options = StackOptions {
+        services,
+        site,
+        database: source.database,
+        replace_url: Some(source.home_url.clone()),
+        ..StackOptions::default()
+    };
+    let compose = catalog::stack(&options, &mut secrets)?;
+    fs::create_dir_all(directory)?;
+    fs::write(&file, parse_compose(&compose))?;
+    Ok(file)
+}
+
+/// The archive a clone imports: `archive` when given, else the newest one of
+/// the source backups.
+pub fn archive(source: &Source, archive: Option<PathBuf>) -> io::Result<PathBuf> {
+    if let Some(archive) = archive {
+        return Ok(archive);
+    }
+    let Some(directory) = &source.backups else {
+        return Err(invalid(
+            &source.file,
+            "the `backup` service has no local directory; pass the archive to import",
+        ));
+    };
+    backup::latest(directory)
+}
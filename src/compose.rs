@@ -0,0 +1,1003 @@
+use serde_json::{json, Map, Value};
+
+use crate::image::Image;
+use crate::resources::format_size;
+
+pub struct Compose {
+    pub version: Option<String>,
+    pub services: Vec<Service>,
+    pub volumes: Vec<Volume>,
+    pub networks: Vec<Network>,
+    pub configs: Vec<Config>,
+    pub environments: Vec<Environment>,
+    pub extensions: Vec<Extension>,
+}
+
+#[derive(Default)]
+pub struct Service {
+    /// The image to pull, or the tag of the image built from `build`.
+    pub image: Image,
+    pub build: Option<Build>,
+    pub container_name: String,
+    pub ports: Vec<Port>,
+    pub volumes: Vec<Mount>,
+    pub networks: Vec<NetworkAttachment>,
+    pub depends_on: Vec<Dependency>,
+    pub restart: Policy,
+    pub environments: Vec<Environment>,
+    pub command: Command,
+    pub labels: Vec<Label>,
+    pub resources: Resources,
+    pub healthcheck: Option<Healthcheck>,
+    /// `uid:gid` the container runs as, when not the image default.
+    pub user: Option<String>,
+    /// Exec form entrypoint; empty keeps the image entrypoint.
+    pub entrypoint: Vec<String>,
+    pub configs: Vec<ConfigFile>,
+    /// `None` keeps the daemon default.
+    pub logging: Option<Logging>,
+}
+
+/// Builds the image of a service instead of pulling it.
+pub struct Build {
+    /// Directory with the `Dockerfile`, relative to the compose file.
+    pub context: String
+}
+
+pub struct Port {
+    pub host: String,
+    pub container: String
+}
+
+pub struct Dependency {
+    pub service: String,
+    /// `service_started`, `service_healthy` or
+    /// `service_completed_successfully`; `None` renders the short syntax.
+    pub condition: Option<String>
+}
+
+#[derive(Default)]
+pub struct Policy {
+    pub condition: String
+}
+
+#[derive(Default)]
+pub struct Command {
+    pub value: String
+}
+
+pub struct Healthcheck {
+    /// `CMD`/`CMD-SHELL` followed by its arguments.
+    pub test: Vec<String>,
+    pub interval: String,
+    pub timeout: String,
+    pub retries: u32,
+    pub start_period: String,
+}
+
+pub struct Label {
+    pub key: String,
+    pub value: String
+}
+
+/// Resource limits and reservations of a service. Memory sizes are bytes.
+#[derive(Default)]
+pub struct Resources {
+    pub cpus: Option<f64>,
+    pub memory: Option<u64>,
+    pub reservation_cpus: Option<f64>,
+    pub reservation_memory: Option<u64>,
+    pub shm_size: Option<u64>,
+    pub ulimits: Vec<Ulimit>,
+}
+
+pub struct Ulimit {
+    pub name: String,
+    pub soft: u64,
+    pub hard: u64,
+}
+
+/// The log driver of a service and the rotation of its log files.
+#[derive(Clone)]
+pub struct Logging {
+    /// `json-file`, `local`, `journald`...
+    pub driver: String,
+    /// Size of a log file before it is rotated, in bytes.
+    pub max_size: Option<u64>,
+    /// Number of log files kept.
+    pub max_file: Option<u32>,
+}
+
+/// How a mount is attached to a service container.
+pub enum MountKind {
+    /// A host path mounted into the container.
+    Bind,
+    /// A named volume declared in the top-level `volumes` section.
+    Volume,
+    /// An in-memory filesystem; `source` is ignored.
+    Tmpfs,
+}
+
+/// A mount entry of a service, rendered with the compose long syntax.
+pub struct Mount {
+    pub kind: MountKind,
+    pub source: String,
+    pub target: String,
+    pub read_only: bool,
+}
+
+/// A top-level named volume.
+pub struct Volume {
+    pub title: String,
+    pub driver: String,
+    pub driver_opts: Vec<DriverOption>,
+    pub external: bool,
+}
+
+pub struct DriverOption {
+    pub key: String,
+    pub value: String
+}
+
+/// A file generated by woors and mounted into a service.
+pub struct ConfigFile {
+    /// Name of the top-level config holding the content.
+    pub title: String,
+    pub target: String,
+    pub content: String,
+}
+
+/// A top-level config, inlined in the compose file.
+pub struct Config {
+    pub title: String,
+    pub content: String,
+}
+
+/// A top-level network definition.
+pub struct Network {
+    pub title: String,
+    /// `bridge`, `overlay`, `macvlan`...; ignored for external networks.
+    pub driver: String,
+    pub driver_opts: Vec<DriverOption>,
+    pub external: bool,
+    /// Internal networks have no route to the outside world.
+    pub internal: bool,
+    pub subnets: Vec<Subnet>,
+}
+
+/// An IPAM pool of a network.
+pub struct Subnet {
+    pub subnet: String,
+    pub gateway: Option<String>,
+}
+
+/// Attaches a service to a top-level network.
+pub struct NetworkAttachment {
+    pub network: String,
+    pub aliases: Vec<String>,
+    pub ipv4_address: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct Environment {
+    pub title: String,
+    pub value: String
+}
+
+pub struct Extension {
+    pub title: String,
+    pub value: String
+}
+
+impl Environment {
+    pub fn new(title: &str, value: &str) -> Environment {
+        Environment {
+            title: title.to_string(),
+            value: value.to_string(),
+        }
+    }
+}
+
+impl MountKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MountKind::Bind => "bind",
+            MountKind::Volume => "volume",
+            MountKind::Tmpfs => "tmpfs",
+        }
+    }
+}
+
+/// Declares a local volume for every named volume mounted by `services`,
+/// in order of first use.
+pub fn named_volumes(services: &[Service]) -> Vec<Volume> {
+    let mut volumes: Vec<Volume> = Vec::new();
+    for service in services {
+        for mount in &service.volumes {
+            if !matches!(mount.kind, MountKind::Volume) {
+                continue;
+            }
+            if volumes.iter().any(|volume| volume.title == mount.source) {
+                continue;
+            }
+            volumes.push(Volume {
+                title: mount.source.clone(),
+                driver: "local".to_string(),
+                driver_opts: vec![],
+                external: false
+            });
+        }
+    }
+    volumes
+}
+
+/// Declares a top-level config for every config file of `services`, in
+/// order of first use.
+pub fn named_configs(services: &[Service]) -> Vec<Config> {
+    let mut configs: Vec<Config> = Vec::new();
+    for service in services {
+        for file in &service.configs {
+            if configs.iter().any(|config| config.title == file.title) {
+                continue;
+            }
+            configs.push(Config {
+                title: file.title.clone(),
+                content: file.content.clone()
+            });
+        }
+    }
+    configs
+}
+
+/// Quotes `value` as a YAML double-quoted scalar, escaping `$` so compose
+/// does not interpolate it.
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '$' => quoted.push_str("$$"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+pub fn parse_compose(compose: &Compose) -> String {
+    let mut compose_str = String::new();
+    compose_str.push_str("version: ");
+    compose_str.push_str(compose.version.as_deref().unwrap());
+    compose_str.push('\n');
+
+    if !compose.services.is_empty() {
+        compose_str.push('\n');
+        compose_str.push('\n');
+        compose_str.push_str("services:\n");
+        for service in &compose.services {
+            compose_str.push('\n');
+            compose_str.push_str("  ");
+            compose_str.push_str(&service.container_name);
+            compose_str.push_str(":\n");
+            compose_str.push_str("    image: ");
+            compose_str.push_str(&service.image.to_string());
+            compose_str.push('\n');
+            if let Some(build) = &service.build {
+                compose_str.push_str("    build:\n");
+                compose_str.push_str("      context: ");
+                compose_str.push_str(&build.context);
+                compose_str.push('\n');
+            }
+            compose_str.push_str("    container_name: ");
+            compose_str.push_str(&service.container_name);
+            compose_str.push('\n');
+
+            if !service.ports.is_empty() {
+                compose_str.push('\n');
+                compose_str.push_str("    ports:\n");
+                for port in &service.ports {
+                    compose_str.push_str("      - ");
+                    compose_str.push_str(&port.host);
+                    compose_str.push(':');
+                    compose_str.push_str(&port.container);
+                    compose_str.push('\n');
+                }
+            }
+
+            if !service.volumes.is_empty() {
+                compose_str.push('\n');
+                compose_str.push_str("    volumes:\n");
+                for mount in &service.volumes {
+                    compose_str.push_str("      - type: ");
+                    compose_str.push_str(mount.kind.as_str());
+                    compose_str.push('\n');
+                    if !matches!(mount.kind, MountKind::Tmpfs) {
+                        compose_str.push_str("        source: ");
+                        compose_str.push_str(&mount.source);
+                        compose_str.push('\n');
+                    }
+                    compose_str.push_str("        target: ");
+                    compose_str.push_str(&mount.target);
+                    compose_str.push('\n');
+                    if mount.read_only {
+                        compose_str.push_str("        read_only: true\n");
+                    }
+                }
+            }
+
+            if let Some(user) = &service.user {
+                compose_str.push('\n');
+                compose_str.push_str("    user: ");
+                compose_str.push_str(&quote(user));
+                compose_str.push('\n');
+            }
+
+            if !service.configs.is_empty() {
+                compose_str.push('\n');
+                compose_str.push_str("    configs:\n");
+                for file in &service.configs {
+                    compose_str.push_str("      - source: ");
+                    compose_str.push_str(&file.title);
+                    compose_str.push('\n');
+                    compose_str.push_str("        target: ");
+                    compose_str.push_str(&file.target);
+                    compose_str.push('\n');
+                }
+            }
+
+            if !service.depends_on.is_empty() {
+                compose_str.push('\n');
+                compose_str.push_str("    depends_on:\n");
+                let long = service.depends_on.iter().any(|dependency| dependency.condition.is_some());
+                for dependency in &service.depends_on {
+                    if !long {
+                        compose_str.push_str("      - ");
+                        compose_str.push_str(&dependency.service);
+                        compose_str.push('\n');
+                        continue;
+                    }
+                    compose_str.push_str("      ");
+                    compose_str.push_str(&dependency.service);
+                    compose_str.push_str(":\n");
+                    compose_str.push_str("        condition: ");
+                    compose_str.push_str(dependency.condition.as_deref().unwrap_or("service_started"));
+                    compose_str.push('\n');
+                }
+            }
+
+            if !service.restart.condition.is_empty() {
+                compose_str.push('\n');
+                compose_str.push_str("    restart: ");
+                // A bare `no` would be read as a YAML boolean.
+                compose_str.push_str(&quote(&service.restart.condition));
+                compose_str.push('\n');
+            }
+
+            if !service.entrypoint.is_empty() {
+                compose_str.push('\n');
+                compose_str.push_str("    entrypoint:\n");
+                for argument in &service.entrypoint {
+                    compose_str.push_str("      - ");
+                    compose_str.push_str(&quote(argument));
+                    compose_str.push('\n');
+                }
+            }
+
+            if !service.command.value.is_empty() {
+                compose_str.push('\n');
+                compose_str.push_str("    command: ");
+                compose_str.push_str(&quote(&service.command.value));
+                compose_str.push('\n');
+            }
+
+            if let Some(healthcheck) = &service.healthcheck {
+                compose_str.push('\n');
+                compose_str.push_str("    healthcheck:\n");
+                compose_str.push_str("      test: [");
+                let test: Vec<String> = healthcheck.test.iter().map(|argument| quote(argument)).collect();
+                compose_str.push_str(&test.join(", "));
+                compose_str.push_str("]\n");
+                compose_str.push_str("      interval: ");
+                compose_str.push_str(&healthcheck.interval);
+                compose_str.push('\n');
+                compose_str.push_str("      timeout: ");
+                compose_str.push_str(&healthcheck.timeout);
+                compose_str.push('\n');
+                compose_str.push_str("      retries: ");
+                compose_str.push_str(&healthcheck.retries.to_string());
+                compose_str.push('\n');
+                compose_str.push_str("      start_period: ");
+                compose_str.push_str(&healthcheck.start_period);
+                compose_str.push('\n');
+            }
+
+            if !service.labels.is_empty() {
+                compose_str.push('\n');
+                compose_str.push_str("    labels:\n");
+                for label in &service.labels {
+                    compose_str.push_str("      - ");
+                    compose_str.push_str(&quote(&format!("{}={}", label.key, label.value)));
+                    compose_str.push('\n');
+                }
+            }
+
+            if !service.environments.is_empty() {
+                compose_str.push('\n');
+                compose_str.push_str("    environment:\n");
+                for environment in &service.environments {
+                    compose_str.push_str("      - ");
+                    compose_str.push_str(&quote(&format!("{}={}", environment.title, environment.value)));
+                    compose_str.push('\n');
+                }
+            }
+
+            if service.resources.shm_size.is_some() || !service.resources.ulimits.is_empty() {
+                compose_str.push('\n');
+            }
+            if let Some(shm_size) = service.resources.shm_size {
+                compose_str.push_str("    shm_size: ");
+                compose_str.push_str(&format_size(shm_size));
+                compose_str.push('\n');
+            }
+            if !service.resources.ulimits.is_empty() {
+                compose_str.push_str("    ulimits:\n");
+                for ulimit in &service.resources.ulimits {
+                    compose_str.push_str("      ");
+                    compose_str.push_str(&ulimit.name);
+                    compose_str.push_str(":\n");
+                    compose_str.push_str("        soft: ");
+                    compose_str.push_str(&ulimit.soft.to_string());
+                    compose_str.push('\n');
+                    compose_str.push_str("        hard: ");
+                    compose_str.push_str(&ulimit.hard.to_string());
+                    compose_str.push('\n');
+                }
+            }
+
+            let limits = (service.resources.cpus, service.resources.memory);
+            let reservations = (service.resources.reservation_cpus, service.resources.reservation_memory);
+            if limits != (None, None) || reservations != (None, None) {
+                compose_str.push('\n');
+                compose_str.push_str("    deploy:\n");
+                compose_str.push_str("      resources:\n");
+                for (title, (cpus, memory)) in [("limits", limits), ("reservations", reservations)] {
+                    if (cpus, memory) == (None, None) {
+                        continue;
+                    }
+                    compose_str.push_str("        ");
+                    compose_str.push_str(title);
+                    compose_str.push_str(":\n");
+                    if let Some(cpus) = cpus {
+                        compose_str.push_str("          cpus: \"");
+                        compose_str.push_str(&cpus.to_string());
+                        compose_str.push_str("\"\n");
+                    }
+                    if let Some(memory) = memory {
+                        compose_str.push_str("          memory: ");
+                        compose_str.push_str(&format_size(memory));
+                        compose_str.push('\n');
+                    }
+                }
+            }
+
+            if let Some(logging) = &service.logging {
+                compose_str.push('\n');
+                compose_str.push_str("    logging:\n");
+                compose_str.push_str("      driver: ");
+                compose_str.push_str(&logging.driver);
+                compose_str.push('\n');
+                if logging.max_size.is_some() || logging.max_file.is_some() {
+                    compose_str.push_str("      options:\n");
+                }
+                if let Some(max_size) = logging.max_size {
+                    compose_str.push_str("        max-size: \"");
+                    compose_str.push_str(&format_size(max_size));
+                    compose_str.push_str("\"\n");
+                }
+                if let Some(max_file) = logging.max_file {
+                    compose_str.push_str("        max-file: \"");
+                    compose_str.push_str(&max_file.to_string());
+                    compose_str.push_str("\"\n");
+                }
+            }
+
+            if !service.networks.is_empty() {
+                compose_str.push('\n');
+                compose_str.push_str("    networks:\n");
+                for attachment in &service.networks {
+                    compose_str.push_str("      ");
+                    compose_str.push_str(&attachment.network);
+                    if attachment.aliases.is_empty() && attachment.ipv4_address.is_none() {
+                        compose_str.push_str(": {}\n");
+                        continue;
+                    }
+                    compose_str.push_str(":\n");
+                    if !attachment.aliases.is_empty() {
+                        compose_str.push_str("        aliases:\n");
+                        for alias in &attachment.aliases {
+                            compose_str.push_str("          - ");
+                            compose_str.push_str(alias);
+                            compose_str.push('\n');
+                        }
+                    }
+                    if let Some(address) = &attachment.ipv4_address {
+                        compose_str.push_str("        ipv4_address: ");
+                        compose_str.push_str(address);
+                        compose_str.push('\n');
+                    }
+                }
+            }
+
+        }
+    }
+
+    if !compose.volumes.is_empty() {
+        compose_str.push('\n');
+        compose_str.push('\n');
+        compose_str.push_str("volumes:\n");
+        for volume in &compose.volumes {
+            compose_str.push_str("  ");
+            compose_str.push_str(&volume.title);
+            compose_str.push_str(":\n");
+            if volume.external {
+                compose_str.push_str("    external: true\n");
+                continue;
+            }
+            compose_str.push_str("    driver: ");
+            compose_str.push_str(&volume.driver);
+            compose_str.push('\n');
+            if !volume.driver_opts.is_empty() {
+                compose_str.push_str("    driver_opts:\n");
+                for option in &volume.driver_opts {
+                    compose_str.push_str("      ");
+                    compose_str.push_str(&option.key);
+                    compose_str.push_str(": \"");
+                    compose_str.push_str(&option.value);
+                    compose_str.push_str("\"\n");
+                }
+            }
+        }
+    }
+
+    if !compose.configs.is_empty() {
+        compose_str.push('\n');
+        compose_str.push('\n');
+        compose_str.push_str("configs:\n");
+        for config in &compose.configs {
+            compose_str.push_str("  ");
+            compose_str.push_str(&config.title);
+            compose_str.push_str(":\n");
+            compose_str.push_str("    content: |\n");
+            for line in config.content.lines() {
+                if !line.is_empty() {
+                    compose_str.push_str("      ");
+                    compose_str.push_str(&line.replace('$', "$$"));
+                }
+                compose_str.push('\n');
+            }
+        }
+    }
+
+    if !compose.networks.is_empty() {
+        compose_str.push('\n');
+        compose_str.push('\n');
+        compose_str.push_str("networks:\n");
+        for network in &compose.networks {
+            compose_str.push_str("  ");
+            compose_str.push_str(&network.title);
+            compose_str.push_str(":\n");
+            if network.external {
+                compose_str.push_str("    external: true\n");
+                continue;
+            }
+            compose_str.push_str("    driver: ");
+            compose_str.push_str(&network.driver);
+            compose_str.push('\n');
+            if !network.driver_opts.is_empty() {
+                compose_str.push_str("    driver_opts:\n");
+                for option in &network.driver_opts {
+                    compose_str.push_str("      ");
+                    compose_str.push_str(&option.key);
+                    compose_str.push_str(": \"");
+                    compose_str.push_str(&option.value);
+                    compose_str.push_str("\"\n");
+                }
+            }
+            if network.internal {
+                compose_str.push_str("    internal: true\n");
+            }
+            if !network.subnets.is_empty() {
+                compose_str.push_str("    ipam:\n");
+                compose_str.push_str("      config:\n");
+                for subnet in &network.subnets {
+                    compose_str.push_str("        - subnet: ");
+                    compose_str.push_str(&subnet.subnet);
+                    compose_str.push('\n');
+                    if let Some(gateway) = &subnet.gateway {
+                        compose_str.push_str("          gateway: ");
+                        compose_str.push_str(gateway);
+                        compose_str.push('\n');
+                    }
+                }
+            }
+        }
+    }
+
+    compose_str
+}
+
+/// The compose file of `compose` as JSON, which compose reads as well as
+/// the YAML of [`parse_compose`]. Values are escaped for interpolation the
+/// same way.
+pub fn compose_json(compose: &Compose) -> Value {
+    let escape = |value: &str| value.replace('$', "$$");
+    let mut root = Map::new();
+    root.insert("version".to_string(), json!(compose.version));
+
+    let mut services = Map::new();
+    for service in &compose.services {
+        let mut entry = Map::new();
+        entry.insert("image".to_string(), json!(service.image.to_string()));
+        if let Some(build) = &service.build {
+            entry.insert("build".to_string(), json!({ "context": build.context }));
+        }
+        entry.insert("container_name".to_string(), json!(service.container_name));
+        if !service.ports.is_empty() {
+            let ports: Vec<String> =
+                service.ports.iter().map(|port| format!("{}:{}", port.host, port.container)).collect();
+            entry.insert("ports".to_string(), json!(ports));
+        }
+        if !service.volumes.is_empty() {
+            let mounts: Vec<Value> = service
+                .volumes
+                .iter()
+                .map(|mount| {
+                    let mut entry = Map::new();
+                    entry.insert("type".to_string(), json!(mount.kind.as_str()));
+                    if !matches!(mount.kind, MountKind::Tmpfs) {
+                        entry.insert("source".to_string(), json!(mount.source));
+                    }
+                    entry.insert("target".to_string(), json!(mount.target));
+                    if mount.read_only {
+                        entry.insert("read_only".to_string(), json!(true));
+                    }
+                    Value::Object(entry)
+                })
+                .collect();
+            entry.insert("volumes".to_string(), json!(mounts));
+        }
+        if let Some(user) = &service.user {
+            entry.insert("user".to_string(), json!(escape(user)));
+        }
+        if !service.configs.is_empty() {
+            let configs: Vec<Value> = service
+                .configs
+                .iter()
+                .map(|file| json!({ "source": file.title, "target": file.target }))
+                .collect();
+            entry.insert("configs".to_string(), json!(configs));
+        }
+        if !service.depends_on.is_empty() {
+            let long = service.depends_on.iter().any(|dependency| dependency.condition.is_some());
+            let depends_on = if long {
+                let conditions: Map<String, Value> = service
+                    .depends_on
+                    .iter()
+                    .map(|dependency| {
+                        let condition = dependency.condition.as_deref().unwrap_or("service_started");
+                        (dependency.service.clone(), json!({ "condition": condition }))
+                    })
+                    .collect();
+                Value::Object(conditions)
+            } else {
+                json!(service.depends_on.iter().map(|dependency| &dependency.service).collect::<Vec<_>>())
+            };
+            entry.insert("depends_on".to_string(), depends_on);
+        }
+        if !service.restart.condition.is_empty() {
+            entry.insert("restart".to_string(), json!(escape(&service.restart.condition)));
+        }
+        if !service.entrypoint.is_empty() {
+            let entrypoint: Vec<String> = service.entrypoint.iter().map(|argument| escape(argument)).collect();
+            entry.insert("entrypoint".to_string(), json!(entrypoint));
+        }
+        if !service.command.value.is_empty() {
+            entry.insert("command".to_string(), json!(escape(&service.command.value)));
+        }
+        if let Some(healthcheck) = &service.healthcheck {
+            let test: Vec<String> = healthcheck.test.iter().map(|argument| escape(argument)).collect();
+            entry.insert(
+                "healthcheck".to_string(),
+                json!({
+                    "test": test,
+                    "interval": healthcheck.interval,
+                    "timeout": healthcheck.timeout,
+                    "retries": healthcheck.retries,
+                    "start_period": healthcheck.start_period,
+                }),
+            );
+        }
+        if !service.labels.is_empty() {
+            let labels: Vec<String> =
+                service.labels.iter().map(|label| escape(&format!("{}={}", label.key, label.value))).collect();
+            entry.insert("labels".to_string(), json!(labels));
+        }
+        if !service.environments.is_empty() {
+            let environment: Vec<String> = service
+                .environments
+                .iter()
+                .map(|environment| escape(&format!("{}={}", environment.title, environment.value)))
+                .collect();
+            entry.insert("environment".to_string(), json!(environment));
+        }
+        if let Some(shm_size) = service.resources.shm_size {
+            entry.insert("shm_size".to_string(), json!(format_size(shm_size)));
+        }
+        if !service.resources.ulimits.is_empty() {
+            let ulimits: Map<String, Value> = service
+                .resources
+                .ulimits
+                .iter()
+                .map(|ulimit| (ulimit.name.clone(), json!({ "soft": ulimit.soft, "hard": ulimit.hard })))
+                .collect();
+            entry.insert("ulimits".to_string(), Value::Object(ulimits));
+        }
+        let limits = (service.resources.cpus, service.resources.memory);
+        let reservations = (service.resources.reservation_cpus, service.resources.reservation_memory);
+        if limits != (None, None) || reservations != (None, None) {
+            let mut resources = Map::new();
+            for (title, (cpus, memory)) in [("limits", limits), ("reservations", reservations)] {
+                if (cpus, memory) == (None, None) {
+                    continue;
+                }
+                let mut values = Map::new();
+                if let Some(cpus) = cpus {
+                    values.insert("cpus".to_string(), json!(cpus.to_string()));
+                }
+                if let Some(memory) = memory {
+                    values.insert("memory".to_string(), json!(format_size(memory)));
+                }
+                resources.insert(title.to_string(), Value::Object(values));
+            }
+            entry.insert("deploy".to_string(), json!({ "resources": resources }));
+        }
+        if let Some(logging) = &service.logging {
+            let mut options = Map::new();
+            if let Some(max_size) = logging.max_size {
+                options.insert("max-size".to_string(), json!(format_size(max_size)));
+            }
+            if let Some(max_file) = logging.max_file {
+                options.insert("max-file".to_string(), json!(max_file.to_string()));
+            }
+            let mut values = Map::new();
+            values.insert("driver".to_string(), json!(logging.driver));
+            if !options.is_empty() {
+                values.insert("options".to_string(), Value::Object(options));
+            }
+            entry.insert("logging".to_string(), Value::Object(values));
+        }
+        if !service.networks.is_empty() {
+            let mut networks = Map::new();
+            for attachment in &service.networks {
+                let mut values = Map::new();
+                if !attachment.aliases.is_empty() {
+                    values.insert("aliases".to_string(), json!(attachment.aliases));
+                }
+                if let Some(address) = &attachment.ipv4_address {
+                    values.insert("ipv4_address".to_string(), json!(address));
+                }
+                networks.insert(attachment.network.clone(), Value::Object(values));
+            }
+            entry.insert("networks".to_string(), Value::Object(networks));
+        }
+        services.insert(service.container_name.clone(), Value::Object(entry));
+    }
+    if !services.is_empty() {
+        root.insert("services".to_string(), Value::Object(services));
+    }
+
+    if !compose.volumes.is_empty() {
+        let volumes: Map<String, Value> = compose
+            .volumes
+            .iter()
+            .map(|volume| {
+                let value = if volume.external {
+                    json!({ "external": true })
+                } else {
+                    let mut values = Map::new();
+                    values.insert("driver".to_string(), json!(volume.driver));
+                    if !volume.driver_opts.is_empty() {
+                        values.insert("driver_opts".to_string(), driver_opts(&volume.driver_opts));
+                    }
+                    Value::Object(values)
+                };
+                (volume.title.clone(), value)
+            })
+            .collect();
+        root.insert("volumes".to_string(), Value::Object(volumes));
+    }
+
+    if !compose.configs.is_empty() {
+        let configs: Map<String, Value> = compose
+            .configs
+            .iter()
+            .map(|config| (config.title.clone(), json!({ "content": escape(&config.content) })))
+            .collect();
+        root.insert("configs".to_string(), Value::Object(configs));
+    }
+
+    if !compose.networks.is_empty() {
+        let networks: Map<String, Value> = compose
+            .networks
+            .iter()
+            .map(|network| {
+                if network.external {
+                    return (network.title.clone(), json!({ "external": true }));
+                }
+                let mut values = Map::new();
+                values.insert("driver".to_string(), json!(network.driver));
+                if !network.driver_opts.is_empty() {
+                    values.insert("driver_opts".to_string(), driver_opts(&network.driver_opts));
+                }
+                if network.internal {
+                    values.insert("internal".to_string(), json!(true));
+                }
+                if !network.subnets.is_empty() {
+                    let config: Vec<Value> = network
+                        .subnets
+                        .iter()
+                        .map(|subnet| match &subnet.gateway {
+                            Some(gateway) => json!({ "subnet": subnet.subnet, "gateway": gateway }),
+                            None => json!({ "subnet": subnet.subnet }),
+                        })
+                        .collect();
+                    values.insert("ipam".to_string(), json!({ "config": config }));
+                }
+                (network.title.clone(), Value::Object(values))
+            })
+            .collect();
+        root.insert("networks".to_string(), Value::Object(networks));
+    }
+
+    Value::Object(root)
+}
+
+fn driver_opts(options: &[DriverOption]) -> Value {
+    Value::Object(options.iter().map(|option| (option.key.clone(), json!(option.value))).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compose(services: Vec<Service>, configs: Vec<Config>) -> Compose {
+        Compose {
+            version: Some("3.8".to_string()),
+            services,
+            volumes: vec![],
+            networks: vec![],
+            configs,
+            environments: vec![],
+            extensions: vec![],
+        }
+    }
+
+    #[test]
+    fn quote_escapes_yaml_and_interpolation() {
+        assert_eq!(quote("plain"), "\"plain\"");
+        assert_eq!(quote("a\"b\\c"), "\"a\\\"b\\\\c\"");
+        assert_eq!(quote("one\ntwo\tthree"), "\"one\\ntwo\\tthree\"");
+        assert_eq!(quote("pa$$word${HOME}"), "\"pa$$$$word$${HOME}\"");
+        assert_eq!(quote("no"), "\"no\"");
+    }
+
+    #[test]
+    fn service_values_are_quoted() {
+        let service = Service {
+            image: Image::parse("mysql:8.0").unwrap(),
+            container_name: "mysql".to_string(),
+            environments: vec![Environment::new("MYSQL_PASSWORD", "a$b\"c")],
+            labels: vec![Label {
+                key: "traefik.http.routers.site.rule".to_string(),
+                value: "Host(`$host`)".to_string(),
+            }],
+            restart: Policy {
+                condition: "no".to_string(),
+            },
+            command: Command {
+                value: "mysqld --init-file=\"$HOME/init.sql\"".to_string(),
+            },
+            ..Default::default()
+        };
+        let rendered = parse_compose(&compose(vec![service], vec![]));
+        assert!(rendered.starts_with("version: 3.8\n"));
+        assert!(rendered.contains("    image: docker.io/library/mysql:8.0\n"));
+        assert!(rendered.contains("      - \"MYSQL_PASSWORD=a$$b\\\"c\"\n"));
+        assert!(rendered.contains("      - \"traefik.http.routers.site.rule=Host(`$$host`)\"\n"));
+        assert!(rendered.contains("    restart: \"no\"\n"));
+        assert!(rendered.contains("    command: \"mysqld --init-file=\\\"$$HOME/init.sql\\\"\"\n"));
+    }
+
+    fn mount(kind: MountKind, source: &str, target: &str, read_only: bool) -> Mount {
+        Mount {
+            kind,
+            source: source.to_string(),
+            target: target.to_string(),
+            read_only,
+        }
+    }
+
+    fn mounts() -> Service {
+        Service {
+            image: Image::parse("nginx:1.27").unwrap(),
+            container_name: "nginx".to_string(),
+            volumes: vec![
+                mount(MountKind::Volume, "wordpress", "/var/www/html", false),
+                mount(MountKind::Bind, "./nginx.conf", "/etc/nginx/nginx.conf", true),
+                mount(MountKind::Tmpfs, "", "/tmp", false),
+                mount(MountKind::Volume, "wordpress", "/srv/html", true),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn mounts_use_the_long_syntax() {
+        let rendered = parse_compose(&compose(vec![mounts()], vec![]));
+        let expected = [
+            "    volumes:",
+            "      - type: volume",
+            "        source: wordpress",
+            "        target: /var/www/html",
+            "      - type: bind",
+            "        source: ./nginx.conf",
+            "        target: /etc/nginx/nginx.conf",
+            "        read_only: true",
+            "      - type: tmpfs",
+            "        target: /tmp",
+            "",
+        ];
+        assert!(rendered.contains(&expected.join("\n")));
+    }
+
+    #[test]
+    fn named_volumes_skip_bind_mounts_and_tmpfs() {
+        let volumes = named_volumes(&[mounts()]);
+        let titles: Vec<&str> = volumes.iter().map(|volume| volume.title.as_str()).collect();
+        assert_eq!(titles, vec!["wordpress"]);
+    }
+
+    #[test]
+    fn configs_are_block_scalars() {
+        let config = Config {
+            title: "nginx".to_string(),
+            content: "server {\n\n    set $root /var/www;\n}\n".to_string(),
+        };
+        let rendered = parse_compose(&compose(vec![], vec![config]));
+        assert!(rendered.ends_with(
+            "configs:\n  nginx:\n    content: |\n      server {\n\n          set $$root /var/www;\n      }\n"
+        ));
+    }
+
+    #[test]
+    fn empty_sections_are_left_out() {
+        let rendered = parse_compose(&compose(vec![], vec![]));
+        assert_eq!(rendered, "version: 3.8\n");
+    }
+
+    #[test]
+    fn json_is_the_model_of_the_yaml_file() {
+        let options = crate::catalog::StackOptions::default();
+        let stack = crate::catalog::stack(&options, &mut crate::secrets::Secrets::default()).unwrap();
+        let mut yaml: Value = serde_yaml::from_str(&parse_compose(&stack)).unwrap();
+        let mut json = compose_json(&stack);
+        // A bare `3.8` is a number in YAML.
+        assert_eq!(json["version"], "3.8");
+        yaml.as_object_mut().unwrap().remove("version");
+        json.as_object_mut().unwrap().remove("version");
+        assert_eq!(json, yaml);
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::catalog::StackOptions;
+use crate::image::{self, Image};
+use crate::project;
+use crate::site::Profile;
+use crate::template;
+
+/// Configuration file read from the current directory when present.
+pub const FILE: &str = "woors.toml";
+
+/// Prefix of the environment variables of the settings, e.g. `WOORS_PORT`.
+const PREFIX: &str = "WOORS_";
+
+/// Names another configuration file than [`FILE`].
+pub const FILE_VARIABLE: &str = "WOORS_CONFIG";
+
+/// Prefix of the image overrides, e.g. `WOORS_IMAGE_CODE_SERVER` for
+/// `code-server`.
+const IMAGE_PREFIX: &str = "WOORS_IMAGE_";
+
+/// Verbosity of the server logs.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+/// The settings of woors, from the defaults, [`FILE`], the `WOORS_*`
+/// environment variables and the command line, each overriding the
+/// previous ones.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address `woors serve` binds.
+    pub address: String,
+    pub port: u16,
+    pub log_level: LogLevel,
+    /// PEM certificate chain and private key `woors serve` uses to serve
+    /// HTTPS instead of HTTP.
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// Directory of the project store and its key.
+    pub data_dir: PathBuf,
+    /// Directory of the service templates; `templates` in the data
+    /// directory when unset.
+    pub template_dir: Option<PathBuf>,
+    /// Profile of the stacks that do not set one.
+    pub profile: Profile,
+    /// Admin e-mail of the stacks that do not set one.
+    pub email: Option<String>,
+    /// Image references replacing those of the release, by catalog service.
+    pub images: BTreeMap<String, String>,
+    /// Where each setting comes from, for `woors config show`.
+    #[serde(skip)]
+    sources: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            address: "0.0.0.0".to_string(),
+            port: 8888,
+            log_level: LogLevel::default(),
+            tls_cert: None,
+            tls_key: None,
+            data_dir: PathBuf::from("."),
+            template_dir: None,
+            profile: Profile::default(),
+            email: None,
+            images: BTreeMap::new(),
+            sources: BTreeMap::new(),
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// The environment variable of a setting.
+fn variable(key: &str) -> String {
+    format!("{}{}", PREFIX, key.to_ascii_uppercase())
+}
+
+impl Config {
+    /// The defaults overridden by the configuration file, `file` or else
+    /// [`FILE_VARIABLE`] or [`FILE`], and by the environment. Only a file
+    /// that was asked for has to exist.
+    pub fn load(file: Option<&Path>) -> io::Result<Config> {
+        // Variables that are not Unicode cannot be settings.
+        let vars: BTreeMap<String, String> = env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect();
+        let mut config = Config::default();
+        let file = match file {
+            Some(file) => Some(file.to_path_buf()),
+            None => vars.get(FILE_VARIABLE).map(PathBuf::from),
+        };
+        let table = match &file {
+            Some(file) => Some((fs::read_to_string(file)?, file.clone())),
+            None => match fs::read_to_string(FILE) {
+                Ok(content) => Some((content, PathBuf::from(FILE))),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err),
+            },
+        };
+        if let Some((content, file)) = table {
+            let table: Table = content
+                .parse()
+                .map_err(|err| invalid(format!("{}: {}", file.display(), err)))?;
+            config = config.merge(table, &file.display().to_string())?;
+        }
+        config.apply_env(&vars)
+    }
+
+    /// The settings of `table` over these, recording where they come from.
+    fn merge(self, table: Table, source: &str) -> io::Result<Config> {
+        let mut sources = self.sources.clone();
+        for (key, value) in &table {
+            match (key.as_str(), value) {
+                ("images", Value::Table(images)) => {
+                    for service in images.keys() {
+                        sources.insert(format!("images.{}", service), source.to_string());
+                    }
+                }
+                _ => {
+                    sources.insert(key.clone(), source.to_string());
+                }
+            }
+        }
+        let mut merged = Table::try_from(&self).map_err(io::Error::other)?;
+        for (key, value) in table {
+            match (merged.get_mut(&key), value) {
+                (Some(Value::Table(images)), Value::Table(overrides)) => images.extend(overrides),
+                (_, value) => {
+                    merged.insert(key, value);
+                }
+            }
+        }
+        let mut config: Config = merged
+            .try_into()
+            .map_err(|err: toml::de::Error| invalid(format!("{}: {}", source, err.message())))?;
+        config.sources = sources;
+        Ok(config)
+    }
+
+    /// Overrides the settings with their `WOORS_*` variables of `vars`, the
+    /// environment.
+    fn apply_env(self, vars: &BTreeMap<String, String>) -> io::Result<Config> {
+        let defaults = Table::try_from(Config::default()).map_err(io::Error::other)?;
+        let keys = [
+            "address", "port", "log_level", "tls_cert", "tls_key", "data_dir", "template_dir", "profile", "email",
+        ];
+        let mut config = self;
+        for key in keys {
+            let name = variable(key);
+            let Some(raw) = vars.get(&name).cloned() else {
+                continue;
+            };
+            // Numbers are the only settings that are not strings.
+            let value = match defaults.get(key) {
+                Some(Value::Integer(_)) => Value::Integer(
+                    raw.trim()
+                        .parse()
+                        .map_err(|_| invalid(format!("{}: expected a number, got `{}`", name, raw)))?,
+                ),
+                _ => Value::String(raw),
+            };
+            config = config.merge(Table::from_iter([(key.to_string(), value)]), &name)?;
+        }
+        let mut images = Table::new();
+        for (name, reference) in vars {
+            if let Some(service) = name.strip_prefix(IMAGE_PREFIX) {
+                images.insert(service.to_ascii_lowercase().replace('_', "-"), Value::String(reference.clone()));
+            }
+        }
+        for (service, reference) in images {
+            let name = format!("{}{}", IMAGE_PREFIX, service.to_ascii_uppercase().replace('-', "_"));
+            let table = Table::from_iter([("images".to_string(), Value::Table(Table::from_iter([(service, reference)])))]);
+            config = config.merge(table, &name)?;
+        }
+        Ok(config)
+    }
+
+    /// Records a command line flag overriding a setting.
+    pub fn set_flag(&mut self, key: &str, flag: &str) {
+        self.sources.insert(key.to_string(), flag.to_string());
+    }
+
+    /// Checks the settings that can be wrong without failing to parse.
+    pub fn validate(&self) -> io::Result<()> {
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            return Err(invalid("`tls_cert` and `tls_key` go together".to_string()));
+        }
+        for (key, path) in [("tls_cert", &self.tls_cert), ("tls_key", &self.tls_key)] {
+            if let Some(path) = path.as_ref().filter(|path| !path.is_file()) {
+                return Err(invalid(format!("{}: `{}` is not a file", key, path.display())));
+            }
+        }
+        for (service, reference) in &self.images {
+            self.image(service, reference)?;
+        }
+        Ok(())
+    }
+
+    fn image(&self, service: &str, reference: &str) -> io::Result<Image> {
+        if !image::current_release().images.iter().any(|(name, ..)| *name == service) {
+            return Err(invalid(format!("images: `{}` is not a catalog service", service)));
+        }
+        Image::parse(reference).ok_or_else(|| invalid(format!("images: invalid reference `{}` for `{}`", reference, service)))
+    }
+
+    /// Makes the image overrides those of every catalog service from now on.
+    pub fn install_images(&self) -> io::Result<()> {
+        let mut images = BTreeMap::new();
+        for (service, reference) in &self.images {
+            images.insert(service.clone(), self.image(service, reference)?);
+        }
+        image::override_images(images);
+        Ok(())
+    }
+
+    /// The `host:port` the server binds.
+    pub fn bind(&self) -> (String, u16) {
+        (self.address.clone(), self.port)
+    }
+
+    /// The certificate chain and private key to serve HTTPS with, when set.
+    pub fn tls(&self) -> Option<(&Path, &Path)> {
+        Some((self.tls_cert.as_deref()?, self.tls_key.as_deref()?))
+    }
+
+    /// The project store, [`project::DATABASE`] in the data directory.
+    pub fn database(&self) -> PathBuf {
+        self.data_dir.join(project::DATABASE)
+    }
+
+    /// The service templates directory.
+    pub fn template_dir(&self) -> PathBuf {
+        self.template_dir.clone().unwrap_or_else(|| self.data_dir.join(template::DIRECTORY))
+    }
+
+    /// Makes the service templates part of the catalog from now on.
+    pub fn install_templates(&self) -> io::Result<()> {
+        template::install(template::load(&self.template_dir())?);
+        Ok(())
+    }
+
+    /// The stack definition the API requests and command line flags
+    /// start from.
+    pub fn stack_defaults(&self) -> StackOptions {
+        let mut stack = StackOptions::default();
+        stack.site.profile = self.profile;
+        stack.shop.admin_email = self.email.clone();
+        stack
+    }
+
+    /// The effective settings as TOML, each commented with its origin.
+    pub fn show(&self) -> io::Result<String> {
+        let table = Table::try_from(self).map_err(io::Error::other)?;
+        let source = |key: &str| self.sources.get(key).map_or("default", String::as_str).to_string();
+        let mut shown = String::new();
+        let mut images = None;
+        for (key, value) in &table {
+            match value {
+                Value::Table(table) => images = Some(table),
+                _ => writeln!(shown, "{} = {}  # {}", key, value, source(key)).map_err(io::Error::other)?,
+            }
+        }
+        for key in ["tls_cert", "tls_key", "template_dir", "email"] {
+            if !table.contains_key(key) {
+                writeln!(shown, "# {} is not set", key).map_err(io::Error::other)?;
+            }
+        }
+        if let Some(images) = images.filter(|images| !images.is_empty()) {
+            shown.push_str("\n[images]\n");
+            for (service, reference) in images {
+                let origin = source(&format!("images.{}", service));
+                writeln!(shown, "{} = {}  # {}", service, reference, origin).map_err(io::Error::other)?;
+            }
+        }
+        Ok(shown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(content: &str) -> io::Result<Config> {
+        Config::default().merge(content.parse().unwrap(), FILE)
+    }
+
+    #[test]
+    fn file_overrides_defaults() {
+        let config = file("port = 9000\nlog_level = \"debug\"\n[images]\nmysql = \"mysql:8.4\"\n").unwrap();
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.log_level, LogLevel::Debug);
+        assert_eq!(config.address, "0.0.0.0");
+        assert_eq!(config.images["mysql"], "mysql:8.4");
+        let shown = config.show().unwrap();
+        assert!(shown.contains("port = 9000  # woors.toml\n"));
+        assert!(shown.contains("address = \"0.0.0.0\"  # default\n"));
+        assert!(shown.contains("mysql = \"mysql:8.4\"  # woors.toml\n"));
+    }
+
+    #[test]
+    fn file_rejects_unknown_settings() {
+        assert!(file("prot = 9000\n").is_err());
+        assert!(file("port = \"http\"\n").is_err());
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> BTreeMap<String, String> {
+        vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn environment_and_flags_override_the_file() {
+        let config = file("port = 9000\nlog_level = \"debug\"\n[images]\nmysql = \"mysql:8.4\"\n").unwrap();
+        let env = vars(&[
+            ("WOORS_PORT", "9100"),
+            ("WOORS_IMAGE_CODE_SERVER", "codercom/code-server:4.92.2"),
+            ("HOME", "/root"),
+        ]);
+        let mut config = config.apply_env(&env).unwrap();
+        assert_eq!(config.port, 9100);
+        assert_eq!(config.log_level, LogLevel::Debug);
+        assert_eq!(config.images["mysql"], "mysql:8.4");
+        assert_eq!(config.images["code-server"], "codercom/code-server:4.92.2");
+        config.address = "127.0.0.1".to_string();
+        config.set_flag("address", "--address");
+        let shown = config.show().unwrap();
+        assert!(shown.contains("address = \"127.0.0.1\"  # --address\n"));
+        assert!(shown.contains("port = 9100  # WOORS_PORT\n"));
+        assert!(shown.contains("log_level = \"debug\"  # woors.toml\n"));
+        assert!(shown.contains("code-server = \"codercom/code-server:4.92.2\"  # WOORS_IMAGE_CODE_SERVER\n"));
+        assert!(shown.contains("# tls_cert is not set\n"));
+    }
+
+    #[test]
+    fn environment_numbers_are_checked() {
+        let err = Config::default().apply_env(&vars(&[("WOORS_PORT", "http")])).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("WOORS_PORT"));
+    }
+
+    #[test]
+    fn tls_needs_both_files() {
+        let config = Config::default().apply_env(&vars(&[("WOORS_TLS_CERT", "Cargo.toml")])).unwrap();
+        assert!(config.validate().unwrap_err().to_string().contains("go together"));
+        let config = Config {
+            tls_key: Some(PathBuf::from("missing.pem")),
+            ..config
+        };
+        assert!(config.validate().unwrap_err().to_string().contains("`missing.pem` is not a file"));
+        let config = Config {
+            tls_key: Some(PathBuf::from("Cargo.toml")),
+            ..config
+        };
+        config.validate().unwrap();
+        assert_eq!(config.tls(), Some((Path::new("Cargo.toml"), Path::new("Cargo.toml"))));
+    }
+}
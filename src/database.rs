@@ -0,0 +1,194 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::compose::{
+    Command, ConfigFile, Environment, Healthcheck, Label, Mount, MountKind, NetworkAttachment, Policy, Resources,
+    Service,
+};
+use crate::image::catalog_image;
+use crate::resources::{format_size, GIB, MIB};
+use crate::secrets::Secrets;
+use crate::site::Site;
+use crate::wordpress;
+
+pub const PORT: u16 = 3306;
+
+/// Memory limit of the database container.
+const MEMORY_LIMIT: u64 = GIB;
+
+/// The database server of the stack.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, ValueEnum, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    #[default]
+    Mysql,
+    Mariadb,
+}
+
+impl Engine {
+    /// The service name, which is also the host name other services connect
+    /// to.
+    pub fn service(&self) -> &'static str {
+        match self {
+            Engine::Mysql => "mysql",
+            Engine::Mariadb => "mariadb",
+        }
+    }
+
+    /// `host:port` of the server.
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.service(), PORT)
+    }
+
+    /// The command line client shipped with the image.
+    pub fn client(&self) -> &'static str {
+        match self {
+            Engine::Mysql => "mysql",
+            Engine::Mariadb => "mariadb",
+        }
+    }
+
+    /// The dump tool shipped with the image.
+    pub fn dump(&self) -> &'static str {
+        match self {
+            Engine::Mysql => "mysqldump",
+            Engine::Mariadb => "mariadb-dump",
+        }
+    }
+
+    /// A shell command prefix running a tool as root inside the database
+    /// container, whether the password is passed directly or through a
+    /// `_FILE` variable.
+    pub fn as_root(&self) -> String {
+        let prefix = self.env_prefix();
+        format!(
+            "MYSQL_PWD=\"${{{0}_ROOT_PASSWORD:-$(cat \"${0}_ROOT_PASSWORD_FILE\")}}\"",
+            prefix
+        )
+    }
+
+    /// A shell expression for the site database inside the database
+    /// container.
+    pub fn database_variable(&self) -> String {
+        format!("\"${}_DATABASE\"", self.env_prefix())
+    }
+
+    /// Prefix of the environment variables of the official image.
+    fn env_prefix(&self) -> &'static str {
+        match self {
+            Engine::Mysql => "MYSQL",
+            Engine::Mariadb => "MARIADB",
+        }
+    }
+
+    fn healthcheck(&self) -> Vec<String> {
+        let test: &[&str] = match self {
+            Engine::Mysql => &["CMD", "mysqladmin", "ping", "-h", "localhost"],
+            Engine::Mariadb => &["CMD", "healthcheck.sh", "--connect", "--innodb_initialized"],
+        };
+        test.iter().map(|argument| argument.to_string()).collect()
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Engine::Mysql => "MySQL Server",
+            Engine::Mariadb => "MariaDB Server",
+        }
+    }
+}
+
+/// The root password of the database server, whatever its engine.
+pub fn root_password(secrets: &mut Secrets) -> String {
+    secrets.password("database.root_password")
+}
+
+/// The database server, with the database and user of the site.
+pub fn service(engine: Engine, site: &Site, secrets: &mut Secrets) -> Service {
+    let name = engine.service();
+    Service {
+        image: catalog_image(name),
+        container_name: name.to_string(),
+        ports: vec![],
+        volumes: vec![
+            Mount {
+                kind: MountKind::Volume,
+                source: name.to_string(),
+                target: "/var/lib/mysql".to_string(),
+                read_only: false
+            }
+        ],
+        networks: vec![
+            NetworkAttachment {
+                network: "backend".to_string(),
+                aliases: vec!["db".to_string()],
+                ipv4_address: None
+            }
+        ],
+        environments: environment(engine, site, secrets),
+        restart: Policy {
+            condition: "always".to_string()
+        },
+        command: Command {
+            value: "".to_string()
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: engine.description().to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(1.0),
+            memory: Some(MEMORY_LIMIT),
+            reservation_cpus: Some(0.25),
+            reservation_memory: Some(512 * MIB),
+            shm_size: None,
+            ulimits: vec![]
+        },
+        depends_on: vec![],
+        healthcheck: Some(Healthcheck {
+            test: engine.healthcheck(),
+            interval: "10s".to_string(),
+            timeout: "5s".to_string(),
+            retries: 5,
+            start_period: "30s".to_string()
+        }),
+        configs: vec![tuning(engine)],
+        ..Default::default()
+    }
+}
+
+/// The root password plus the database and user WordPress connects with.
+fn environment(engine: Engine, site: &Site, secrets: &mut Secrets) -> Vec<Environment> {
+    let prefix = engine.env_prefix();
+    let database = wordpress::database(site, secrets);
+    vec![
+        Environment::new(&format!("{}_ROOT_PASSWORD", prefix), &root_password(secrets)),
+        Environment::new(&format!("{}_DATABASE", prefix), &database.name),
+        Environment::new(&format!("{}_USER", prefix), &database.user),
+        Environment::new(&format!("{}_PASSWORD", prefix), &database.password),
+    ]
+}
+
+/// Server settings sized for the container memory limit and WordPress'
+/// `utf8mb4` tables.
+fn tuning(engine: Engine) -> ConfigFile {
+    let mut lines = vec![
+        "[mysqld]".to_string(),
+        format!("innodb_buffer_pool_size = {}", format_size(MEMORY_LIMIT / 2).to_uppercase()),
+        "max_connections = 100".to_string(),
+        "character-set-server = utf8mb4".to_string(),
+        "collation-server = utf8mb4_unicode_ci".to_string(),
+        "skip-name-resolve".to_string(),
+    ];
+    match engine {
+        Engine::Mysql => lines.push("host_cache_size = 0".to_string()),
+        Engine::Mariadb => lines.push("innodb_log_file_size = 128M".to_string()),
+    }
+    ConfigFile {
+        title: format!("{}_cnf", engine.service()),
+        target: "/etc/mysql/conf.d/woors.cnf".to_string(),
+        content: lines.join("\n") + "\n",
+    }
+}
@@ -0,0 +1,141 @@
+/// Lines kept around each change.
+const CONTEXT: usize = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// A line of the diff, with the positions of the texts before it.
+struct Line<'a> {
+    kind: Kind,
+    text: &'a str,
+    old: usize,
+    new: usize,
+}
+
+/// The lines of `old` and `new`, aligned on their longest common
+/// subsequence.
+fn align<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    // lengths[i][j]: longest common subsequence of old[i..] and new[j..].
+    let mut lengths = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::with_capacity(old.len().max(new.len()));
+    while i < old.len() || j < new.len() {
+        let (kind, text) = if i < old.len() && j < new.len() && old[i] == new[j] {
+            (Kind::Equal, old[i])
+        } else if i < old.len() && (j == new.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            (Kind::Delete, old[i])
+        } else {
+            (Kind::Insert, new[j])
+        };
+        lines.push(Line { kind, text, old: i, new: j });
+        match kind {
+            Kind::Equal => (i, j) = (i + 1, j + 1),
+            Kind::Delete => i += 1,
+            Kind::Insert => j += 1,
+        }
+    }
+    lines
+}
+
+/// A unified diff of `old` and `new`, empty when they are the same.
+pub fn unified(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let lines = align(&old, &new);
+
+    // Ranges of lines around the changes, merged when they touch.
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for (index, line) in lines.iter().enumerate() {
+        if line.kind == Kind::Equal {
+            continue;
+        }
+        let start = index.saturating_sub(CONTEXT);
+        let end = (index + CONTEXT + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let mut diff = format!("--- {}\n+++ {}\n", old_label, new_label);
+    for (start, end) in hunks {
+        let hunk = &lines[start..end];
+        let old_count = hunk.iter().filter(|line| line.kind != Kind::Insert).count();
+        let new_count = hunk.iter().filter(|line| line.kind != Kind::Delete).count();
+        // Empty ranges start at the line before them, as in `diff -u`.
+        let old_start = if old_count == 0 { hunk[0].old } else { hunk[0].old + 1 };
+        let new_start = if new_count == 0 { hunk[0].new } else { hunk[0].new + 1 };
+        diff.push_str(&format!("@@ -{},{} +{},{} @@\n", old_start, old_count, new_start, new_count));
+        for line in hunk {
+            let prefix = match line.kind {
+                Kind::Equal => ' ',
+                Kind::Delete => '-',
+                Kind::Insert => '+',
+            };
+            diff.push(prefix);
+            diff.push_str(line.text);
+            diff.push('\n');
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_texts_have_no_diff() {
+        assert_eq!(unified("a\nb\n", "a\nb\n", "old", "new"), "");
+        assert_eq!(unified("", "", "old", "new"), "");
+    }
+
+    #[test]
+    fn changes_keep_three_lines_of_context() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n";
+        assert_eq!(
+            unified(old, new, "a", "b"),
+            "--- a\n+++ b\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n"
+        );
+    }
+
+    #[test]
+    fn distant_changes_are_separate_hunks() {
+        let old: String = (1..=20).map(|line| format!("{}\n", line)).collect();
+        let new: String = (1..=20)
+            .map(|line| match line {
+                2 => "two\n".to_string(),
+                19 => "nineteen\n".to_string(),
+                line => format!("{}\n", line),
+            })
+            .collect();
+        let diff = unified(&old, &new, "a", "b");
+        assert_eq!(diff.matches("@@ -").count(), 2);
+        assert!(diff.contains("@@ -1,5 +1,5 @@\n 1\n-2\n+two\n"));
+        assert!(diff.contains("@@ -16,5 +16,5 @@\n"));
+    }
+
+    #[test]
+    fn empty_ranges_start_before_them() {
+        assert_eq!(unified("", "a\n", "a", "b"), "--- a\n+++ b\n@@ -0,0 +1,1 @@\n+a\n");
+        assert_eq!(unified("a\n", "", "a", "b"), "--- a\n+++ b\n@@ -1,1 +0,0 @@\n-a\n");
+    }
+}
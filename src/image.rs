@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+pub const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// A fully qualified image reference: `registry/repository:tag@digest`.
+/// Images built locally have no registry.
+#[derive(Clone, PartialEq, Default)]
+pub struct Image {
+    pub registry: String,
+    pub repository: String,
+    pub tag: String,
+    pub digest: Option<String>,
+}
+
+/// The known-good images of one woors release, keyed by catalog service.
+pub struct Release {
+    pub version: &'static str,
+    /// `(service, registry, repository, tag)`
+    pub images: &'static [(&'static str, &'static str, &'static str, &'static str)],
+}
+
+/// Every woors release, oldest first. A release never changes once
+/// published; bumping an image means adding a new release entry.
+pub const RELEASES: &[Release] = &[
+    Release {
+        version: "0.1.0",
+        images: &[
+            ("mysql", "docker.io", "library/mysql", "8.4.3"),
+            ("mariadb", "docker.io", "library/mariadb", "11.4.4"),
+            ("redis", "docker.io", "library/redis", "7.4.1"),
+            ("wordpress", "docker.io", "library/wordpress", "6.6.2-php8.2-apache"),
+            ("wpcli", "docker.io", "library/wordpress", "cli-2.11.0-php8.2"),
+            ("phpmyadmin", "docker.io", "library/phpmyadmin", "5.2.1"),
+            ("portainer", "docker.io", "portainer/portainer-ce", "2.21.4"),
+            ("cadvisor", "gcr.io", "cadvisor/cadvisor", "v0.49.1"),
+            ("certbot", "docker.io", "certbot/certbot", "v2.11.0"),
+            ("nginx", "docker.io", "library/nginx", "1.27.2"),
+            ("traefik", "docker.io", "library/traefik", "v3.2.0"),
+            ("gitlab", "docker.io", "gitlab/gitlab-ce", "17.5.1-ce.0"),
+            ("sonarqube", "docker.io", "library/sonarqube", "10.7.0-community"),
+            ("code-server", "docker.io", "codercom/code-server", "4.93.1"),
+            ("mailhog", "docker.io", "mailhog/mailhog", "v1.0.1"),
+            ("vault", "docker.io", "hashicorp/vault", "1.18.1"),
+            ("backup", "docker.io", "offen/docker-volume-backup", "v2.43.0"),
+            ("minio", "docker.io", "minio/minio", "RELEASE.2024-10-13T13-34-11Z"),
+            ("minio-init", "docker.io", "minio/mc", "RELEASE.2024-10-08T09-37-26Z"),
+            ("prometheus", "docker.io", "prom/prometheus", "v2.55.0"),
+            ("grafana", "docker.io", "grafana/grafana", "11.3.0"),
+            ("node-exporter", "docker.io", "prom/node-exporter", "v1.8.2"),
+            ("mysqld-exporter", "docker.io", "prom/mysqld-exporter", "v0.16.0"),
+            ("redis-exporter", "docker.io", "oliver006/redis_exporter", "v1.66.0"),
+            ("loki", "docker.io", "grafana/loki", "3.2.1"),
+            ("promtail", "docker.io", "grafana/promtail", "3.2.1"),
+            ("graphviz", "docker.io", "pmsipilot/docker-compose-viz", "1.3.0"),
+            // Built locally from the `Dockerfile`; not published to a registry.
+            ("woors", "", "woors", "0.1.0"),
+        ],
+    },
+];
+
+/// Images configured in place of those of the release, by catalog service.
+static OVERRIDES: OnceLock<BTreeMap<String, Image>> = OnceLock::new();
+
+impl Image {
+    /// Parses `[registry/]repository[:tag][@digest]`, expanding Docker Hub
+    /// shorthands such as `mysql` to `docker.io/library/mysql:latest`.
+    pub fn parse(reference: &str) -> Option<Image> {
+        let (name, digest) = match reference.split_once('@') {
+            Some((name, digest)) => (name, Some(digest.to_string())),
+            None => (reference, None),
+        };
+        let (name, tag) = match name.rfind(':') {
+            Some(index) if !name[index..].contains('/') => (&name[..index], &name[index + 1..]),
+            _ => (name, "latest"),
+        };
+        if name.is_empty() || tag.is_empty() {
+            return None;
+        }
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, rest)) if host.contains('.') || host.contains(':') || host == "localhost" => {
+                (host.to_string(), rest.to_string())
+            }
+            Some(_) => (DEFAULT_REGISTRY.to_string(), name.to_string()),
+            None => (DEFAULT_REGISTRY.to_string(), format!("library/{}", name)),
+        };
+        Some(Image {
+            registry,
+            repository,
+            tag: tag.to_string(),
+            digest,
+        })
+    }
+
+    /// Whether both references name the same `registry/repository:tag`,
+    /// regardless of digest.
+    pub fn same_tag(&self, other: &Image) -> bool {
+        self.registry == other.registry && self.repository == other.repository && self.tag == other.tag
+    }
+}
+
+impl fmt::Display for Image {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.registry.is_empty() {
+            write!(f, "{}/", self.registry)?;
+        }
+        write!(f, "{}:{}", self.repository, self.tag)?;
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+/// Looks up a release by version.
+pub fn release(version: &str) -> Option<&'static Release> {
+    RELEASES.iter().find(|release| release.version == version)
+}
+
+/// The release matching this build, or the newest one known.
+pub fn current_release() -> &'static Release {
+    release(env!("CARGO_PKG_VERSION")).unwrap_or_else(|| RELEASES.last().unwrap())
+}
+
+/// Replaces the release images of catalog services for the rest of the
+/// process. Only the first call has an effect.
+pub fn override_images(images: BTreeMap<String, Image>) {
+    let _ = OVERRIDES.set(images);
+}
+
+/// The known-good image of a catalog service in the current release, or
+/// the image configured in its place.
+///
+/// Panics when the service is missing from the release table, which is a
+/// bug in the catalog rather than a user error.
+pub fn catalog_image(service: &str) -> Image {
+    if let Some(image) = OVERRIDES.get().and_then(|images| images.get(service)) {
+        return image.clone();
+    }
+    let release = current_release();
+    let (_, registry, repository, tag) = release
+        .images
+        .iter()
+        .find(|(name, ..)| *name == service)
+        .unwrap_or_else(|| panic!("no image for `{}` in release {}", service, release.version));
+    Image {
+        registry: registry.to_string(),
+        repository: repository.to_string(),
+        tag: tag.to_string(),
+        digest: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Parts = Option<(String, String, String, Option<String>)>;
+
+    fn parts(reference: &str) -> Parts {
+        Image::parse(reference).map(|image| (image.registry, image.repository, image.tag, image.digest))
+    }
+
+    fn expected(registry: &str, repository: &str, tag: &str, digest: Option<&str>) -> Parts {
+        Some((registry.to_string(), repository.to_string(), tag.to_string(), digest.map(str::to_string)))
+    }
+
+    #[test]
+    fn parse_expands_docker_hub_shorthands() {
+        assert_eq!(parts("mysql"), expected("docker.io", "library/mysql", "latest", None));
+        assert_eq!(parts("mysql:8.0"), expected("docker.io", "library/mysql", "8.0", None));
+        assert_eq!(parts("grafana/grafana:11.2.0"), expected("docker.io", "grafana/grafana", "11.2.0", None));
+    }
+
+    #[test]
+    fn parse_keeps_registries() {
+        assert_eq!(parts("ghcr.io/org/app:1.2"), expected("ghcr.io", "org/app", "1.2", None));
+        assert_eq!(parts("localhost/app"), expected("localhost", "app", "latest", None));
+        // The port of the registry is not a tag.
+        assert_eq!(parts("registry:5000/app"), expected("registry:5000", "app", "latest", None));
+        assert_eq!(parts("registry:5000/app:2"), expected("registry:5000", "app", "2", None));
+    }
+
+    #[test]
+    fn parse_keeps_digests() {
+        let digest = "sha256:0123456789abcdef";
+        assert_eq!(
+            parts(&format!("redis:7.4@{}", digest)),
+            expected("docker.io", "library/redis", "7.4", Some(digest))
+        );
+        assert_eq!(
+            parts(&format!("redis@{}", digest)),
+            expected("docker.io", "library/redis", "latest", Some(digest))
+        );
+    }
+
+    #[test]
+    fn parse_rejects_empty_names_and_tags() {
+        assert!(Image::parse("").is_none());
+        assert!(Image::parse("mysql:").is_none());
+        assert!(Image::parse(":8.0").is_none());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for reference in ["docker.io/library/mysql:8.0", "ghcr.io/org/app:1.2@sha256:abc"] {
+            assert_eq!(Image::parse(reference).unwrap().to_string(), reference);
+        }
+    }
+}
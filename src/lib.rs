@@ -0,0 +1,27 @@
+pub mod app;
+pub mod backup;
+pub mod catalog;
+pub mod clone;
+pub mod compose;
+pub mod config;
+pub mod database;
+pub mod diff;
+pub mod image;
+pub mod lock;
+pub mod logging;
+pub mod monitoring;
+pub mod project;
+pub mod redis;
+pub mod resources;
+pub mod secrets;
+pub mod server;
+pub mod site;
+pub mod telemetry;
+pub mod template;
+pub mod token;
+pub mod traefik;
+pub mod vault;
+pub mod woocommerce;
+pub mod woors;
+pub mod wordpress;
+pub mod wpcli;
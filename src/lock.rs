@@ -0,0 +1,343 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+
+use serde::{Deserialize, Serialize};
+
+use crate::compose::Compose;
+use crate::image::Image;
+
+pub const LOCK_FILE: &str = "woors.lock";
+pub const LOCK_VERSION: u32 = 1;
+
+const LOCK_HEADER: &str = "# This file is automatically @generated by woors.\n\
+                           # Update it with `woors lock --update <service>`.\n";
+
+/// The exact image reference recorded for every service of a stack.
+#[derive(Serialize, Deserialize)]
+pub struct LockFile {
+    pub version: u32,
+    #[serde(default, rename = "image")]
+    pub images: Vec<LockedImage>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LockedImage {
+    pub service: String,
+    pub reference: String,
+}
+
+/// Image references with digests that `woors lock` resolves tags against.
+pub struct Manifest {
+    pub images: Vec<Image>,
+}
+
+impl LockFile {
+    pub fn new() -> LockFile {
+        LockFile {
+            version: LOCK_VERSION,
+            images: vec![],
+        }
+    }
+
+    pub fn read(path: &Path) -> io::Result<LockFile> {
+        let content = fs::read_to_string(path)?;
+        let lock: LockFile = toml::from_str(&content).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err))
+        })?;
+        if lock.version != LOCK_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: unsupported lock file version {}", path.display(), lock.version),
+            ));
+        }
+        Ok(lock)
+    }
+
+    /// Reads the lock file at `path`, or returns `None` if there is none yet.
+    pub fn read_optional(path: &Path) -> io::Result<Option<LockFile>> {
+        match LockFile::read(path) {
+            Ok(lock) => Ok(Some(lock)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let content = toml::to_string(self)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+        fs::write(path, format!("{}\n{}", LOCK_HEADER, content))
+    }
+
+    /// The locked image of `service`, if any.
+    pub fn image(&self, service: &str) -> io::Result<Option<Image>> {
+        let Some(locked) = self.images.iter().find(|locked| locked.service == service) else {
+            return Ok(None);
+        };
+        Image::parse(&locked.reference).map(Some).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid image reference `{}` for `{}`", locked.reference, service),
+            )
+        })
+    }
+
+    /// Records `image` for `service`, replacing any previous entry.
+    pub fn set(&mut self, service: &str, image: &Image) {
+        let reference = image.to_string();
+        match self.images.iter_mut().find(|locked| locked.service == service) {
+            Some(locked) => locked.reference = reference,
+            None => self.images.push(LockedImage {
+                service: service.to_string(),
+                reference,
+            }),
+        }
+    }
+}
+
+impl Default for LockFile {
+    fn default() -> Self {
+        LockFile::new()
+    }
+}
+
+impl Manifest {
+    /// Reads a manifest file: one `repository:tag@digest` reference per
+    /// line, as printed by
+    /// `docker images --digests --format '{{.Repository}}:{{.Tag}}@{{.Digest}}'`.
+    /// Blank lines and `#` comments are ignored.
+    pub fn read(path: &Path) -> io::Result<Manifest> {
+        let content = fs::read_to_string(path)?;
+        let mut images = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match Image::parse(line) {
+                Some(image) if image.digest.is_some() => images.push(image),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}:{}: expected `repository:tag@digest`", path.display(), number + 1),
+                    ))
+                }
+            }
+        }
+        Ok(Manifest { images })
+    }
+
+    /// Asks the local Docker daemon for the digests of the given images,
+    /// which must have been pulled already. Images that are not present
+    /// locally are left out.
+    pub fn from_docker(images: &[&Image]) -> io::Result<Manifest> {
+        let mut resolved = Vec::new();
+        for image in images {
+            let tagged = Image {
+                digest: None,
+                ..(*image).clone()
+            };
+            let output = process::Command::new("docker")
+                .args(["image", "inspect", "--format", "{{json .RepoDigests}}"])
+                .arg(tagged.to_string())
+                .output()?;
+            if !output.status.success() {
+                continue;
+            }
+            let digests: Vec<String> = serde_json::from_slice(&output.stdout).unwrap_or_default();
+            let digest = digests
+                .iter()
+                .filter_map(|reference| Image::parse(reference))
+                .find(|candidate| {
+                    candidate.registry == tagged.registry && candidate.repository == tagged.repository
+                })
+                .and_then(|candidate| candidate.digest);
+            if let Some(digest) = digest {
+                resolved.push(Image {
+                    digest: Some(digest),
+                    ..tagged
+                });
+            }
+        }
+        Ok(Manifest { images: resolved })
+    }
+
+    /// The manifest entry with the same tag as `image`.
+    pub fn resolve(&self, image: &Image) -> Option<&Image> {
+        self.images.iter().find(|candidate| candidate.same_tag(image))
+    }
+}
+
+/// Replaces the image of every locked service with its locked reference.
+/// Services missing from the lock file keep their catalog image.
+pub fn apply_lock(compose: &mut Compose, lock: &LockFile) -> io::Result<()> {
+    for service in compose.services.iter_mut() {
+        if let Some(locked) = lock.image(&service.container_name)? {
+            service.image = locked;
+        }
+    }
+    Ok(())
+}
+
+/// Applies the lock file and requires a digest on every pulled service
+/// image: a partially pinned stack is not reproducible.
+pub fn pin_digests(compose: &mut Compose, lock: &LockFile) -> io::Result<()> {
+    apply_lock(compose, lock)?;
+    let missing: Vec<&str> = compose
+        .services
+        .iter()
+        .filter(|service| service.build.is_none() && service.image.digest.is_none())
+        .map(|service| service.container_name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no locked digest for: {}", missing.join(", ")),
+        ));
+    }
+    Ok(())
+}
+
+/// Locks every service of `compose` that is not locked yet, plus the
+/// services listed in `update`, using the digests found in `manifest`.
+///
+/// Returns the services whose entry changed.
+pub fn lock_services(
+    compose: &Compose,
+    lock: &mut LockFile,
+    manifest: &Manifest,
+    update: &[String],
+) -> io::Result<Vec<String>> {
+    for service in update {
+        if !compose.services.iter().any(|candidate| &candidate.container_name == service) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no service named `{}` in the stack", service),
+            ));
+        }
+    }
+
+    let mut changed = Vec::new();
+    let mut unresolved = Vec::new();
+    // Images built by compose have no registry digest to lock.
+    for service in compose.services.iter().filter(|service| service.build.is_none()) {
+        let name = &service.container_name;
+        if lock.image(name)?.is_some() && !update.contains(name) {
+            continue;
+        }
+        match manifest.resolve(&service.image) {
+            Some(image) => {
+                if lock.image(name)?.as_ref() != Some(image) {
+                    lock.set(name, image);
+                    changed.push(name.clone());
+                }
+            }
+            None => unresolved.push(format!("{} ({})", name, service.image)),
+        }
+    }
+    if !unresolved.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no digest found for: {}", unresolved.join(", ")),
+        ));
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compose::{Build, Service};
+
+    const DIGEST: &str = "sha256:1111111111111111111111111111111111111111111111111111111111111111";
+    const NEW_DIGEST: &str = "sha256:2222222222222222222222222222222222222222222222222222222222222222";
+
+    fn service(name: &str, image: &str) -> Service {
+        Service {
+            image: Image::parse(image).unwrap(),
+            container_name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn compose(services: Vec<Service>) -> Compose {
+        Compose {
+            version: Some("3.8".to_string()),
+            services,
+            volumes: vec![],
+            networks: vec![],
+            configs: vec![],
+            environments: vec![],
+            extensions: vec![],
+        }
+    }
+
+    fn built(name: &str) -> Service {
+        Service {
+            build: Some(Build {
+                context: ".".to_string(),
+            }),
+            ..service(name, name)
+        }
+    }
+
+    fn manifest(references: &[String]) -> Manifest {
+        Manifest {
+            images: references.iter().map(|reference| Image::parse(reference).unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn lock_services_records_digests() {
+        let stack = compose(vec![service("mysql", "mysql:8.0"), built("app")]);
+        let mut lock = LockFile::new();
+        let manifest = manifest(&[format!("mysql:8.0@{}", DIGEST)]);
+        assert_eq!(lock_services(&stack, &mut lock, &manifest, &[]).unwrap(), vec!["mysql".to_string()]);
+        assert_eq!(lock.images.len(), 1);
+        assert_eq!(lock.images[0].reference, format!("docker.io/library/mysql:8.0@{}", DIGEST));
+    }
+
+    #[test]
+    fn lock_services_keeps_locked_entries_unless_updated() {
+        let stack = compose(vec![service("mysql", "mysql:8.0")]);
+        let mut lock = LockFile::new();
+        lock_services(&stack, &mut lock, &manifest(&[format!("mysql:8.0@{}", DIGEST)]), &[]).unwrap();
+        let newer = manifest(&[format!("mysql:8.0@{}", NEW_DIGEST)]);
+        assert!(lock_services(&stack, &mut lock, &newer, &[]).unwrap().is_empty());
+        assert!(lock.images[0].reference.ends_with(DIGEST));
+        let update = ["mysql".to_string()];
+        assert_eq!(lock_services(&stack, &mut lock, &newer, &update).unwrap(), update.to_vec());
+        assert!(lock.images[0].reference.ends_with(NEW_DIGEST));
+    }
+
+    #[test]
+    fn lock_services_fails_on_unresolved_and_unknown_services() {
+        let stack = compose(vec![service("mysql", "mysql:8.0"), service("redis", "redis:7.4")]);
+        let manifest = manifest(&[format!("mysql:8.0@{}", DIGEST)]);
+        let err = lock_services(&stack, &mut LockFile::new(), &manifest, &[]).unwrap_err();
+        assert!(err.to_string().contains("redis"));
+        let err = lock_services(&stack, &mut LockFile::new(), &manifest, &["nginx".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("nginx"));
+    }
+
+    #[test]
+    fn pin_digests_applies_the_lock() {
+        let mut lock = LockFile::new();
+        lock.set("mysql", &Image::parse(&format!("mysql:8.0@{}", DIGEST)).unwrap());
+        let mut stack = compose(vec![service("mysql", "mysql:8.0"), built("app")]);
+        pin_digests(&mut stack, &lock).unwrap();
+        assert_eq!(stack.services[0].image.digest.as_deref(), Some(DIGEST));
+        assert!(stack.services[1].image.digest.is_none());
+    }
+
+    #[test]
+    fn pin_digests_requires_every_pulled_image() {
+        let mut stack = compose(vec![service("mysql", "mysql:8.0"), service("redis", "redis:7.4")]);
+        let mut lock = LockFile::new();
+        lock.set("mysql", &Image::parse(&format!("mysql:8.0@{}", DIGEST)).unwrap());
+        let err = pin_digests(&mut stack, &lock).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().ends_with(": redis"));
+    }
+}
@@ -0,0 +1,283 @@
+use std::io;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::compose::{
+    Command, ConfigFile, Dependency, Healthcheck, Label, Logging, Mount, MountKind, NetworkAttachment, Policy, Port,
+    Resources, Service,
+};
+use crate::image::catalog_image;
+use crate::resources::MIB;
+use crate::site::Profile;
+
+/// Drivers keeping the logs in rotated files on the host.
+const ROTATED_DRIVERS: [&str; 2] = ["json-file", "local"];
+
+/// Driver of the services. Logs stay readable with `docker logs` and by
+/// Promtail.
+pub const DRIVER: &str = "json-file";
+
+/// Overrides of the per-profile logging defaults.
+#[derive(Clone, Default, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct LoggingOptions {
+    pub driver: Option<String>,
+    /// Size of a log file before rotation, in bytes.
+    pub max_size: Option<u64>,
+    pub max_file: Option<u32>,
+}
+
+impl LoggingOptions {
+    /// The logging of every service: rotated `json-file` logs, kept longer
+    /// in production, unless overridden. Only the drivers writing files
+    /// rotate them; Docker refuses `max-size` and `max-file` for the others.
+    pub fn resolve(&self, profile: Profile) -> io::Result<Logging> {
+        let driver = self.driver.clone().unwrap_or_else(|| DRIVER.to_string());
+        if !ROTATED_DRIVERS.contains(&driver.as_str()) {
+            if self.max_size.is_some() || self.max_file.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("the `{}` log driver does not rotate files; drop the log max size and file count", driver),
+                ));
+            }
+            return Ok(Logging {
+                driver,
+                max_size: None,
+                max_file: None,
+            });
+        }
+        let (max_size, max_file) = match profile {
+            Profile::Dev => (10 * MIB, 3),
+            Profile::Prod => (50 * MIB, 5),
+        };
+        Ok(Logging {
+            driver,
+            max_size: Some(self.max_size.unwrap_or(max_size)),
+            max_file: Some(self.max_file.unwrap_or(max_file)),
+        })
+    }
+}
+
+/// Sets `logging` on the services without their own.
+pub fn apply(services: &mut [Service], logging: &Logging) {
+    for service in services.iter_mut().filter(|service| service.logging.is_none()) {
+        service.logging = Some(logging.clone());
+    }
+}
+
+/// Adds Loki and a Promtail shipping the logs of the stack containers to it,
+/// and a Loki datasource to Grafana when it is there.
+pub fn wire(services: &mut Vec<Service>) {
+    let mut containers: Vec<String> = services.iter().map(|service| service.container_name.clone()).collect();
+    containers.extend(["loki".to_string(), "promtail".to_string()]);
+
+    if let Some(grafana) = services.iter_mut().find(|service| service.container_name == "grafana") {
+        if let Some(datasources) = grafana.configs.iter_mut().find(|file| file.title == "grafana_datasources") {
+            datasources.content.push_str(&grafana_datasource());
+        }
+    }
+    services.push(loki());
+    services.push(promtail(&containers));
+}
+
+/// A single-process Loki on the filesystem, keeping logs for a week.
+fn loki_config() -> ConfigFile {
+    let content = [
+        "auth_enabled: false",
+        "server:",
+        "  http_listen_port: 3100",
+        "common:",
+        "  path_prefix: /loki",
+        "  replication_factor: 1",
+        "  storage:",
+        "    filesystem:",
+        "      chunks_directory: /loki/chunks",
+        "      rules_directory: /loki/rules",
+        "  ring:",
+        "    kvstore:",
+        "      store: inmemory",
+        "schema_config:",
+        "  configs:",
+        "    - from: 2024-01-01",
+        "      store: tsdb",
+        "      object_store: filesystem",
+        "      schema: v13",
+        "      index:",
+        "        prefix: index_",
+        "        period: 24h",
+        "compactor:",
+        "  working_directory: /loki/compactor",
+        "  retention_enabled: true",
+        "  delete_request_store: filesystem",
+        "limits_config:",
+        "  retention_period: 168h",
+    ];
+    ConfigFile {
+        title: "loki_yaml".to_string(),
+        target: "/etc/loki/loki.yaml".to_string(),
+        content: content.join("\n") + "\n",
+    }
+}
+
+/// Discovers containers through the Docker API and only keeps those of
+/// `containers`, labelled with their container and compose service names.
+fn promtail_config(containers: &[String]) -> ConfigFile {
+    let content = [
+        "server:".to_string(),
+        "  http_listen_port: 9080".to_string(),
+        "  grpc_listen_port: 0".to_string(),
+        "positions:".to_string(),
+        "  filename: /var/lib/promtail/positions.yaml".to_string(),
+        "clients:".to_string(),
+        "  - url: http://loki:3100/loki/api/v1/push".to_string(),
+        "scrape_configs:".to_string(),
+        "  - job_name: docker".to_string(),
+        "    docker_sd_configs:".to_string(),
+        "      - host: unix:///var/run/docker.sock".to_string(),
+        "        refresh_interval: 5s".to_string(),
+        "    relabel_configs:".to_string(),
+        "      - source_labels: [\"__meta_docker_container_name\"]".to_string(),
+        format!("        regex: \"/({})\"", containers.join("|")),
+        "        action: keep".to_string(),
+        "      - source_labels: [\"__meta_docker_container_name\"]".to_string(),
+        "        regex: \"/(.*)\"".to_string(),
+        "        target_label: container".to_string(),
+        "      - source_labels: [\"__meta_docker_container_label_com_docker_compose_service\"]".to_string(),
+        "        target_label: service".to_string(),
+        "      - source_labels: [\"__meta_docker_container_log_stream\"]".to_string(),
+        "        target_label: stream".to_string(),
+    ];
+    ConfigFile {
+        title: "promtail_yaml".to_string(),
+        target: "/etc/promtail/promtail.yaml".to_string(),
+        content: content.join("\n") + "\n",
+    }
+}
+
+/// Appended to the datasources of the `monitoring` preset.
+fn grafana_datasource() -> String {
+    let content = [
+        "  - name: Loki",
+        "    type: loki",
+        "    uid: loki",
+        "    access: proxy",
+        "    url: http://loki:3100",
+    ];
+    content.join("\n") + "\n"
+}
+
+fn loki() -> Service {
+    Service {
+        image: catalog_image("loki"),
+        container_name: "loki".to_string(),
+        ports: vec![
+            Port {
+                host: "3100".to_string(),
+                container: "3100".to_string()
+            }
+        ],
+        volumes: vec![
+            Mount {
+                kind: MountKind::Volume,
+                source: "loki".to_string(),
+                target: "/loki".to_string(),
+                read_only: false
+            }
+        ],
+        networks: vec![
+            NetworkAttachment {
+                network: "web".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        environments: vec![],
+        restart: Policy {
+            condition: "always".to_string()
+        },
+        command: Command {
+            value: "-config.file=/etc/loki/loki.yaml".to_string()
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "Loki Server".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.5),
+            memory: Some(512 * MIB),
+            reservation_memory: Some(128 * MIB),
+            ..Default::default()
+        },
+        healthcheck: Some(Healthcheck {
+            test: vec![
+                "CMD-SHELL".to_string(),
+                "wget -q -O /dev/null http://localhost:3100/ready || exit 1".to_string()
+            ],
+            interval: "10s".to_string(),
+            timeout: "5s".to_string(),
+            retries: 10,
+            start_period: "20s".to_string()
+        }),
+        configs: vec![loki_config()],
+        ..Default::default()
+    }
+}
+
+fn promtail(containers: &[String]) -> Service {
+    Service {
+        image: catalog_image("promtail"),
+        container_name: "promtail".to_string(),
+        ports: vec![],
+        volumes: vec![
+            Mount {
+                kind: MountKind::Bind,
+                source: "/var/run/docker.sock".to_string(),
+                target: "/var/run/docker.sock".to_string(),
+                read_only: true
+            },
+            Mount {
+                kind: MountKind::Volume,
+                source: "promtail".to_string(),
+                target: "/var/lib/promtail".to_string(),
+                read_only: false
+            }
+        ],
+        networks: vec![
+            NetworkAttachment {
+                network: "web".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        environments: vec![],
+        restart: Policy {
+            condition: "always".to_string()
+        },
+        command: Command {
+            value: "-config.file=/etc/promtail/promtail.yaml".to_string()
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "Promtail Log Shipper".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.25),
+            memory: Some(128 * MIB),
+            reservation_memory: Some(32 * MIB),
+            ..Default::default()
+        },
+        depends_on: vec![
+            Dependency {
+                service: "loki".to_string(),
+                condition: Some("service_healthy".to_string())
+            }
+        ],
+        configs: vec![promtail_config(containers)],
+        ..Default::default()
+    }
+}
@@ -1,1060 +1,614 @@
-use actix_web::{web, App, HttpServer};
+use std::path::{Path, PathBuf};
 
-pub struct Compose {
-    pub version: Option<String>,
-    pub services: Vec<Service>,
-    pub volumes: Vec<Volume>,
-    pub networks: Vec<Network>,
-    pub environments: Vec<Environment>,
-    pub extensions: Vec<Extension>,
+use clap::{Args, Parser, Subcommand};
+
+use api::app;
+use api::backup::{self, BackupOptions, Target};
+use api::catalog::{self, Preset, StackOptions};
+use api::clone;
+use api::compose::parse_compose;
+use api::config::{Config, LogLevel};
+use api::database::Engine;
+use api::lock::{apply_lock, lock_services, pin_digests, LockFile, Manifest, LOCK_FILE};
+use api::logging::{self, LoggingOptions};
+use api::project::Store;
+use api::resources::{parse_size, Budget};
+use api::secrets::Secrets;
+use api::server;
+use api::site::{Profile, Site};
+use api::token::{Role, Tokens};
+use api::woocommerce::Shop;
+
+/// The compose file `backup` and `restore` operate on by default.
+const COMPOSE_FILE: &str = "docker-compose.yml";
+
+#[derive(Parser)]
+#[command(name = "woors", version, about = "Generates docker-compose stacks for WordPress sites")]
+struct Cli {
+    /// Configuration file; defaults to `$WOORS_CONFIG`, or else `woors.toml` when
+    /// there is one
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Directory of the project store and its key
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+    #[arg(long, global = true, value_enum)]
+    log_level: Option<LogLevel>,
+    #[command(subcommand)]
+    command: Option<Commands>,
 }
 
-pub struct Service {
-    pub image: String,
-    pub container_name: String,
-    pub ports: Vec<Port>,
-    pub volumes: Vec<Volume>,
-    pub networks: Vec<Network>,
-    pub depends_on: Vec<Dependency>,
-    pub restart: Policy,
-    pub environments: Vec<Environment>,
-    pub command: Command,
-    pub labels: Vec<Label>,
+#[derive(Subcommand)]
+enum Commands {
+    /// Run the HTTP API (default)
+    Serve(ServeArgs),
+    /// Print a docker-compose file to stdout
+    DockerCompose {
+        #[command(flatten)]
+        stack: StackArgs,
+        /// Fail if the selected services reserve more memory than this, e.g. `4G`
+        #[arg(long, value_parser = parse_memory)]
+        host_memory: Option<u64>,
+        /// Pin every image to the digest recorded in the lock file
+        #[arg(long)]
+        pin_digests: bool,
+        #[arg(long, default_value = LOCK_FILE)]
+        lock_file: PathBuf,
+    },
+    /// Record the exact image reference of every service in the lock file
+    Lock {
+        #[command(flatten)]
+        stack: StackArgs,
+        /// Resolve digests from this manifest instead of the local Docker images
+        #[arg(long)]
+        manifest: Option<PathBuf>,
+        /// Re-resolve an already locked service
+        #[arg(long, value_name = "SERVICE")]
+        update: Vec<String>,
+        #[arg(long, default_value = LOCK_FILE)]
+        lock_file: PathBuf,
+    },
+    /// Archive a running stack now, outside of the backup schedule
+    Backup {
+        /// Compose file of the stack, with a `backup` service
+        #[arg(long, short, default_value = COMPOSE_FILE)]
+        file: PathBuf,
+    },
+    /// Restore a backup archive into a stack
+    Restore {
+        /// Defaults to the newest archive of `--directory`
+        archive: Option<PathBuf>,
+        /// Compose file of the stack, with a `backup` service
+        #[arg(long, short, default_value = COMPOSE_FILE)]
+        file: PathBuf,
+        /// Local backup directory to take the newest archive from
+        #[arg(long, default_value = backup::DIRECTORY)]
+        directory: PathBuf,
+    },
+    /// Copy a stack to a new site, e.g. production to staging
+    Clone(CloneArgs),
+    /// Scaffold a native client of the webshop
+    App {
+        #[command(flatten)]
+        site: SiteArgs,
+        #[arg(long, default_value = app::DIRECTORY)]
+        output: PathBuf,
+        /// Overwrite the files of a non-empty output directory
+        #[arg(long)]
+        force: bool,
+    },
+    /// Manage the stack projects saved in the project store
+    Project {
+        /// Project store, instead of `woors.db` in the data directory; its key is
+        /// kept next to it unless `WOORS_SECRET_KEY` is set
+        #[arg(long, global = true)]
+        store: Option<PathBuf>,
+        #[command(subcommand)]
+        command: ProjectCommand,
+    },
+    /// Manage the API tokens of `woors serve`
+    Token {
+        /// Project store the tokens are kept in, instead of `woors.db` in the data
+        /// directory
+        #[arg(long, global = true)]
+        store: Option<PathBuf>,
+        #[command(subcommand)]
+        command: TokenCommand,
+    },
+    /// Describe the services stacks can select
+    Catalog {
+        /// Profile whose ports, volumes and variables are described; defaults
+        /// to the `profile` setting
+        #[arg(long, global = true, value_enum)]
+        site_profile: Option<Profile>,
+        #[command(subcommand)]
+        command: CatalogCommand,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
 }
 
-pub struct Port {
-    pub host: String,
-    pub container: String
+/// Overrides of the server settings.
+#[derive(Args, Default)]
+struct ServeArgs {
+    /// Address to bind
+    #[arg(long)]
+    address: Option<String>,
+    #[arg(long)]
+    port: Option<u16>,
+    /// PEM certificate chain to serve HTTPS with
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key of the certificate
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
 }
 
-pub struct Dependency {
-    pub service: String
+impl ServeArgs {
+    fn apply(&self, config: &mut Config) {
+        if let Some(address) = &self.address {
+            config.address = address.clone();
+            config.set_flag("address", "--address");
+        }
+        if let Some(port) = self.port {
+            config.port = port;
+            config.set_flag("port", "--port");
+        }
+        if let Some(tls_cert) = &self.tls_cert {
+            config.tls_cert = Some(tls_cert.clone());
+            config.set_flag("tls_cert", "--tls-cert");
+        }
+        if let Some(tls_key) = &self.tls_key {
+            config.tls_key = Some(tls_key.clone());
+            config.set_flag("tls_key", "--tls-key");
+        }
+    }
 }
 
-pub struct Policy {
-    pub condition: String
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the effective settings and where each comes from
+    Show(ServeArgs),
 }
 
-pub struct Command {
-    pub value: String
+#[derive(Subcommand)]
+enum TokenCommand {
+    /// List the tokens
+    List,
+    /// Create a token and print it; it cannot be shown again
+    Create {
+        name: String,
+        #[arg(long, value_enum, default_value_t = Role::ReadOnly)]
+        role: Role,
+    },
+    /// Revoke a token; its bearer is refused from the next request on
+    Revoke { name: String },
 }
 
-pub struct Label {
-    pub key: String,
-    pub value: String
+#[derive(Subcommand)]
+enum CatalogCommand {
+    /// List the services with their profiles, origin and description
+    List,
+    /// Print a service as JSON: image, ports, volumes, variables, dependencies,
+    /// profiles and resources
+    Show { service: String },
 }
 
-pub struct Volume {
-    pub title: String,
-    pub driver: String
+#[derive(Subcommand)]
+enum ProjectCommand {
+    /// List the projects
+    List,
+    /// Save a stack definition as a new project and generate its secrets
+    Create {
+        name: String,
+        #[command(flatten)]
+        stack: Box<StackArgs>,
+    },
+    /// Print a project as JSON, without its secrets
+    Show { name: String },
+    /// Print the docker-compose file of a project, with its saved secrets, and
+    /// record it as a revision
+    Render {
+        name: String,
+        /// Render this revision again instead, without recording it
+        #[arg(long)]
+        revision: Option<u32>,
+    },
+    /// List the revisions of a project
+    Revisions { name: String },
+    /// Print a unified diff of the compose files of two revisions
+    Diff {
+        name: String,
+        from: u32,
+        /// Defaults to the latest revision
+        to: Option<u32>,
+    },
+    /// Make a revision the project definition again, as a new revision
+    Rollback { name: String, revision: u32 },
+    /// Delete a project and its secrets
+    Delete { name: String },
 }
 
-pub struct Network {
-    pub driver: String,
-    pub external: bool
+/// Describes the site.
+#[derive(Args)]
+struct SiteArgs {
+    #[arg(long, default_value = "woors")]
+    site_title: String,
+    /// Host name of the site, e.g. `shop.com`
+    #[arg(long, default_value = "example.com")]
+    site_url: String,
+    /// Defaults to the `profile` setting, `dev` unless configured
+    #[arg(long, value_enum)]
+    site_profile: Option<Profile>,
 }
 
-pub struct Environment {
-    pub title: String,
-    pub value: String
+impl SiteArgs {
+    fn site(self, config: &Config) -> Site {
+        Site {
+            title: self.site_title,
+            url: self.site_url,
+            profile: self.site_profile.unwrap_or(config.profile),
+        }
+    }
 }
 
-pub struct Extension {
-    pub title: String,
-    pub value: String
+/// Describes the stack to generate.
+#[derive(Args)]
+struct StackArgs {
+    #[command(flatten)]
+    site: SiteArgs,
+    /// Database engine of the stack
+    #[arg(long, value_enum, default_value_t = Engine::Mysql)]
+    database: Engine,
+    /// Catalog services to include (with their dependencies); defaults to all but vault, backup,
+    /// woors and traefik
+    #[arg(long, value_delimiter = ',', value_name = "SERVICE")]
+    services: Vec<String>,
+    /// Ready-made additions, e.g. `woocommerce`
+    #[arg(long = "preset", value_enum, value_delimiter = ',')]
+    presets: Vec<Preset>,
+    /// WooCommerce store country, e.g. `BE` or `US:CA`
+    #[arg(long, default_value = "US")]
+    store_country: String,
+    /// WooCommerce store currency, e.g. `EUR`
+    #[arg(long, default_value = "USD")]
+    store_currency: String,
+    /// Extra WordPress plugins installed by the `woocommerce` preset
+    #[arg(long, value_delimiter = ',', value_name = "PLUGIN")]
+    plugins: Vec<String>,
+    /// WordPress themes installed by the `woocommerce` preset; the first is activated
+    #[arg(long, value_delimiter = ',', value_name = "THEME")]
+    themes: Vec<String>,
+    /// E-mail of the generated WordPress admin; defaults to the `email` setting,
+    /// or else `admin@<site url>`
+    #[arg(long)]
+    admin_email: Option<String>,
+    /// Store the generated secrets in Vault and have services read them from files
+    #[arg(long)]
+    vault_secrets: bool,
+    /// Cron expression of the `backup` service
+    #[arg(long, default_value = backup::SCHEDULE)]
+    backup_schedule: String,
+    /// Days archives are kept before being pruned
+    #[arg(long, default_value_t = 7)]
+    backup_retention_days: u32,
+    /// Where archives go: a local directory, `s3://bucket[/path]` or `minio`
+    #[arg(long, default_value = backup::DIRECTORY)]
+    backup_target: Target,
+    /// `host[:port]` of an S3-compatible server; defaults to AWS
+    #[arg(long)]
+    backup_s3_endpoint: Option<String>,
+    #[arg(long)]
+    backup_s3_access_key: Option<String>,
+    #[arg(long)]
+    backup_s3_secret_key: Option<String>,
+    /// Log driver of the services
+    #[arg(long, default_value = logging::DRIVER)]
+    log_driver: String,
+    /// Size of a log file before rotation, e.g. `10M`; defaults to 10M in dev and 50M in prod.
+    /// Only for the `json-file` and `local` drivers
+    #[arg(long, value_parser = parse_memory)]
+    log_max_size: Option<u64>,
+    /// Number of rotated log files kept; defaults to 3 in dev and 5 in prod. Only for the
+    /// `json-file` and `local` drivers
+    #[arg(long)]
+    log_max_file: Option<u32>,
 }
 
+impl StackArgs {
+    fn options(self, config: &Config) -> StackOptions {
+        StackOptions {
+            services: self.services,
+            site: self.site.site(config),
+            database: self.database,
+            presets: self.presets,
+            shop: Shop {
+                country: self.store_country,
+                currency: self.store_currency,
+                plugins: self.plugins,
+                themes: self.themes,
+                admin_email: self.admin_email.or_else(|| config.email.clone()),
+            },
+            vault_secrets: self.vault_secrets,
+            backup: BackupOptions {
+                schedule: self.backup_schedule,
+                retention_days: self.backup_retention_days,
+                target: self.backup_target,
+                s3_endpoint: self.backup_s3_endpoint,
+                s3_access_key: self.backup_s3_access_key,
+                s3_secret_key: self.backup_s3_secret_key,
+            },
+            replace_url: None,
+            logging: LoggingOptions {
+                driver: Some(self.log_driver),
+                max_size: self.log_max_size,
+                max_file: self.log_max_file,
+            },
+        }
+    }
+}
 
-pub fn parse_compose(compose: Compose) -> String {
-    let mut compose_str = String::new();
-    compose_str.push_str("version: ");
-    compose_str.push_str(&compose.version.unwrap());
-    compose_str.push_str("\n");
+fn parse_memory(value: &str) -> Result<u64, String> {
+    parse_size(value).ok_or_else(|| format!("invalid memory size `{}`", value))
+}
 
-    if compose.services.len() > 0 {
-        compose_str.push_str("\n");
-        compose_str.push_str("\n");
-        compose_str.push_str("services:\n");
-        for service in compose.services {
-            compose_str.push_str("\n");
-            compose_str.push_str("  ");
-            compose_str.push_str(&service.container_name);
-            compose_str.push_str(":\n");
-            compose_str.push_str("    image: ");
-            compose_str.push_str(&service.image);
-            compose_str.push_str("\n");
-            compose_str.push_str("    container_name: ");
-            compose_str.push_str(&service.container_name);
-            compose_str.push_str("\n");
 
-            if service.ports.len() > 0 {
-                compose_str.push_str("\n");
-                compose_str.push_str("    ports:\n");
-                for port in service.ports {
-                    compose_str.push_str("      - ");
-                    compose_str.push_str(&port.host);
-                    compose_str.push_str(":");
-                    compose_str.push_str(&port.container);
-                    compose_str.push_str("\n");
-                }
-            }
+fn docker_compose(
+    config: &Config,
+    stack: StackArgs,
+    host_memory: Option<u64>,
+    pin: bool,
+    lock_file: PathBuf,
+) -> std::io::Result<()> {
+    let mut compose = catalog::stack(&stack.options(config), &mut Secrets::default())?;
+    if let Some(host_memory) = host_memory {
+        let budget = Budget::new(&compose, host_memory);
+        if let Some(warning) = budget.warning() {
+            eprintln!("warning: {}", warning);
+        }
+        budget.check()?;
+    }
+    if pin {
+        let lock = LockFile::read(&lock_file)?;
+        pin_digests(&mut compose, &lock)?;
+    } else if let Some(lock) = LockFile::read_optional(&lock_file)? {
+        apply_lock(&mut compose, &lock)?;
+    }
+    print!("{}", parse_compose(&compose));
+    Ok(())
+}
 
-        
-            if service.volumes.len() > 0 {
-                compose_str.push_str("\n");
-                compose_str.push_str("    volumes:\n");
-                for volume in service.volumes {
-                    compose_str.push_str("      - ");
-                    compose_str.push_str(&volume.title);
-                    compose_str.push_str(":");
-                    compose_str.push_str(&volume.driver);
-                    compose_str.push_str("\n");
-                }
-            }
+fn lock(
+    config: &Config,
+    stack: StackArgs,
+    manifest: Option<PathBuf>,
+    update: Vec<String>,
+    lock_file: PathBuf,
+) -> std::io::Result<()> {
+    let compose = catalog::stack(&stack.options(config), &mut Secrets::default())?;
+    let mut lock = LockFile::read_optional(&lock_file)?.unwrap_or_default();
+    let manifest = match manifest {
+        Some(path) => Manifest::read(&path)?,
+        None => {
+            let images: Vec<_> = compose
+                .services
+                .iter()
+                .filter(|service| service.build.is_none())
+                .map(|service| &service.image)
+                .collect();
+            Manifest::from_docker(&images)?
+        }
+    };
+    let changed = lock_services(&compose, &mut lock, &manifest, &update)?;
+    lock.write(&lock_file)?;
+    for service in changed {
+        eprintln!("locked {}", service);
+    }
+    Ok(())
+}
 
-            if service.depends_on.len() > 0 {
-                compose_str.push_str("\n");
-                compose_str.push_str("    depends_on:\n");
-                for dependency in service.depends_on {
-                    compose_str.push_str("      - ");
-                    compose_str.push_str(&dependency.service);
-                    compose_str.push_str("\n");
-                }
-            }
+fn restore(archive: Option<PathBuf>, file: PathBuf, directory: PathBuf) -> std::io::Result<()> {
+    let archive = match archive {
+        Some(archive) => archive,
+        None => backup::latest(&file.parent().unwrap_or(Path::new("")).join(directory))?,
+    };
+    eprintln!("restoring {}", archive.display());
+    backup::restore(&file, &archive)
+}
 
-            if service.restart.condition.len() > 0 {
-                compose_str.push_str("\n");
-                compose_str.push_str("    restart: ");
-                compose_str.push_str(&service.restart.condition);
-                compose_str.push_str("\n");
-            }
+/// Copies a stack.
+#[derive(Args)]
+struct CloneArgs {
+    /// Compose file of the stack to copy
+    #[arg(long)]
+    from: PathBuf,
+    /// Directory of the new stack
+    #[arg(long)]
+    to: PathBuf,
+    /// Defaults to the database name of the copied stack
+    #[arg(long)]
+    site_title: Option<String>,
+    /// Host name of the new site, e.g. `staging.shop.com`
+    #[arg(long)]
+    site_url: String,
+    /// Defaults to the profile of the copied stack
+    #[arg(long, value_enum)]
+    site_profile: Option<Profile>,
+    /// Archive to import; defaults to the newest one of the copied stack
+    #[arg(long)]
+    archive: Option<PathBuf>,
+    /// Only generate the new stack, without importing a backup
+    #[arg(long)]
+    no_restore: bool,
+    /// Overwrite an existing compose file in `--to`
+    #[arg(long)]
+    force: bool,
+}
 
-            if service.command.value.len() > 0 {
-                compose_str.push_str("\n");
-                compose_str.push_str("    command: ");
-                compose_str.push_str(&service.command.value);
-                compose_str.push_str("\n");
-            }
+fn clone_stack(args: CloneArgs) -> std::io::Result<()> {
+    let source = clone::Source::read(&args.from)?;
+    let archive = if args.no_restore { None } else { Some(clone::archive(&source, args.archive)?) };
+    let site = Site {
+        title: args.site_title.or(source.database_name.clone()).unwrap_or_else(|| Site::default().title),
+        url: args.site_url,
+        profile: args.site_profile.unwrap_or(source.profile),
+    };
+    let file = clone::generate(&source, site, &args.to, args.force)?;
+    eprintln!("generated {}", file.display());
+    if let Some(archive) = archive {
+        eprintln!("restoring {}", archive.display());
+        backup::restore(&file, &archive)?;
+    }
+    Ok(())
+}
 
-            if service.labels.len() > 0 {
-                compose_str.push_str("\n");
-                compose_str.push_str("    labels:\n");
-                for label in service.labels {
-                    compose_str.push_str("      - ");
-                    compose_str.push_str(&label.key);
-                    compose_str.push_str("=");
-                    compose_str.push_str(&label.value);
-                    compose_str.push_str("\n");
-                }
-            }
+fn scaffold_app(config: &Config, site: SiteArgs, output: PathBuf, force: bool) -> std::io::Result<()> {
+    for file in app::scaffold(&site.site(config), &output, force)? {
+        eprintln!("created {}", file.display());
+    }
+    Ok(())
+}
 
-            if service.environments.len() > 0 {
-                compose_str.push_str("\n");
-                compose_str.push_str("    environments:\n");
-                for environment in service.environments {
-                    compose_str.push_str("      - ");
-                    compose_str.push_str(&environment.title);
-                    compose_str.push_str("=");
-                    compose_str.push_str(&environment.value);
-                    compose_str.push_str("\n");
-                }
+fn manage_project(config: &Config, store: Option<PathBuf>, command: ProjectCommand) -> std::io::Result<()> {
+    let store = Store::open(&store.unwrap_or_else(|| config.database()))?;
+    match command {
+        ProjectCommand::List => {
+            for project in store.list()? {
+                println!("{}\t{}\t{}", project.name, project.stack.site.url, project.stack.site.profile.as_str());
             }
-
-            if service.networks.len() > 0 {
-                compose_str.push_str("\n");
-                compose_str.push_str("    networks:\n");
-                for network in service.networks {
-                    compose_str.push_str("      - ");
-                    compose_str.push_str(&network.driver);
-                    compose_str.push_str("\n");
-                }
+        }
+        ProjectCommand::Create { name, stack } => {
+            store.create(&name, stack.options(config))?;
+            eprintln!("created project {}", name);
+        }
+        ProjectCommand::Show { name } => {
+            println!("{}", serde_json::to_string_pretty(&store.get(&name)?.summary())?);
+        }
+        ProjectCommand::Render { name, revision: None } => {
+            let (revision, _) = store.render(&name)?;
+            eprintln!("revision {}", revision.number);
+            print!("{}", revision.compose);
+        }
+        ProjectCommand::Render { name, revision: Some(number) } => {
+            print!("{}", parse_compose(&store.rerender(&name, number)?.1));
+        }
+        ProjectCommand::Revisions { name } => {
+            for revision in store.revisions(&name)? {
+                println!("{}\t{}\t{}\t{}", revision.number, revision.created_at, revision.version, revision.hash);
             }
-
+        }
+        ProjectCommand::Diff { name, from, to } => {
+            let to = match to {
+                Some(to) => to,
+                None => store.revisions(&name)?.last().map_or(from, |revision| revision.number),
+            };
+            print!("{}", store.diff(&name, from, to)?);
+        }
+        ProjectCommand::Rollback { name, revision } => {
+            let rolled_back = store.rollback(&name, revision)?;
+            eprintln!("rolled back {} to revision {} as revision {}", name, revision, rolled_back.number);
+        }
+        ProjectCommand::Delete { name } => {
+            store.delete(&name)?;
+            eprintln!("deleted project {}", name);
         }
     }
+    Ok(())
+}
 
-    if compose.volumes.len() > 0 {
-        compose_str.push_str("\n");
-        compose_str.push_str("\n");
-        compose_str.push_str("volumes:\n");
-        for volume in compose.volumes {
-            compose_str.push_str("  ");
-            compose_str.push_str(&volume.title);
-            compose_str.push_str(":\n");
-            compose_str.push_str("    driver: ");
-            compose_str.push_str(&volume.driver);
-            compose_str.push_str("\n");
+fn manage_token(config: &Config, store: Option<PathBuf>, command: TokenCommand) -> std::io::Result<()> {
+    let tokens = Tokens::open(&store.unwrap_or_else(|| config.database()))?;
+    match command {
+        TokenCommand::List => {
+            for token in tokens.list()? {
+                println!("{}\t{}\t{}", token.name, token.role.as_str(), token.created_at);
+            }
+        }
+        TokenCommand::Create { name, role } => {
+            println!("{}", tokens.create(&name, role)?);
+            eprintln!("created {} token {}; store it now, it cannot be shown again", role.as_str(), name);
+        }
+        TokenCommand::Revoke { name } => {
+            tokens.revoke(&name)?;
+            eprintln!("revoked token {}", name);
         }
     }
+    Ok(())
+}
 
-    if compose.networks.len() > 0 {
-        compose_str.push_str("\n");
-        compose_str.push_str("\n");
-        compose_str.push_str("networks:\n");
-        for network in compose.networks {
-            compose_str.push_str("  ");
-            compose_str.push_str(&network.driver);
-            compose_str.push_str(":\n");
-            compose_str.push_str("    external: ");
-            compose_str.push_str(&network.external.to_string());
-            compose_str.push_str("\n");
+fn describe_catalog(config: &Config, site_profile: Option<Profile>, command: CatalogCommand) -> std::io::Result<()> {
+    let mut options = config.stack_defaults();
+    if let Some(profile) = site_profile {
+        options.site.profile = profile;
+    }
+    match command {
+        CatalogCommand::List => {
+            for entry in catalog::entries(&options) {
+                let profiles: Vec<&str> = entry.profiles.iter().map(Profile::as_str).collect();
+                let origin = if entry.template { "template" } else { "built-in" };
+                println!("{}\t{}\t{}\t{}", entry.name, profiles.join(","), origin, entry.description);
+            }
+        }
+        CatalogCommand::Show { service } => {
+            println!("{}", serde_json::to_string_pretty(&catalog::entry(&options, &service)?)?);
         }
     }
+    Ok(())
+}
 
-    compose_str
+/// The configuration, with the global flags of the command line over it.
+fn configure(cli: &Cli) -> std::io::Result<Config> {
+    let mut config = Config::load(cli.config.as_deref())?;
+    if let Some(data_dir) = &cli.data_dir {
+        config.data_dir = data_dir.clone();
+        config.set_flag("data_dir", "--data-dir");
+    }
+    if let Some(log_level) = cli.log_level {
+        config.log_level = log_level;
+        config.set_flag("log_level", "--log-level");
+    }
+    Ok(config)
 }
 
+async fn run(cli: Cli) -> std::io::Result<()> {
+    let mut config = configure(&cli)?;
+    let command = cli.command.unwrap_or(Commands::Serve(ServeArgs::default()));
+    if let Commands::Serve(args) | Commands::Config { command: ConfigCommand::Show(args) } = &command {
+        args.apply(&mut config);
+    }
+    config.validate()?;
+    config.install_images()?;
+    // `config show` stays usable to find out where a broken template is.
+    if !matches!(command, Commands::Config { .. }) {
+        config.install_templates()?;
+    }
+    match command {
+        Commands::Serve(_) => server::serve(config).await,
+        Commands::DockerCompose { stack, host_memory, pin_digests, lock_file } => {
+            docker_compose(&config, stack, host_memory, pin_digests, lock_file)
+        }
+        Commands::Lock { stack, manifest, update, lock_file } => lock(&config, stack, manifest, update, lock_file),
+        Commands::Backup { file } => backup::backup(&file),
+        Commands::Restore { archive, file, directory } => restore(archive, file, directory),
+        Commands::Clone(args) => clone_stack(args),
+        Commands::App { site, output, force } => scaffold_app(&config, site, output, force),
+        Commands::Project { store, command } => manage_project(&config, store, command),
+        Commands::Token { store, command } => manage_token(&config, store, command),
+        Commands::Catalog { site_profile, command } => describe_catalog(&config, site_profile, command),
+        Commands::Config { command: ConfigCommand::Show(_) } => {
+            print!("{}", config.show()?);
+            Ok(())
+        }
+    }
+}
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    HttpServer::new(|| {
-        App::new()
-            .service(web::resource("/dc").to(|| async { 
-                let compose = Compose {
-                    version: Some("3.8".to_string()),
-                    services: vec![
-                        Service {
-                            image: "mysql".to_string(),
-                            container_name: "mysql".to_string(),
-                            ports: vec![
-                                Port {
-                                    host: "3306".to_string(),
-                                    container: "3306".to_string()
-                                }
-                            ],
-                            volumes: vec![
-                                Volume {
-                                    title: "./mysql".to_string(),
-                                    driver: "/var/lib/mysql".to_string()
-                                }
-                            ],
-                            networks: vec![
-                                Network {
-                                    driver: "web".to_string(),
-                                    external: false
-                                }
-                            ],
-                            environments: vec![
-                                Environment {
-                                    title: "MYSQL_ROOT_PASSWORD".to_string(),
-                                    value: "123456".to_string()
-                                }
-                            ],
-                            restart: Policy {
-                                condition: "always".to_string()
-                            },
-                            command: Command {
-                                value: "mysqld".to_string()
-                            },
-                            labels: vec![
-                                Label {
-                                    key: "com.example.description".to_string(),
-                                    value: "MySQL Server".to_string()
-                                }
-                            ], 
-                            depends_on: vec![]
-                        },
-                        Service {
-                            image: "redis".to_string(),
-                            container_name: "redis".to_string(),
-                            ports: vec![
-                                Port {
-                                    host: "6379".to_string(),
-                                    container: "6379".to_string()
-                                }
-                            ],
-                            volumes: vec![
-                                Volume {
-                                    title: "./redis".to_string(),
-                                    driver: "/data".to_string()
-                                }
-                            ],
-                            networks: vec![
-                                Network {
-                                    driver: "web".to_string(),
-                                    external: false
-                                }
-                            ],
-                            environments: vec![
-                                Environment {
-                                    title: "REDIS_PASSWORD".to_string(),
-                                    value: "123456".to_string()
-                                }
-                            ],
-                            restart: Policy {
-                                condition: "always".to_string()
-                            },
-                            command: Command {
-                                value: "redis-server".to_string()
-                            },
-                            labels: vec![
-                                Label {
-                                    key: "com.example.description".to_string(),
-                                    value: "Redis Server".to_string()
-                                }
-                            ],
-                            depends_on: vec![
-                                Dependency {
-                                    service: "mysql".to_string()
-                                }
-                            ]
-                        },
-                        Service {
-                            image: "wordpress".to_string(),
-                            container_name: "wordpress".to_string(),
-                            ports: vec![
-                                Port {
-                                    host: "8080".to_string(),
-                                    container: "80".to_string()
-                                }
-                            ],
-                            volumes: vec![
-                                Volume {
-                                    title: "./wordpress".to_string(),
-                                    driver: "/var/www/html".to_string()
-                                }
-                            ],
-                            networks: vec![
-                                Network {
-                                    driver: "web".to_string(),
-                                    external: false
-                                }
-                            ],
-                            environments: vec![
-                                Environment {
-                                    title: "WORDPRESS_DB_HOST".to_string(),
-                                    value: "mysql:3306".to_string()
-                                },
-                                Environment {
-                                    title: "WORDPRESS_DB_USER".to_string(),
-                                    value: "root".to_string()
-                                },
-                                Environment {
-                                    title: "WORDPRESS_DB_PASSWORD".to_string(),
-                                    value: "123456".to_string()
-                                }
-                            ],
-                            restart: Policy {
-                                condition: "always".to_string()
-                            },
-                            command: Command {
-                                value: "apache2-foreground".to_string()
-                            },
-                            labels: vec![
-                                Label {
-                                    key: "com.example.description".to_string(),
-                                    value: "WordPress Server".to_string()
-                                }
-                            ],
-                            depends_on: vec![
-                                Dependency {
-                                    service: "mysql".to_string()
-                                }
-                            ]
-                        },
-                        Service {
-                            image: "phpmyadmin".to_string(),
-                            container_name: "phpmyadmin".to_string(),
-                            ports: vec![
-                                Port {
-                                    host: "8081".to_string(),
-                                    container: "80".to_string()
-                                }
-                            ],
-                            volumes: vec![],
-                            networks: vec![
-                                Network {
-                                    driver: "web".to_string(),
-                                    external: false
-                                }
-                            ],
-                            environments: vec![
-                                Environment {
-                                    title: "PMA_HOST".to_string(),
-                                    value: "mysql".to_string()
-                                },
-                                Environment {
-                                    title: "PMA_USER".to_string(),
-                                    value: "root".to_string()
-                                },
-                                Environment {
-                                    title: "PMA_PASSWORD".to_string(),
-                                    value: "123456".to_string()
-                                }
-                            ],
-                            restart: Policy {
-                                condition: "always".to_string()
-                            },
-                            command: Command {
-                                value: "apache2-foreground".to_string()
-                            },
-                            labels: vec![
-                                Label {
-                                    key: "com.example.description".to_string(),
-                                    value: "PhpMyAdmin Server".to_string()
-                                }
-                            ],
-                            depends_on: vec![
-                                Dependency {
-                                    service: "mysql".to_string()
-                                }
-                            ]
-                        },
-                        Service {
-                            image: "portainer".to_string(),
-                            container_name: "portainer".to_string(),
-                            ports: vec![
-                                Port {
-                                    host: "9000".to_string(),
-                                    container: "9000".to_string()
-                                }
-                            ],
-                            volumes: vec![
-                                Volume {
-                                    title: "/var/run/docker.sock".to_string(),
-                                    driver: "/var/run/docker.sock".to_string()
-                                }
-                            ],
-                            networks: vec![
-                                Network {
-                                    driver: "web".to_string(),
-                                    external: false
-                                }
-                            ],
-                            environments: vec![],
-                            restart: Policy {
-                                condition: "always".to_string()
-                            },
-                            command: Command {
-                                value: "".to_string()
-                            },
-                            labels: vec![
-                                Label {
-                                    key: "com.example.description".to_string(),
-                                    value: "Portainer Server".to_string()
-                                }
-                            ],
-                            depends_on: vec![]
-                        },
-                        Service {
-                            image: "cadvisor".to_string(),
-                            container_name: "cadvisor".to_string(),
-                            ports: vec![
-                                Port {
-                                    host: "8082".to_string(),
-                                    container: "8080".to_string()
-                                }
-                            ],
-                            volumes: vec![
-                                Volume {
-                                    title: "/".to_string(),
-                                    driver: "/".to_string()
-                                }
-                            ],
-                            networks: vec![
-                                Network {
-                                    driver: "web".to_string(),
-                                    external: false
-                                }
-                            ],
-                            environments: vec![],
-                            restart: Policy {
-                                condition: "always".to_string()
-                            },
-                            command: Command {
-                                value: "".to_string()
-                            },
-                            labels: vec![
-                                Label {
-                                    key: "com.example.description".to_string(),
-                                    value: "Cadvisor Server".to_string()
-                                }
-                            ],
-                            depends_on: vec![]
-                        },
-                        Service {
-                            image: "certbot".to_string(),
-                            container_name: "certbot".to_string(),
-                            ports: vec![],
-                            volumes: vec![
-                                Volume {
-                                    title: "/etc/letsencrypt".to_string(),
-                                    driver: "/etc/letsencrypt".to_string()
-                                },
-                                Volume {
-                                    title: "/var/lib/letsencrypt".to_string(),
-                                    driver: "/var/lib/letsencrypt".to_string()
-                                },
-                                Volume {
-                                    title: "/var/log/letsencrypt".to_string(),
-                                    driver: "/var/log/letsencrypt".to_string()
-                                }
-                            ],
-                            networks: vec![
-                                Network {
-                                    driver: "web".to_string(),
-                                    external: false
-                                }
-                            ],
-                            environments: vec![],
-                            restart: Policy {
-                                condition: "always".to_string()
-                            },
-                            command: Command {
-                                value: "".to_string()
-                            },
-                            labels: vec![
-                                Label {
-                                    key: "com.example.description".to_string(),
-                                    value: "Certbot Server".to_string()
-                                }
-                            ],
-                            depends_on: vec![]
-                        },
-                        Service {
-                            image: "nginx".to_string(),
-                            container_name: "nginx".to_string(),
-                            ports: vec![
-                                Port {
-                                    host: "80".to_string(),
-                                    container: "80".to_string()
-                                },
-                                Port {
-                                    host: "443".to_string(),
-                                    container: "443".to_string()
-                                }
-                            ],
-                            volumes: vec![
-                                Volume {
-                                    title: "nginx".to_string(),
-                                    driver: "/etc/nginx".to_string()
-                                }
-                            ],
-                            networks: vec![
-                                Network {
-                                    driver: "web".to_string(),
-                                    external: false
-                                }
-                            ],
-                            environments: vec![],
-                            restart: Policy {
-                                condition: "always".to_string()
-                            },
-                            command: Command {
-                                value: "".to_string()
-                            },
-                            labels: vec![
-                                Label {
-                                    key: "com.example.description".to_string(),
-                                    value: "Nginx Server".to_string()
-                                }
-                            ],
-                            depends_on: vec![
-                                Dependency {
-                                    service: "wordpress".to_string()
-                                },
-                                Dependency {
-                                    service: "phpmyadmin".to_string()
-                                },
-                                Dependency {
-                                    service: "portainer".to_string()
-                                },
-                                Dependency {
-                                    service: "cadvisor".to_string()
-                                },
-                                Dependency {
-                                    service: "certbot".to_string()
-                                }
-                            ]
-                        },
-                        Service {
-                            image: "gitlab".to_string(),
-                            container_name: "gitlab".to_string(),
-                            ports: vec![
-                                Port {
-                                    host: "8083".to_string(),
-                                    container: "80".to_string()
-                                },
-                                Port {
-                                    host: "2222".to_string(),
-                                    container: "22".to_string()
-                                }
-                            ],
-                            volumes: vec![
-                                Volume {
-                                    title: "./gitlab/config".to_string(),
-                                    driver: "/etc/gitlab".to_string()
-                                },
-                                Volume {
-                                    title: "./gitlab/logs".to_string(),
-                                    driver: "/var/log/gitlab".to_string()
-                                },
-                                Volume {
-                                    title: "./gitlab/data".to_string(),
-                                    driver: "/var/opt/gitlab".to_string()
-                                }
-                            ],
-                            networks: vec![
-                                Network {
-                                    driver: "web".to_string(),
-                                    external: false
-                                }
-                            ],
-                            environments: vec![
-                                Environment {
-                                    title: "GITLAB_OMNIBUS_CONFIG".to_string(),
-                                    value: "external_url 'https://gitlab.example.com'; gitlab_rails['gitlab_shell_ssh_port'] = 2222;".to_string()
-                                }
-                            ],
-                            restart: Policy {
-                                condition: "always".to_string()
-                            },
-                            command: Command {
-                                value: "".to_string()
-                            },
-                            labels: vec![
-                                Label {
-                                    key: "com.example.description".to_string(),
-                                    value: "Gitlab Server".to_string()
-                                }
-                            ],
-                            depends_on: vec![]
-                        }, 
-                        Service {
-                            image: "sonarqube".to_string(),
-                            container_name: "sonarqube".to_string(),
-                            ports: vec![
-                                Port {
-                                    host: "8084".to_string(),
-                                    container: "9000".to_string()
-                                }
-                            ],
-                            volumes: vec![
-                                Volume {
-                                    title: "./sonarqube/conf".to_string(),
-                                    driver: "/opt/sonarqube/conf".to_string()
-                                },
-                                Volume {
-                                    title: "./sonarqube/data".to_string(),
-                                    driver: "/opt/sonarqube/data".to_string()
-                                },
-                                Volume {
-                                    title: "./sonarqube/logs".to_string(),
-                                    driver: "/opt/sonarqube/logs".to_string()
-                                },
-                                Volume {
-                                    title: "./sonarqube/extensions".to_string(),
-                                    driver: "/opt/sonarqube/extensions".to_string()
-                                }
-                            ],
-                            networks: vec![
-                                Network {
-                                    driver: "web".to_string(),
-                                    external: false
-                                }
-                            ],
-                            environments: vec![],
-                            restart: Policy {
-                                condition: "always".to_string()
-                            },
-                            command: Command {
-                                value: "".to_string()
-                            },
-                            labels: vec![
-                                Label {
-                                    key: "com.example.description".to_string(),
-                                    value: "Sonarqube Server".to_string()
-                                }
-                            ],
-                            depends_on: vec![]
-                        }, 
-                        Service {
-                            image: "code".to_string(),
-                            container_name: "code-server".to_string(),
-                            ports: vec![
-                                Port {
-                                    host: "8085".to_string(),
-                                    container: "8080".to_string()
-                                }
-                            ],
-                            volumes: vec![
-                                Volume {
-                                    title: "./code-server".to_string(),
-                                    driver: "/home/coder/project".to_string()
-                                }
-                            ],
-                            networks: vec![
-                                Network {
-                                    driver: "web".to_string(),
-                                    external: false
-                                }
-                            ],
-                            environments: vec![
-                                Environment {
-                                    title: "PASSWORD".to_string(),
-                                    value: "123456".to_string()
-                                }
-                            ],
-                            restart: Policy {
-                                condition: "always".to_string()
-                            },
-                            command: Command {
-                                value: "".to_string()
-                            },
-                            labels: vec![
-                                Label {
-                                    key: "com.example.description".to_string(),
-                                    value: "Code Server".to_string()
-                                }
-                            ],
-                            depends_on: vec![]
-                        },
-                        Service {
-                            image: "mailhog/mailhog".to_string(),
-                            container_name: "mailhog".to_string(),
-                            ports: vec![
-                                Port {
-                                    host: "8086".to_string(),
-                                    container: "8025".to_string()
-                                }
-                            ],
-                            volumes: vec![],
-                            networks: vec![
-                                Network {
-                                    driver: "web".to_string(),
-                                    external: false
-                                }
-                            ],
-                            environments: vec![],
-                            restart: Policy {
-                                condition: "always".to_string()
-                            },
-                            command: Command {
-                                value: "".to_string()
-                            },
-                            labels: vec![
-                                Label {
-                                    key: "com.example.description".to_string(),
-                                    value: "Mailhog Server".to_string()
-                                }
-                            ],
-                            depends_on: vec![]
-                        }, 
-                        Service {
-                            image: "pmsipilot/docker-compose-viz".to_string(),
-                            container_name: "graphviz".to_string(),
-                            ports: vec![
-                                Port {
-                                    host: "8087".to_string(),
-                                    container: "8080".to_string()
-                                }
-                            ],
-                            volumes: vec![
-                                Volume {
-                                    title: "/var/run/docker.sock".to_string(),
-                                    driver: "/var/run/docker.sock".to_string()
-                                }
-                            ],
-                            networks: vec![
-                                Network {
-                                    driver: "web".to_string(),
-                                    external: false
-                                }
-                            ],
-                            environments: vec![],
-                            restart: Policy {
-                                condition: "always".to_string()
-                            },
-                            command: Command {
-                                value: "".to_string()
-                            },
-                            labels: vec![
-                                Label {
-                                    key: "com.example.description".to_string(),
-                                    value: "Graphviz Server".to_string()
-                                }
-                            ],
-                            depends_on: vec![]
-                        }
-                    ],
-                    volumes: vec![
-                        Volume {
-                            title: "nginx".to_string(),
-                            driver: "local".to_string()
-                        },
-                        Volume {
-                            title: "mysql".to_string(),
-                            driver: "local".to_string()
-                        },
-                        Volume {
-                            title: "redis".to_string(),
-                            driver: "local".to_string()
-                        },
-                        Volume {
-                            title: "wordpress".to_string(),
-                            driver: "local".to_string()
-                        },
-                        Volume {
-                            title: "phpmyadmin".to_string(),
-                            driver: "local".to_string()
-                        },
-                        Volume {
-                            title: "portainer".to_string(),
-                            driver: "local".to_string()
-                        },
-                        Volume {
-                            title: "cadvisor".to_string(),
-                            driver: "local".to_string()
-                        },
-                        Volume {
-                            title: "certbot".to_string(),
-                            driver: "local".to_string()
-                        },
-                        Volume {
-                            title: "gitlab".to_string(),
-                            driver: "local".to_string()
-                        },
-                        Volume {
-                            title: "sonarqube".to_string(),
-                            driver: "local".to_string()
-                        },
-                        Volume {
-                            title: "code-server".to_string(),
-                            driver: "local".to_string()
-                        },
-                        Volume {
-                            title: "mailhog".to_string(),
-                            driver: "local".to_string()
-                        },
-                        Volume {
-                            title: "graphviz".to_string(),
-                            driver: "local".to_string()
-                        }
-                    ],
-                    networks: vec![
-                        Network {
-                            driver: "web".to_string(),
-                            external: false
-                        },
-                        Network {
-                            driver: "hub".to_string(),
-                            external: false
-                        }
-                    ],
-                    environments: vec![
-                        Environment {
-                            title: "VIRTUAL_HOST".to_string(),
-                            value: "example.com".to_string()
-                        },
-                        Environment {
-                            title: "LETSENCRYPT_HOST".to_string(),
-                            value: "example.com".to_string()
-                        },
-                        Environment {
-                            title: "LETSENCRYPT_EMAIL".to_string(),
-                            value: "ydev@tuta.io".to_string()
-                        }
-                    ],
-                    extensions: vec![
-                        Extension {
-                            title: "traefik.enable".to_string(),
-                            value: "example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.rule".to_string(),
-                            value: "HostRegexp(`{any:.+}`)".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.entrypoints".to_string(),
-                            value: "web".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.middlewares".to_string(),
-                            value: "redirect-to-https".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.middlewares.redirect-to-https.redirectscheme.scheme".to_string(),
-                            value: "https".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.priority".to_string(),
-                            value: "1".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls".to_string(),
-                            value: "true".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.certresolver".to_string(),
-                            value: "letsencrypt".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].main".to_string(),
-                            value: "example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[0]".to_string(),
-                            value: "www.example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[1]".to_string(),
-                            value: "gitlab.example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[2]".to_string(),
-                            value: "sonarqube.example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[3]".to_string(),
-                            value: "code-server.example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[4]".to_string(),
-                            value: "mailhog.example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[5]".to_string(),
-                            value: "graphviz.example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[6]".to_string(),
-                            value: "portainer.example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[7]".to_string(),
-                            value: "cadvisor.example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[8]".to_string(),
-                            value: "phpmyadmin.example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[9]".to_string(),
-                            value: "wordpress.example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[10]".to_string(),
-                            value: "redis.example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[11]".to_string(),
-                            value: "mysql.example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[12]".to_string(),
-                            value: "nginx.example.com".to_string()
-                        },
-                        Extension {
-                            title: "traefik.http.routers.http-catchall.tls.domains[0].sans[13]".to_string(),
-                            value: "hub.example.com".to_string()
-                        },
-                        Extension {
-                            title: "nginx.http.routers.nginx.rule".to_string(),
-                            value: "Host(`nginx.example.com`)".to_string()
-                        },
-                        Extension {
-                            title: "nginx.http.routers.nginx.entrypoints".to_string(),
-                            value: "web".to_string()
-                        },
-                        Extension {
-                            title: "nginx.http.routers.nginx.tls".to_string(),
-                            value: "true".to_string()
-                        },
-                        Extension {
-                            title: "nginx.http.routers.nginx.tls.certresolver".to_string(),
-                            value: "letsencrypt".to_string()
-                        },
-                        Extension {
-                            title: "nginx.http.routers.nginx.tls.domains[0].main".to_string(),
-                            value: "nginx.example.com".to_string()
-                        },
-                        Extension {
-                            title: "nginx.http.routers.nginx.tls.domains[0].sans[0]".to_string(),
-                            value: "www.nginx.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.rule".to_string(),
-                            value: "Host(`hub.example.com`)".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.entrypoints".to_string(),
-                            value: "web".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls".to_string(),
-                            value: "true".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.certresolver".to_string(),
-                            value: "letsencrypt".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].main".to_string(),
-                            value: "hub.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].sans[0]".to_string(),
-                            value: "www.hub.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].sans[1]".to_string(),
-                            value: "gitlab.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].sans[2]".to_string(),
-                            value: "sonarqube.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].sans[3]".to_string(),
-                            value: "code-server.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].sans[4]".to_string(),
-                            value: "mailhog.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].sans[5]".to_string(),
-                            value: "graphviz.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].sans[6]".to_string(),
-                            value: "portainer.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].sans[7]".to_string(),
-                            value: "cadvisor.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].sans[8]".to_string(),
-                            value: "phpmyadmin.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].sans[9]".to_string(),
-                            value: "wordpress.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].sans[10]".to_string(),
-                            value: "redis.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].sans[11]".to_string(),
-                            value: "mysql.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].sans[12]".to_string(),
-                            value: "nginx.example.com".to_string()
-                        },
-                        Extension {
-                            title: "hub.http.routers.hub.tls.domains[0].sans[13]".to_string(),
-                            value: "hub.example.com".to_string()
-                        }
-                    ]
-                };
-                let compose_str = parse_compose(compose);
-                actix_web::HttpResponse::Ok().body(compose_str)
-             }))
-    })
-    .bind("0.0.0.0:8888")?
-    .run()
-    .await
+async fn main() {
+    if let Err(err) = run(Cli::parse()).await {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
 }
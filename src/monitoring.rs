@@ -0,0 +1,484 @@
+use serde_json::json;
+
+use crate::compose::{
+    Command, ConfigFile, Dependency, Environment, Healthcheck, Label, Mount, MountKind, NetworkAttachment, Policy,
+    Port, Resources, Service,
+};
+use crate::database::{self, Engine};
+use crate::image::catalog_image;
+use crate::resources::MIB;
+use crate::secrets::Secrets;
+
+/// A Prometheus scrape job, with the Grafana panels of its metrics.
+struct Target {
+    job: &'static str,
+    address: &'static str,
+    /// `(title, PromQL expression, unit)`
+    panels: &'static [(&'static str, &'static str, &'static str)],
+}
+
+const CADVISOR: Target = Target {
+    job: "cadvisor",
+    address: "cadvisor:8080",
+    panels: &[
+        ("Container CPU", "sum by (name) (rate(container_cpu_usage_seconds_total{name!=\"\"}[5m]))", "percentunit"),
+        ("Container memory", "sum by (name) (container_memory_working_set_bytes{name!=\"\"})", "bytes"),
+    ],
+};
+
+const NODE: Target = Target {
+    job: "node",
+    address: "node-exporter:9100",
+    panels: &[
+        ("Host CPU", "1 - avg(rate(node_cpu_seconds_total{mode=\"idle\"}[5m]))", "percentunit"),
+        ("Host memory available", "node_memory_MemAvailable_bytes", "bytes"),
+    ],
+};
+
+const MYSQL: Target = Target {
+    job: "mysql",
+    address: "mysqld-exporter:9104",
+    panels: &[
+        ("Database queries", "rate(mysql_global_status_queries[5m])", "ops"),
+        ("Database connections", "mysql_global_status_threads_connected", "short"),
+    ],
+};
+
+const REDIS: Target = Target {
+    job: "redis",
+    address: "redis-exporter:9121",
+    panels: &[
+        ("Redis memory", "redis_memory_used_bytes", "bytes"),
+        ("Redis commands", "rate(redis_commands_processed_total[5m])", "ops"),
+    ],
+};
+
+const TRAEFIK: Target = Target {
+    job: "traefik",
+    address: "traefik:8080",
+    panels: &[
+        ("Proxy requests", "sum by (service) (rate(traefik_service_requests_total[5m]))", "reqps"),
+        ("Proxy errors", "sum by (service) (rate(traefik_service_requests_total{code=~\"5..\"}[5m]))", "reqps"),
+    ],
+};
+
+const WOORS: Target = Target {
+    job: "woors",
+    address: "woors:8888",
+    panels: &[
+        ("Woors requests", "sum by (route) (rate(woors_http_requests_total[5m]))", "reqps"),
+        ("Woors renders", "sum by (service) (increase(woors_renders_total[1h]))", "short"),
+    ],
+};
+
+/// Adds Prometheus, Grafana and the exporters of the selected services.
+/// Only the services present in the stack are scraped.
+pub fn wire(services: &mut Vec<Service>, engine: Engine, secrets: &mut Secrets) {
+    let has = |name: &str| services.iter().any(|service| service.container_name == name);
+    let mut targets = vec![];
+    let mut extra = vec![node_exporter()];
+    if has("cadvisor") {
+        targets.push(&CADVISOR);
+    }
+    targets.push(&NODE);
+    if has(engine.service()) {
+        targets.push(&MYSQL);
+        extra.push(mysqld_exporter(engine, secrets));
+    }
+    if has("redis") {
+        targets.push(&REDIS);
+        extra.push(redis_exporter(secrets));
+    }
+    if has("traefik") {
+        targets.push(&TRAEFIK);
+    }
+    if has("woors") {
+        targets.push(&WOORS);
+    }
+
+    if let Some(database) = services.iter_mut().find(|service| service.container_name == engine.service()) {
+        database.configs.push(exporter_user(secrets));
+    }
+    services.extend(extra);
+    services.push(prometheus(&targets));
+    services.push(grafana(&targets, secrets));
+}
+
+fn exporter_password(secrets: &mut Secrets) -> String {
+    secrets.password("monitoring.exporter_password")
+}
+
+/// The read-only account of `mysqld-exporter`. Like every init script it
+/// only runs when the database volume is created.
+fn exporter_user(secrets: &mut Secrets) -> ConfigFile {
+    let content = [
+        format!(
+            "CREATE USER IF NOT EXISTS 'exporter'@'%' IDENTIFIED BY '{}' WITH MAX_USER_CONNECTIONS 3;",
+            exporter_password(secrets)
+        ),
+        "GRANT PROCESS, REPLICATION CLIENT, SELECT ON *.* TO 'exporter'@'%';".to_string(),
+    ];
+    ConfigFile {
+        title: "exporter_sql".to_string(),
+        target: "/docker-entrypoint-initdb.d/exporter.sql".to_string(),
+        content: content.join("\n") + "\n",
+    }
+}
+
+/// `prometheus.yml` scraping `targets`, and Prometheus itself.
+fn scrape_config(targets: &[&Target]) -> ConfigFile {
+    let mut lines = vec![
+        "global:".to_string(),
+        "  scrape_interval: 15s".to_string(),
+        "scrape_configs:".to_string(),
+        "  - job_name: prometheus".to_string(),
+        "    static_configs:".to_string(),
+        "      - targets: [\"localhost:9090\"]".to_string(),
+    ];
+    for target in targets {
+        lines.push(format!("  - job_name: {}", target.job));
+        lines.push("    static_configs:".to_string());
+        lines.push(format!("      - targets: [\"{}\"]", target.address));
+    }
+    ConfigFile {
+        title: "prometheus_yml".to_string(),
+        target: "/etc/prometheus/prometheus.yml".to_string(),
+        content: lines.join("\n") + "\n",
+    }
+}
+
+fn prometheus(targets: &[&Target]) -> Service {
+    Service {
+        image: catalog_image("prometheus"),
+        container_name: "prometheus".to_string(),
+        ports: vec![
+            Port {
+                host: "9090".to_string(),
+                container: "9090".to_string()
+            }
+        ],
+        volumes: vec![
+            Mount {
+                kind: MountKind::Volume,
+                source: "prometheus".to_string(),
+                target: "/prometheus".to_string(),
+                read_only: false
+            }
+        ],
+        networks: vec![
+            NetworkAttachment {
+                network: "web".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            },
+            NetworkAttachment {
+                network: "backend".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        environments: vec![],
+        restart: Policy {
+            condition: "always".to_string()
+        },
+        command: Command {
+            value: "--config.file=/etc/prometheus/prometheus.yml --storage.tsdb.path=/prometheus --storage.tsdb.retention.time=15d".to_string()
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "Prometheus Server".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.5),
+            memory: Some(512 * MIB),
+            reservation_memory: Some(128 * MIB),
+            ..Default::default()
+        },
+        healthcheck: Some(Healthcheck {
+            test: vec![
+                "CMD-SHELL".to_string(),
+                "wget -q -O /dev/null http://localhost:9090/-/ready || exit 1".to_string()
+            ],
+            interval: "10s".to_string(),
+            timeout: "5s".to_string(),
+            retries: 5,
+            start_period: "10s".to_string()
+        }),
+        configs: vec![scrape_config(targets)],
+        ..Default::default()
+    }
+}
+
+/// The Prometheus datasource of Grafana.
+fn datasources() -> ConfigFile {
+    let content = [
+        "apiVersion: 1",
+        "datasources:",
+        "  - name: Prometheus",
+        "    type: prometheus",
+        "    uid: prometheus",
+        "    access: proxy",
+        "    url: http://prometheus:9090",
+        "    isDefault: true",
+    ];
+    ConfigFile {
+        title: "grafana_datasources".to_string(),
+        target: "/etc/grafana/provisioning/datasources/woors.yml".to_string(),
+        content: content.join("\n") + "\n",
+    }
+}
+
+/// Loads the dashboards of `/etc/grafana/dashboards` into a `woors` folder.
+fn dashboard_provider() -> ConfigFile {
+    let content = [
+        "apiVersion: 1",
+        "providers:",
+        "  - name: woors",
+        "    folder: woors",
+        "    type: file",
+        "    options:",
+        "      path: /etc/grafana/dashboards",
+    ];
+    ConfigFile {
+        title: "grafana_dashboards".to_string(),
+        target: "/etc/grafana/provisioning/dashboards/woors.yml".to_string(),
+        content: content.join("\n") + "\n",
+    }
+}
+
+/// An overview dashboard with two panels per row, for the scraped targets.
+fn dashboard(targets: &[&Target]) -> ConfigFile {
+    let panels: Vec<serde_json::Value> = targets
+        .iter()
+        .flat_map(|target| target.panels.iter())
+        .enumerate()
+        .map(|(index, (title, expression, unit))| {
+            json!({
+                "id": index + 1,
+                "type": "timeseries",
+                "title": title,
+                "datasource": { "type": "prometheus", "uid": "prometheus" },
+                "gridPos": { "h": 8, "w": 12, "x": (index % 2) * 12, "y": (index / 2) * 8 },
+                "fieldConfig": { "defaults": { "unit": unit }, "overrides": [] },
+                "targets": [{ "refId": "A", "expr": expression }],
+            })
+        })
+        .collect();
+    let dashboard = json!({
+        "uid": "woors-overview",
+        "title": "woors overview",
+        "schemaVersion": 39,
+        "time": { "from": "now-6h", "to": "now" },
+        "refresh": "30s",
+        "panels": panels,
+    });
+    ConfigFile {
+        title: "grafana_overview".to_string(),
+        target: "/etc/grafana/dashboards/overview.json".to_string(),
+        content: serde_json::to_string_pretty(&dashboard).unwrap_or_default() + "\n",
+    }
+}
+
+fn grafana(targets: &[&Target], secrets: &mut Secrets) -> Service {
+    Service {
+        image: catalog_image("grafana"),
+        container_name: "grafana".to_string(),
+        ports: vec![
+            Port {
+                host: "3000".to_string(),
+                container: "3000".to_string()
+            }
+        ],
+        volumes: vec![
+            Mount {
+                kind: MountKind::Volume,
+                source: "grafana".to_string(),
+                target: "/var/lib/grafana".to_string(),
+                read_only: false
+            }
+        ],
+        networks: vec![
+            NetworkAttachment {
+                network: "web".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        environments: vec![
+            Environment::new("GF_SECURITY_ADMIN_USER", "admin"),
+            Environment::new("GF_SECURITY_ADMIN_PASSWORD", &secrets.password("grafana.admin_password")),
+            Environment::new("GF_USERS_ALLOW_SIGN_UP", "false"),
+        ],
+        restart: Policy {
+            condition: "always".to_string()
+        },
+        command: Command {
+            value: "".to_string()
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "Grafana Server".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.5),
+            memory: Some(256 * MIB),
+            reservation_memory: Some(64 * MIB),
+            ..Default::default()
+        },
+        depends_on: vec![
+            Dependency {
+                service: "prometheus".to_string(),
+                condition: None
+            }
+        ],
+        configs: vec![datasources(), dashboard_provider(), dashboard(targets)],
+        ..Default::default()
+    }
+}
+
+/// Host metrics, read from the host file systems mounted read-only.
+fn node_exporter() -> Service {
+    Service {
+        image: catalog_image("node-exporter"),
+        container_name: "node-exporter".to_string(),
+        ports: vec![],
+        volumes: vec![
+            Mount {
+                kind: MountKind::Bind,
+                source: "/proc".to_string(),
+                target: "/host/proc".to_string(),
+                read_only: true
+            },
+            Mount {
+                kind: MountKind::Bind,
+                source: "/sys".to_string(),
+                target: "/host/sys".to_string(),
+                read_only: true
+            },
+            Mount {
+                kind: MountKind::Bind,
+                source: "/".to_string(),
+                target: "/rootfs".to_string(),
+                read_only: true
+            }
+        ],
+        networks: vec![
+            NetworkAttachment {
+                network: "web".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        environments: vec![],
+        restart: Policy {
+            condition: "always".to_string()
+        },
+        command: Command {
+            value: "--path.procfs=/host/proc --path.sysfs=/host/sys --path.rootfs=/rootfs".to_string()
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "Node Exporter".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.25),
+            memory: Some(64 * MIB),
+            reservation_memory: Some(16 * MIB),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn mysqld_exporter(engine: Engine, secrets: &mut Secrets) -> Service {
+    Service {
+        image: catalog_image("mysqld-exporter"),
+        container_name: "mysqld-exporter".to_string(),
+        ports: vec![],
+        volumes: vec![],
+        networks: vec![
+            NetworkAttachment {
+                network: "backend".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        environments: vec![Environment::new("MYSQLD_EXPORTER_PASSWORD", &exporter_password(secrets))],
+        restart: Policy {
+            condition: "always".to_string()
+        },
+        command: Command {
+            value: format!("--mysqld.address={}:{} --mysqld.username=exporter", engine.service(), database::PORT)
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "MySQL Exporter".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.25),
+            memory: Some(64 * MIB),
+            reservation_memory: Some(16 * MIB),
+            ..Default::default()
+        },
+        depends_on: vec![
+            Dependency {
+                service: engine.service().to_string(),
+                condition: Some("service_healthy".to_string())
+            }
+        ],
+        ..Default::default()
+    }
+}
+
+fn redis_exporter(secrets: &mut Secrets) -> Service {
+    Service {
+        image: catalog_image("redis-exporter"),
+        container_name: "redis-exporter".to_string(),
+        ports: vec![],
+        volumes: vec![],
+        networks: vec![
+            NetworkAttachment {
+                network: "backend".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        environments: vec![
+            Environment::new("REDIS_ADDR", "redis://redis:6379"),
+            Environment::new("REDIS_PASSWORD", &secrets.password("redis.password")),
+        ],
+        restart: Policy {
+            condition: "always".to_string()
+        },
+        command: Command {
+            value: "".to_string()
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "Redis Exporter".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.25),
+            memory: Some(64 * MIB),
+            reservation_memory: Some(16 * MIB),
+            ..Default::default()
+        },
+        depends_on: vec![
+            Dependency {
+                service: "redis".to_string(),
+                condition: Some("service_healthy".to_string())
+            }
+        ],
+        ..Default::default()
+    }
+}
@@ -0,0 +1,707 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::catalog::{self, StackOptions};
+use crate::compose::{parse_compose, Compose};
+use crate::diff;
+use crate::secrets::Secrets;
+
+/// Default project store, relative to the current directory.
+pub const DATABASE: &str = "woors.db";
+
+/// Hex encoded 256-bit key of the secrets; overrides the key file.
+pub const KEY_VARIABLE: &str = "WOORS_SECRET_KEY";
+
+const SCHEMA: &str = "
+PRAGMA foreign_keys = ON;
+CREATE TABLE IF NOT EXISTS projects (
+    name TEXT PRIMARY KEY,
+    stack TEXT NOT NULL,
+    secrets BLOB NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS revisions (
+    project TEXT NOT NULL REFERENCES projects (name) ON DELETE CASCADE,
+    number INTEGER NOT NULL,
+    stack TEXT NOT NULL,
+    secrets BLOB NOT NULL,
+    version TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    compose BLOB NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (project, number)
+);
+";
+
+const NONCE_LENGTH: usize = 12;
+
+/// The version recorded in revisions.
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A saved stack definition, with the secrets generated for it.
+pub struct Project {
+    pub name: String,
+    pub stack: StackOptions,
+    pub secrets: Secrets,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// A project as the API and `woors project show` describe it; the secrets
+/// stay in the store.
+#[derive(Serialize, ToSchema)]
+pub struct Summary {
+    pub name: String,
+    pub stack: StackOptions,
+    /// Unix time, in seconds.
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// A render of a project, kept as it was: the inputs and secrets it was
+/// rendered from and the compose file, encrypted like the secrets.
+pub struct Revision {
+    pub number: u32,
+    pub stack: StackOptions,
+    pub secrets: Secrets,
+    /// The woors version that rendered it.
+    pub version: String,
+    /// SHA-256 of the compose file, in hex.
+    pub hash: String,
+    pub compose: String,
+    pub created_at: u64,
+}
+
+/// A revision as the API and `woors project revisions` list it.
+#[derive(Serialize, ToSchema)]
+pub struct RevisionSummary {
+    pub number: u32,
+    pub version: String,
+    pub hash: String,
+    /// Unix time, in seconds.
+    pub created_at: u64,
+}
+
+impl Revision {
+    pub fn summary(&self) -> RevisionSummary {
+        RevisionSummary {
+            number: self.number,
+            version: self.version.clone(),
+            hash: self.hash.clone(),
+            created_at: self.created_at,
+        }
+    }
+
+    /// Whether `compose` renders to the compose file of the revision.
+    pub fn renders(&self, compose: &Compose) -> bool {
+        hash(&parse_compose(compose)) == self.hash
+    }
+}
+
+impl Project {
+    pub fn summary(&self) -> Summary {
+        Summary {
+            name: self.name.clone(),
+            stack: self.stack.clone(),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        }
+    }
+}
+
+/// Projects in an SQLite file. Secrets are encrypted with AES-256-GCM.
+pub struct Store {
+    connection: Connection,
+    cipher: Aes256Gcm,
+}
+
+pub(crate) fn storage_error(err: rusqlite::Error) -> io::Error {
+    io::Error::other(err.to_string())
+}
+
+/// The S3 secret key of the backups is kept with the encrypted secrets,
+/// out of the stack columns.
+const S3_SECRET_KEY: &str = "backup.s3_secret_key";
+
+/// The stack as stored, without the S3 secret key.
+fn stack_column(stack: &StackOptions) -> io::Result<String> {
+    let mut stack = stack.clone();
+    stack.backup.s3_secret_key = None;
+    Ok(serde_json::to_string(&stack)?)
+}
+
+/// The stack of a row, with the S3 secret key back from its secrets.
+fn read_stack(column: &str, secrets: &Secrets) -> io::Result<StackOptions> {
+    let mut stack: StackOptions = serde_json::from_str(column)?;
+    if let Some(key) = secrets.get(S3_SECRET_KEY) {
+        stack.backup.s3_secret_key = Some(key.to_string());
+    }
+    Ok(stack)
+}
+
+/// Records the S3 secret key of `stack` in `secrets`, or forgets the one of
+/// a previous definition.
+fn keep_s3_secret_key(stack: &StackOptions, secrets: &mut Secrets) {
+    match &stack.backup.s3_secret_key {
+        Some(key) => secrets.insert(S3_SECRET_KEY, key),
+        None => secrets.remove(S3_SECRET_KEY),
+    }
+}
+
+/// SHA-256 of a compose file, in hex.
+fn hash(compose: &str) -> String {
+    encode_hex(&Sha256::digest(compose.as_bytes()))
+}
+
+fn not_found(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("no project named `{}`", name))
+}
+
+fn revision_not_found(name: &str, number: u32) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("project `{}` has no revision {}", name, number))
+}
+
+/// Stack errors come from the definition the project is saved with.
+fn invalid_stack(err: io::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err.to_string())
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Lowercase letters, digits, `-` and `_`, like the stack file names they
+/// end up in.
+fn validate_name(name: &str) -> io::Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !valid {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid project name `{}`: use up to 64 lowercase letters, digits, `-` and `_`", name),
+        ));
+    }
+    Ok(())
+}
+
+/// The key file of a store: `woors.key` next to `woors.db`.
+pub fn key_file(database: &Path) -> PathBuf {
+    database.with_extension("key")
+}
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    let value = value.trim();
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(value.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+/// The key of the secrets: [`KEY_VARIABLE`], or the key file of the store,
+/// generated on first use and only readable by its owner.
+fn key(database: &Path) -> io::Result<Key<Aes256Gcm>> {
+    let (value, origin) = match env::var(KEY_VARIABLE) {
+        Ok(value) => (value, KEY_VARIABLE.to_string()),
+        Err(_) => {
+            let file = key_file(database);
+            let value = match fs::read_to_string(&file) {
+                Ok(value) => value,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    let value = encode_hex(&Aes256Gcm::generate_key(OsRng));
+                    let mut options = fs::OpenOptions::new();
+                    options.write(true).create_new(true);
+                    #[cfg(unix)]
+                    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+                    options.open(&file)?.write_all(format!("{}\n", value).as_bytes())?;
+                    value
+                }
+                Err(err) => return Err(err),
+            };
+            (value, file.display().to_string())
+        }
+    };
+    match decode_hex(&value) {
+        Some(bytes) if bytes.len() == 32 => Ok(*Key::<Aes256Gcm>::from_slice(&bytes)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: expected a key of 64 hexadecimal digits", origin),
+        )),
+    }
+}
+
+impl Store {
+    /// Opens the store at `path`, creating it and its key when missing.
+    pub fn open(path: &Path) -> io::Result<Store> {
+        let cipher = Aes256Gcm::new(&key(path)?);
+        let connection = Connection::open(path).map_err(storage_error)?;
+        connection.execute_batch(SCHEMA).map_err(storage_error)?;
+        Ok(Store { connection, cipher })
+    }
+
+    fn encrypt(&self, secrets: &Secrets) -> io::Result<Vec<u8>> {
+        self.seal(&serde_json::to_vec(secrets)?)
+    }
+
+    fn decrypt(&self, name: &str, blob: &[u8]) -> io::Result<Secrets> {
+        Ok(serde_json::from_slice(&self.unseal(name, blob)?)?)
+    }
+
+    fn seal(&self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| io::Error::other("cannot encrypt the secrets"))?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    fn unseal(&self, name: &str, blob: &[u8]) -> io::Result<Vec<u8>> {
+        let undecryptable = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("cannot decrypt the secrets of `{}`; is the key the one they were saved with?", name),
+            )
+        };
+        if blob.len() < NONCE_LENGTH {
+            return Err(undecryptable());
+        }
+        let (nonce, ciphertext) = blob.split_at(NONCE_LENGTH);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| undecryptable())
+    }
+
+    /// Fails unless the database answers.
+    pub fn check(&self) -> io::Result<()> {
+        self.connection
+            .query_row("SELECT COUNT(*) FROM projects", [], |row| row.get::<_, u64>(0))
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    /// Every project, by name.
+    pub fn list(&self) -> io::Result<Vec<Summary>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT name, stack, secrets, created_at, updated_at FROM projects ORDER BY name")
+            .map_err(storage_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .map_err(storage_error)?;
+        let mut projects = vec![];
+        for row in rows {
+            let (name, stack, secrets, created_at, updated_at) = row.map_err(storage_error)?;
+            let secrets = self.decrypt(&name, &secrets)?;
+            projects.push(Summary {
+                stack: read_stack(&stack, &secrets)?,
+                name,
+                created_at,
+                updated_at,
+            });
+        }
+        Ok(projects)
+    }
+
+    pub fn get(&self, name: &str) -> io::Result<Project> {
+        let row = self
+            .connection
+            .query_row(
+                "SELECT stack, secrets, created_at, updated_at FROM projects WHERE name = ?1",
+                params![name],
+                |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?, row.get(2)?, row.get(3)?))
+                },
+            )
+            .optional()
+            .map_err(storage_error)?;
+        let (stack, secrets, created_at, updated_at) = row.ok_or_else(|| not_found(name))?;
+        let secrets = self.decrypt(name, &secrets)?;
+        Ok(Project {
+            name: name.to_string(),
+            stack: read_stack(&stack, &secrets)?,
+            secrets,
+            created_at,
+            updated_at,
+        })
+    }
+
+    /// Saves a new project and generates its secrets.
+    pub fn create(&self, name: &str, stack: StackOptions) -> io::Result<Project> {
+        validate_name(name)?;
+        let mut secrets = Secrets::default();
+        catalog::stack(&stack, &mut secrets).map_err(invalid_stack)?;
+        keep_s3_secret_key(&stack, &mut secrets);
+        let time = now();
+        let inserted = self
+            .connection
+            .execute(
+                "INSERT INTO projects (name, stack, secrets, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?4) ON CONFLICT (name) DO NOTHING",
+                params![name, stack_column(&stack)?, self.encrypt(&secrets)?, time],
+            )
+            .map_err(storage_error)?;
+        if inserted == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("a project named `{}` already exists", name),
+            ));
+        }
+        Ok(Project {
+            name: name.to_string(),
+            stack,
+            secrets,
+            created_at: time,
+            updated_at: time,
+        })
+    }
+
+    /// Replaces the stack definition of a project. Its secrets are kept, and
+    /// those of newly selected services generated.
+    pub fn update(&self, name: &str, stack: StackOptions) -> io::Result<Project> {
+        let mut project = self.get(name)?;
+        keep_s3_secret_key(&stack, &mut project.secrets);
+        catalog::stack(&stack, &mut project.secrets).map_err(invalid_stack)?;
+        project.stack = stack;
+        project.updated_at = now();
+        self.connection
+            .execute(
+                "UPDATE projects SET stack = ?2, secrets = ?3, updated_at = ?4 WHERE name = ?1",
+                params![
+                    name,
+                    stack_column(&project.stack)?,
+                    self.encrypt(&project.secrets)?,
+                    project.updated_at
+                ],
+            )
+            .map_err(storage_error)?;
+        Ok(project)
+    }
+
+    pub fn delete(&self, name: &str) -> io::Result<()> {
+        let deleted = self
+            .connection
+            .execute("DELETE FROM projects WHERE name = ?1", params![name])
+            .map_err(storage_error)?;
+        if deleted == 0 {
+            return Err(not_found(name));
+        }
+        Ok(())
+    }
+
+    /// Renders the stack of a project with its saved secrets, so it is the
+    /// same every time, and records it as a revision unless it is the same
+    /// as the latest one. Returns the revision and the rendered model.
+    pub fn render(&self, name: &str) -> io::Result<(Revision, Compose)> {
+        self.record(name, true)
+    }
+
+    /// Renders the stack of a project and records it as a new revision,
+    /// unless `reuse` and it is the same as the latest one.
+    fn record(&self, name: &str, reuse: bool) -> io::Result<(Revision, Compose)> {
+        let mut project = self.get(name)?;
+        let saved = project.secrets.iter().count();
+        let model = catalog::stack(&project.stack, &mut project.secrets).map_err(invalid_stack)?;
+        let compose = parse_compose(&model);
+        // A newer catalog may need secrets the project was not saved with.
+        if project.secrets.iter().count() != saved {
+            self.connection
+                .execute(
+                    "UPDATE projects SET secrets = ?2 WHERE name = ?1",
+                    params![name, self.encrypt(&project.secrets)?],
+                )
+                .map_err(storage_error)?;
+        }
+
+        let hash = hash(&compose);
+        let latest: Option<u32> = self
+            .connection
+            .query_row("SELECT MAX(number) FROM revisions WHERE project = ?1", params![name], |row| row.get(0))
+            .map_err(storage_error)?;
+        if let Some(latest) = latest.filter(|_| reuse) {
+            let revision = self.revision(name, latest)?;
+            if revision.hash == hash && revision.version == VERSION {
+                return Ok((revision, model));
+            }
+        }
+        let revision = Revision {
+            number: latest.unwrap_or(0) + 1,
+            stack: project.stack,
+            secrets: project.secrets,
+            version: VERSION.to_string(),
+            hash,
+            compose,
+            created_at: now(),
+        };
+        self.connection
+            .execute(
+                "INSERT INTO revisions (project, number, stack, secrets, version, hash, compose, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    name,
+                    revision.number,
+                    stack_column(&revision.stack)?,
+                    self.encrypt(&revision.secrets)?,
+                    revision.version,
+                    revision.hash,
+                    self.seal(revision.compose.as_bytes())?,
+                    revision.created_at
+                ],
+            )
+            .map_err(storage_error)?;
+        Ok((revision, model))
+    }
+
+    /// Renders the stack of a project like [`Store::render`], but neither
+    /// records a revision nor saves the secrets it needs. Returns the project,
+    /// with the secrets of the stack, and the rendered model.
+    pub fn preview(&self, name: &str) -> io::Result<(Project, Compose)> {
+        let mut project = self.get(name)?;
+        let compose = catalog::stack(&project.stack, &mut project.secrets).map_err(invalid_stack)?;
+        Ok((project, compose))
+    }
+
+    /// The revisions of a project, oldest first.
+    pub fn revisions(&self, name: &str) -> io::Result<Vec<RevisionSummary>> {
+        self.get(name)?;
+        let mut statement = self
+            .connection
+            .prepare("SELECT number, version, hash, created_at FROM revisions WHERE project = ?1 ORDER BY number")
+            .map_err(storage_error)?;
+        let rows = statement
+            .query_map(params![name], |row| {
+                Ok(RevisionSummary {
+                    number: row.get(0)?,
+                    version: row.get(1)?,
+                    hash: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })
+            .map_err(storage_error)?;
+        rows.collect::<Result<_, _>>().map_err(storage_error)
+    }
+
+    pub fn revision(&self, name: &str, number: u32) -> io::Result<Revision> {
+        let row = self
+            .connection
+            .query_row(
+                "SELECT stack, secrets, version, hash, compose, created_at FROM revisions
+                 WHERE project = ?1 AND number = ?2",
+                params![name, number],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Vec<u8>>(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get::<_, Vec<u8>>(4)?,
+                        row.get(5)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(storage_error)?;
+        let (stack, secrets, version, hash, compose, created_at) = row.ok_or_else(|| {
+            match self.get(name) {
+                Ok(_) => revision_not_found(name, number),
+                Err(err) => err,
+            }
+        })?;
+        let compose = String::from_utf8(self.unseal(name, &compose)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let secrets = self.decrypt(name, &secrets)?;
+        Ok(Revision {
+            number,
+            stack: read_stack(&stack, &secrets)?,
+            secrets,
+            version,
+            hash,
+            compose,
+            created_at,
+        })
+    }
+
+    /// Renders a revision again, with the current catalog. The result only
+    /// differs from the recorded compose file when woors changed since.
+    /// Returns the revision, with the secrets of the new stack, and the new
+    /// model.
+    pub fn rerender(&self, name: &str, number: u32) -> io::Result<(Revision, Compose)> {
+        let mut revision = self.revision(name, number)?;
+        let compose = catalog::stack(&revision.stack, &mut revision.secrets).map_err(invalid_stack)?;
+        Ok((revision, compose))
+    }
+
+    /// A unified diff of the compose files of two revisions.
+    pub fn diff(&self, name: &str, from: u32, to: u32) -> io::Result<String> {
+        let old = self.revision(name, from)?;
+        let new = self.revision(name, to)?;
+        Ok(diff::unified(
+            &old.compose,
+            &new.compose,
+            &format!("{}@{}", name, from),
+            &format!("{}@{}", name, to),
+        ))
+    }
+
+    /// Restores the inputs and secrets of a revision as the project
+    /// definition, and renders it as a new revision.
+    pub fn rollback(&self, name: &str, number: u32) -> io::Result<Revision> {
+        let revision = self.revision(name, number)?;
+        self.connection
+            .execute(
+                "UPDATE projects SET stack = ?2, secrets = ?3, updated_at = ?4 WHERE name = ?1",
+                params![
+                    name,
+                    stack_column(&revision.stack)?,
+                    self.encrypt(&revision.secrets)?,
+                    now()
+                ],
+            )
+            .map_err(storage_error)?;
+        Ok(self.record(name, false)?.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::Target;
+
+    /// An in-memory store, so the tests need neither a key file nor
+    /// [`KEY_VARIABLE`].
+    fn store() -> Store {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(SCHEMA).unwrap();
+        Store {
+            connection,
+            cipher: Aes256Gcm::new(&Aes256Gcm::generate_key(OsRng)),
+        }
+    }
+
+    fn s3_stack() -> StackOptions {
+        let mut stack = StackOptions {
+            services: vec!["wordpress".to_string(), "backup".to_string()],
+            ..Default::default()
+        };
+        stack.backup.target = Target::S3 {
+            bucket: "bucket".to_string(),
+            path: None,
+        };
+        stack.backup.s3_secret_key = Some("s3-secret-key-value".to_string());
+        stack
+    }
+
+    #[test]
+    fn secrets_round_trip() {
+        let store = store();
+        let mut secrets = Secrets::default();
+        let password = secrets.password("mysql.password");
+        let blob = store.encrypt(&secrets).unwrap();
+        assert!(!blob.windows(password.len()).any(|window| window == password.as_bytes()));
+        assert_eq!(store.decrypt("shop", &blob).unwrap().get("mysql.password"), Some(password.as_str()));
+    }
+
+    #[test]
+    fn projects_keep_their_secrets_encrypted() {
+        let store = store();
+        let created = store.create("shop", s3_stack()).unwrap();
+        let project = store.get("shop").unwrap();
+        assert_eq!(
+            project.secrets.iter().collect::<Vec<_>>(),
+            created.secrets.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(project.stack.backup.s3_secret_key.as_deref(), Some("s3-secret-key-value"));
+        let column: String = store
+            .connection
+            .query_row("SELECT stack FROM projects WHERE name = 'shop'", [], |row| row.get(0))
+            .unwrap();
+        assert!(!column.contains("s3-secret-key-value"));
+    }
+
+    #[test]
+    fn another_key_cannot_read_the_secrets() {
+        let mut store = store();
+        store.create("shop", StackOptions::default()).unwrap();
+        store.cipher = Aes256Gcm::new(&Aes256Gcm::generate_key(OsRng));
+        let err = store.get("shop").err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("cannot decrypt the secrets of `shop`"));
+        assert_eq!(store.decrypt("shop", b"short").err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn render_reuses_the_latest_revision_when_nothing_changed() {
+        let store = store();
+        store.create("shop", StackOptions::default()).unwrap();
+        let first = store.render("shop").unwrap().0;
+        let again = store.render("shop").unwrap().0;
+        assert_eq!((first.number, again.number), (1, 1));
+        assert_eq!(first.hash, again.hash);
+
+        let stack = StackOptions {
+            services: vec!["wordpress".to_string()],
+            ..Default::default()
+        };
+        store.update("shop", stack).unwrap();
+        assert_eq!(store.render("shop").unwrap().0.number, 2);
+        assert_eq!(store.revisions("shop").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn rollback_records_a_new_revision() {
+        let store = store();
+        store.create("shop", StackOptions::default()).unwrap();
+        let first = store.render("shop").unwrap().0;
+        // Rolling back to the latest revision still records one.
+        assert_eq!(store.rollback("shop", 1).unwrap().number, 2);
+
+        let stack = StackOptions {
+            services: vec!["wordpress".to_string()],
+            ..Default::default()
+        };
+        store.update("shop", stack).unwrap();
+        store.render("shop").unwrap();
+        let rolled_back = store.rollback("shop", 1).unwrap();
+        assert_eq!(rolled_back.number, 4);
+        assert_eq!(rolled_back.hash, first.hash);
+        assert_eq!(rolled_back.compose, first.compose);
+        assert!(store.get("shop").unwrap().stack.services.is_empty());
+        assert_eq!(store.diff("shop", 1, 4).unwrap(), "");
+    }
+
+    #[test]
+    fn rollback_needs_an_existing_revision() {
+        let store = store();
+        store.create("shop", StackOptions::default()).unwrap();
+        assert_eq!(store.rollback("shop", 1).err().unwrap().kind(), io::ErrorKind::NotFound);
+        assert_eq!(store.rollback("cafe", 1).err().unwrap().kind(), io::ErrorKind::NotFound);
+    }
+}
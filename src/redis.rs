@@ -0,0 +1,83 @@
+use crate::compose::{ConfigFile, Environment};
+use crate::resources::{format_size, MIB};
+use crate::secrets::Secrets;
+
+/// Where the generated `redis.conf` is mounted.
+pub const CONFIG_PATH: &str = "/usr/local/etc/redis/redis.conf";
+
+/// Memory limit of the `redis` container.
+pub const MEMORY_LIMIT: u64 = 256 * MIB;
+
+/// The `redis.conf` of the catalog entry, with the generated password, so it
+/// is neither on the command line nor in the environment of the container.
+/// `maxmemory` stays below the container limit so Redis evicts keys before
+/// the kernel kills it.
+pub fn config(secrets: &mut Secrets) -> ConfigFile {
+    let content = [
+        format!("requirepass {}", secrets.password("redis.password")),
+        format!("maxmemory {}", format_size(MEMORY_LIMIT / 4 * 3)),
+        "maxmemory-policy allkeys-lru".to_string(),
+        "appendonly yes".to_string(),
+        "appendfsync everysec".to_string(),
+        "save 900 1".to_string(),
+    ];
+    ConfigFile {
+        title: "redis_conf".to_string(),
+        target: CONFIG_PATH.to_string(),
+        content: content.join("\n") + "\n",
+    }
+}
+
+/// The command of the catalog entry, reading the generated `redis.conf`.
+pub fn command() -> String {
+    format!("redis-server {}", CONFIG_PATH)
+}
+
+/// Pings the server with the password of the generated `redis.conf`.
+pub fn healthcheck() -> String {
+    format!(
+        "REDISCLI_AUTH=\"$(sed -n 's/^requirepass //p' {})\" redis-cli ping | grep -q PONG",
+        CONFIG_PATH
+    )
+}
+
+/// The environment WordPress needs to reach Redis.
+pub fn wordpress_environment(secrets: &mut Secrets) -> Vec<Environment> {
+    vec![
+        Environment::new("WP_REDIS_HOST", "redis"),
+        Environment::new("WP_REDIS_PASSWORD", &secrets.password("redis.password")),
+    ]
+}
+
+/// `wp-config.php` constants of the Redis Object Cache plugin.
+pub fn wordpress_config() -> Vec<String> {
+    vec![
+        "define('WP_CACHE', true);".to_string(),
+        "define('WP_REDIS_HOST', getenv('WP_REDIS_HOST'));".to_string(),
+        "define('WP_REDIS_PASSWORD', getenv('WP_REDIS_PASSWORD'));".to_string(),
+    ]
+}
+
+/// WP-CLI steps installing and enabling the Redis Object Cache plugin.
+pub fn wpcli_steps() -> Vec<String> {
+    vec![
+        "wp plugin is-installed redis-cache || wp plugin install redis-cache".to_string(),
+        "wp plugin activate redis-cache".to_string(),
+        "wp redis status | grep -q 'Status: Connected' || wp redis enable --force".to_string(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_password_is_only_in_the_config() {
+        let mut secrets = Secrets::default();
+        let config = config(&mut secrets);
+        let password = secrets.get("redis.password").unwrap();
+        assert!(config.content.starts_with(&format!("requirepass {}\n", password)));
+        assert!(!command().contains(password));
+        assert!(!healthcheck().contains(password));
+    }
+}
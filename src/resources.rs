@@ -0,0 +1,131 @@
+use std::io;
+
+use crate::compose::Compose;
+
+pub const MIB: u64 = 1024 * 1024;
+pub const GIB: u64 = 1024 * MIB;
+
+/// Parses a byte size such as `512M`, `4G` or `4gb` (binary units).
+pub fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim().to_ascii_lowercase();
+    let value = value.strip_suffix('b').unwrap_or(&value);
+    let (number, unit) = match value.char_indices().find(|(_, c)| c.is_ascii_alphabetic()) {
+        Some((index, _)) => value.split_at(index),
+        None => (value, ""),
+    };
+    let multiplier = match unit {
+        "" => 1,
+        "k" => 1024,
+        "m" => MIB,
+        "g" => GIB,
+        "t" => 1024 * GIB,
+        _ => return None,
+    };
+    let number: f64 = number.trim().parse().ok()?;
+    if number < 0.0 {
+        return None;
+    }
+    Some((number * multiplier as f64) as u64)
+}
+
+/// Formats a byte size with the largest unit that divides it, in the
+/// lowercase form compose expects (`512m`, `4g`).
+pub fn format_size(bytes: u64) -> String {
+    for (unit, size) in [("g", GIB), ("m", MIB), ("k", 1024)] {
+        if bytes >= size && bytes.is_multiple_of(size) {
+            return format!("{}{}", bytes / size, unit);
+        }
+    }
+    format!("{}b", bytes)
+}
+
+/// The outcome of checking a stack against the host memory budget.
+pub struct Budget {
+    pub reserved: u64,
+    pub limited: u64,
+    pub host_memory: u64,
+}
+
+impl Budget {
+    pub fn new(compose: &Compose, host_memory: u64) -> Budget {
+        let mut reserved = 0;
+        let mut limited = 0;
+        for service in &compose.services {
+            reserved += service.resources.reservation_memory.unwrap_or(0);
+            limited += service.resources.memory.unwrap_or(0);
+        }
+        Budget {
+            reserved,
+            limited,
+            host_memory,
+        }
+    }
+
+    /// Reservations are guaranteed to the containers: exceeding the host
+    /// memory means the stack cannot start reliably.
+    pub fn check(&self) -> io::Result<()> {
+        if self.reserved > self.host_memory {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the selected services reserve {} but the host only has {}",
+                    format_size(self.reserved),
+                    format_size(self.host_memory)
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Limits may overcommit the host; that is allowed but worth a warning.
+    pub fn warning(&self) -> Option<String> {
+        if self.limited > self.host_memory {
+            return Some(format!(
+                "memory limits add up to {}, more than the {} of the host",
+                format_size(self.limited),
+                format_size(self.host_memory)
+            ));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_reads_binary_units() {
+        assert_eq!(parse_size("1024"), Some(1024));
+        assert_eq!(parse_size("64k"), Some(64 * 1024));
+        assert_eq!(parse_size("512M"), Some(512 * MIB));
+        assert_eq!(parse_size("4g"), Some(4 * GIB));
+        assert_eq!(parse_size("4gb"), Some(4 * GIB));
+        assert_eq!(parse_size(" 1.5G "), Some(1536 * MIB));
+        assert_eq!(parse_size("1t"), Some(1024 * GIB));
+    }
+
+    #[test]
+    fn parse_size_rejects_garbage() {
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("4x"), None);
+        assert_eq!(parse_size("mb"), None);
+        assert_eq!(parse_size("-1g"), None);
+    }
+
+    #[test]
+    fn format_size_uses_the_largest_exact_unit() {
+        assert_eq!(format_size(4 * GIB), "4g");
+        assert_eq!(format_size(1536 * MIB), "1536m");
+        assert_eq!(format_size(64 * 1024), "64k");
+        assert_eq!(format_size(1000), "1000b");
+        assert_eq!(format_size(0), "0b");
+    }
+
+    #[test]
+    fn format_size_round_trips() {
+        for bytes in [1, 1024, 3 * MIB, 2 * GIB, 1536 * MIB] {
+            assert_eq!(parse_size(&format_size(bytes)), Some(bytes));
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+
+use rand::distributions::{Alphanumeric, Uniform};
+use rand::rngs::OsRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Characters of WordPress keys and salts. Leaves out quotes, backslashes
+/// and `$`, which compose would try to interpolate.
+const SALT_CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789!#%&()*+,-./:;<=>?@[]^_{|}~";
+
+/// What masked secrets are replaced with.
+pub const MASK: &str = "********";
+
+/// Values shorter than this, such as the table prefix, are identifiers
+/// rather than credentials, and too short to be masked reliably.
+const MASKED_LENGTH: usize = 8;
+
+/// The generated credentials of a stack, by name.
+///
+/// A value is generated the first time it is asked for and reused after
+/// that, so rendering the same stack twice with the same `Secrets` yields
+/// identical files.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Secrets {
+    values: BTreeMap<String, String>,
+}
+
+impl Secrets {
+    /// A 32 character alphanumeric password.
+    pub fn password(&mut self, name: &str) -> String {
+        self.generate(name, || {
+            OsRng.sample_iter(&Alphanumeric).take(32).map(char::from).collect()
+        })
+    }
+
+    /// A 64 character WordPress key or salt.
+    pub fn salt(&mut self, name: &str) -> String {
+        self.generate(name, || {
+            let index = Uniform::from(0..SALT_CHARSET.len());
+            (0..64).map(|_| SALT_CHARSET[OsRng.sample(index)] as char).collect()
+        })
+    }
+
+    /// `length` random lowercase letters and digits.
+    pub fn token(&mut self, name: &str, length: usize) -> String {
+        self.generate(name, || {
+            OsRng
+                .sample_iter(&Alphanumeric)
+                .take(length)
+                .map(|c| (c as char).to_ascii_lowercase())
+                .collect()
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    pub fn insert(&mut self, name: &str, value: &str) {
+        self.values.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.values.remove(name);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// `text` with every credential replaced by [`MASK`].
+    pub fn mask(&self, text: &str) -> String {
+        let mut values: Vec<&str> = self
+            .values
+            .values()
+            .map(String::as_str)
+            .filter(|value| value.len() >= MASKED_LENGTH)
+            .collect();
+        // Longest first, so a value containing another is masked whole.
+        values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+        values.iter().fold(text.to_string(), |text, value| text.replace(value, MASK))
+    }
+
+    fn generate(&mut self, name: &str, generate: impl FnOnce() -> String) -> String {
+        self.values.entry(name.to_string()).or_insert_with(generate).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_replaces_credentials() {
+        let mut secrets = Secrets::default();
+        let password = secrets.password("mysql.password");
+        let text = format!("MYSQL_PASSWORD={}\nDB_PASSWORD={}\n", password, password);
+        assert_eq!(secrets.mask(&text), format!("MYSQL_PASSWORD={}\nDB_PASSWORD={}\n", MASK, MASK));
+    }
+
+    #[test]
+    fn mask_keeps_short_values() {
+        let mut secrets = Secrets::default();
+        secrets.insert("wordpress.table_prefix", "wp_");
+        assert_eq!(secrets.mask("TABLE_PREFIX=wp_"), "TABLE_PREFIX=wp_");
+    }
+
+    #[test]
+    fn mask_replaces_the_longest_value_first() {
+        let mut secrets = Secrets::default();
+        secrets.insert("short", "abcdefgh");
+        secrets.insert("long", "abcdefgh12345678");
+        assert_eq!(secrets.mask("a=abcdefgh12345678 b=abcdefgh"), format!("a={} b={}", MASK, MASK));
+    }
+}
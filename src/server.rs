@@ -0,0 +1,1179 @@
+use std::fmt;
+use std::fs::File;
+use std::future::{ready, Ready};
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+use actix_web::body::MessageBody;
+use actix_web::http::header;
+use actix_web::http::StatusCode;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::middleware::{from_fn, Next};
+use actix_web::{web, App, FromRequest, HttpRequest, HttpResponse, HttpServer, ResponseError};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityRequirement, SecurityScheme};
+use utoipa::openapi::{Content, OpenApi as Document, Ref, ResponseBuilder};
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::backup::BackupOptions;
+use crate::catalog::{self, Entry, Needs, Preset, StackOptions, Variable};
+use crate::config::{Config, LogLevel};
+use crate::database::Engine;
+use crate::logging::LoggingOptions;
+use crate::project::{encode_hex, RevisionSummary, Store, Summary};
+use crate::woocommerce::Shop;
+use crate::compose::{compose_json, parse_compose, Compose};
+use crate::secrets::{Secrets, MASK};
+use crate::site::{Profile, Site};
+use crate::telemetry::{Logger, Metrics};
+use crate::token::{Caller, Role, Tokens};
+
+/// Media type of the rendered compose files.
+const YAML: &str = "application/yaml";
+
+/// The OpenAPI document of the HTTP API, served at `/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "woors", description = "Generates docker-compose stacks for WordPress sites"),
+    paths(
+        docker_compose,
+        render_stack,
+        list_projects,
+        create_project,
+        show_project,
+        update_project,
+        delete_project,
+        render_project,
+        list_revisions,
+        show_revision,
+        rerender_revision,
+        rollback_revision,
+        diff_revisions,
+        list_catalog,
+        show_catalog,
+        healthz,
+        readyz,
+        metrics
+    ),
+    components(schemas(
+        SiteRequest, Profile, ApiError, StackOptions, Site, Engine, Preset, Shop, BackupOptions, LoggingOptions,
+        ProjectRequest, Summary, RevisionSummary, Health, Entry, Variable, Needs
+    )),
+    modifiers(&Unlicensed, &Secured)
+)]
+pub struct ApiDoc;
+
+/// Drops the empty license utoipa derives from the package, which has none.
+struct Unlicensed;
+
+impl Modify for Unlicensed {
+    fn modify(&self, openapi: &mut Document) {
+        openapi.info.license = None;
+    }
+}
+
+/// Requires a bearer token on every operation but those declaring their
+/// own security.
+struct Secured;
+
+impl Modify for Secured {
+    fn modify(&self, openapi: &mut Document) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        let mut bearer = Http::new(HttpAuthScheme::Bearer);
+        bearer.description = Some("An API token from `woors token create`".to_string());
+        components.add_security_scheme("bearer", SecurityScheme::Http(bearer));
+        openapi.security = Some(vec![SecurityRequirement::new("bearer", Vec::<String>::new())]);
+
+        let unauthorized = ResponseBuilder::new()
+            .description("No token, or an unknown or revoked one")
+            .content("application/json", Content::new(Some(Ref::from_schema_name("ApiError"))))
+            .build();
+        for item in openapi.paths.paths.values_mut() {
+            let operations = [&mut item.get, &mut item.post, &mut item.put, &mut item.delete];
+            for operation in operations.into_iter().flatten().filter(|operation| operation.security.is_none()) {
+                operation.responses.responses.insert("401".to_string(), unauthorized.clone().into());
+            }
+        }
+    }
+}
+
+/// The body of every error of `/api/v1`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiError {
+    /// The HTTP status code.
+    pub status: u16,
+    /// The reason phrase of the status, e.g. `Unprocessable Entity`.
+    pub error: String,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: &str) -> ApiError {
+        ApiError {
+            status: status.as_u16(),
+            error: status.canonical_reason().unwrap_or_default().to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if self.status == StatusCode::UNAUTHORIZED.as_u16() {
+            response.insert_header((header::WWW_AUTHENTICATE, "Bearer realm=\"woors\""));
+        }
+        response.json(self)
+    }
+}
+
+impl From<io::Error> for ApiError {
+    fn from(err: io::Error) -> ApiError {
+        let status = match err.kind() {
+            io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            io::ErrorKind::AlreadyExists => StatusCode::CONFLICT,
+            io::ErrorKind::InvalidInput => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError::new(status, &err.to_string())
+    }
+}
+
+type Auth = web::Data<Mutex<Tokens>>;
+
+impl FromRequest for Caller {
+    type Error = ApiError;
+    type Future = Ready<Result<Caller, ApiError>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(authenticate(request))
+    }
+}
+
+/// The bearer of the token of the `Authorization` header.
+fn authenticate(request: &HttpRequest) -> Result<Caller, ApiError> {
+    let unauthorized = |message: &str| ApiError::new(StatusCode::UNAUTHORIZED, message);
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim())
+        .ok_or_else(|| unauthorized("an `Authorization: Bearer <token>` header is required"))?;
+    let tokens = request
+        .app_data::<Auth>()
+        .and_then(|tokens| tokens.lock().ok())
+        .ok_or_else(|| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "the token store is unavailable"))?;
+    tokens
+        .authenticate(token)?
+        .ok_or_else(|| unauthorized("unknown or revoked token"))
+}
+
+/// Fails with 403 unless the caller has `role`.
+fn authorize(caller: &Caller, role: Role) -> Result<(), ApiError> {
+    if caller.role < role {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            &format!("the token `{}` is {}; this needs an {} token", caller.name, caller.role.as_str(), role.as_str()),
+        ));
+    }
+    Ok(())
+}
+
+/// A rendered text as the caller may see it: whole for admins, with the
+/// secrets masked otherwise.
+fn reveal(caller: &Caller, text: String, secrets: &Secrets) -> String {
+    match caller.role {
+        Role::Admin => text,
+        Role::ReadOnly => secrets.mask(&text),
+    }
+}
+
+/// A project as the caller may see it. The S3 secret key is the only
+/// credential a stack definition holds.
+fn summarize(caller: &Caller, mut summary: Summary) -> Summary {
+    if caller.role < Role::Admin && summary.stack.backup.s3_secret_key.is_some() {
+        summary.stack.backup.s3_secret_key = Some(MASK.to_string());
+    }
+    summary
+}
+
+/// The JSON body of `/dc`. Every field is optional.
+#[derive(Deserialize, ToSchema)]
+#[schema(example = json!({"site_title": "mydemowebsite", "site_url": "mydemowebsite.com", "site_profile": "dev"}))]
+pub struct SiteRequest {
+    /// Names the WordPress database and its user; defaults to `woors`
+    pub site_title: Option<String>,
+    /// Host name of the site, e.g. `shop.com`; defaults to `example.com`
+    pub site_url: Option<String>,
+    pub site_profile: Option<Profile>,
+}
+
+impl SiteRequest {
+    /// Parses a request body; an empty body describes the default site.
+    pub fn parse(body: &[u8]) -> serde_json::Result<Site> {
+        let mut site = Site::default();
+        if body.iter().all(u8::is_ascii_whitespace) {
+            return Ok(site);
+        }
+        let request: SiteRequest = serde_json::from_slice(body)?;
+        if let Some(title) = request.site_title {
+            site.title = title;
+        }
+        if let Some(url) = request.site_url {
+            site.url = url;
+        }
+        if let Some(profile) = request.site_profile {
+            site.profile = profile;
+        }
+        Ok(site)
+    }
+}
+
+/// Generates the default services for a site, with new secrets, masked for
+/// read-only tokens.
+///
+/// The body is optional; `GET` is accepted too. Superseded by
+/// `POST /api/v1/stacks/render`.
+#[utoipa::path(
+    post,
+    path = "/dc",
+    tag = "stacks",
+    request_body(content = Option<SiteRequest>, content_type = "application/json"),
+    responses(
+        (status = 200, description = "The docker-compose file", body = String, content_type = "application/yaml"),
+        (status = 400, description = "The body is not a valid site", body = String, content_type = "text/plain")
+    )
+)]
+async fn docker_compose(caller: Caller, metrics: Counters, body: web::Bytes) -> HttpResponse {
+    let site = match SiteRequest::parse(&body) {
+        Ok(site) => site,
+        Err(err) => return HttpResponse::BadRequest().body(err.to_string()),
+    };
+    let options = StackOptions {
+        site,
+        ..StackOptions::default()
+    };
+    let mut secrets = Secrets::default();
+    match catalog::stack(&options, &mut secrets) {
+        Ok(compose) => {
+            count_render(&metrics, options.site.profile, &compose);
+            HttpResponse::Ok().content_type(YAML).body(reveal(&caller, parse_compose(&compose), &secrets))
+        }
+        Err(err) => HttpResponse::BadRequest().body(err.to_string()),
+    }
+}
+
+/// How a response is rendered, from the `Accept` header of the request.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Format {
+    Yaml,
+    Json,
+}
+
+impl Format {
+    /// The media types the format is served as.
+    fn media_types(self) -> &'static [&'static str] {
+        match self {
+            Format::Yaml => &["application/yaml", "application/x-yaml", "text/yaml"],
+            Format::Json => &["application/json"],
+        }
+    }
+
+    /// The quality of the most specific range of `ranges` matching the
+    /// format, with the position of that range.
+    fn quality(self, ranges: &[(String, f32)]) -> Option<(f32, usize)> {
+        let mut best: Option<(u8, f32, usize)> = None;
+        for (index, (range, quality)) in ranges.iter().enumerate() {
+            let specificity = if self.media_types().contains(&range.as_str()) {
+                2
+            } else if range.strip_suffix("/*").is_some_and(|kind| {
+                self.media_types().iter().any(|media_type| media_type.split('/').next() == Some(kind))
+            }) {
+                1
+            } else if range == "*/*" {
+                0
+            } else {
+                continue;
+            };
+            if best.is_none_or(|(best, _, _)| specificity > best) {
+                best = Some((specificity, *quality, index));
+            }
+        }
+        best.map(|(_, quality, index)| (quality, index))
+    }
+
+    /// The format `Accept` gives the highest quality, the first listed
+    /// among equals. Ranges with `q=0` refuse a format; YAML is the default.
+    fn negotiate(request: &HttpRequest) -> Result<Format, ApiError> {
+        let Some(accept) = request.headers().get(header::ACCEPT) else {
+            return Ok(Format::Yaml);
+        };
+        let accept = accept.to_str().unwrap_or_default();
+        let ranges: Vec<(String, f32)> = accept.split(',').filter_map(media_range).collect();
+        if ranges.is_empty() {
+            return Ok(Format::Yaml);
+        }
+        [Format::Yaml, Format::Json]
+            .into_iter()
+            .filter_map(|format| format.quality(&ranges).map(|(quality, index)| (format, quality, index)))
+            .filter(|(_, quality, _)| *quality > 0.0)
+            .min_by(|a, b| b.1.total_cmp(&a.1).then(a.2.cmp(&b.2)))
+            .map(|(format, _, _)| format)
+            .ok_or_else(|| {
+                ApiError::new(StatusCode::NOT_ACCEPTABLE, "supported media types: application/yaml, application/json")
+            })
+    }
+}
+
+/// A media range of `Accept`, lowercased, and its quality: 1 unless a `q`
+/// parameter is given, and 0 when that parameter is not a number.
+fn media_range(range: &str) -> Option<(String, f32)> {
+    let mut parameters = range.split(';');
+    let media_type = parameters.next().unwrap_or_default().trim().to_ascii_lowercase();
+    if media_type.is_empty() {
+        return None;
+    }
+    let quality = parameters
+        .filter_map(|parameter| parameter.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+        .map_or(Ok(1.0), |(_, value)| value.trim().parse::<f32>());
+    Some((media_type, quality.unwrap_or(0.0).clamp(0.0, 1.0)))
+}
+
+/// Whether the request declares a JSON body.
+fn is_json(request: &HttpRequest) -> bool {
+    request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case("application/json"))
+}
+
+/// Parses a JSON body: syntax errors are bad requests, and well-formed
+/// bodies that do not describe the expected value are unprocessable.
+pub fn json_body<T: serde::de::DeserializeOwned>(request: &HttpRequest, body: &[u8]) -> Result<T, ApiError> {
+    if !is_json(request) {
+        return Err(ApiError::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, "the body must be `application/json`"));
+    }
+    serde_json::from_slice(body).map_err(|err| {
+        let status = match err.classify() {
+            serde_json::error::Category::Data => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        ApiError::new(status, &err.to_string())
+    })
+}
+
+/// The stack definition the requests start from, from the configuration.
+type Defaults = web::Data<StackOptions>;
+
+fn unprocessable(err: serde_json::Error) -> ApiError {
+    ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, &err.to_string())
+}
+
+/// Lays `overlay` over `base`, object by object.
+fn merge(base: &mut serde_json::Value, overlay: serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge(base.entry(key).or_insert(serde_json::Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// The stack definition of a request, over the configured defaults.
+fn stack_body(defaults: &StackOptions, body: serde_json::Value) -> Result<StackOptions, ApiError> {
+    let mut stack = serde_json::to_value(defaults).map_err(io::Error::other)?;
+    merge(&mut stack, body);
+    serde_json::from_value(stack).map_err(unprocessable)
+}
+
+type Counters = web::Data<Metrics>;
+
+/// Counts a rendered stack in the metrics.
+fn count_render(metrics: &Metrics, profile: Profile, compose: &Compose) {
+    metrics.count_render(profile, compose.services.iter().map(|service| service.container_name.as_str()));
+}
+
+/// A rendered stack: the compose file, or its model as JSON, with the
+/// secrets masked for read-only tokens.
+fn respond(caller: &Caller, compose: &Compose, secrets: &Secrets, format: Format) -> HttpResponse {
+    match format {
+        Format::Yaml => HttpResponse::Ok().content_type(YAML).body(reveal(caller, parse_compose(compose), secrets)),
+        Format::Json => {
+            let mut model = compose_json(compose);
+            if caller.role < Role::Admin {
+                mask_strings(&mut model, secrets);
+            }
+            HttpResponse::Ok().json(model)
+        }
+    }
+}
+
+/// Masks the secrets in every string of a JSON value.
+fn mask_strings(value: &mut serde_json::Value, secrets: &Secrets) {
+    match value {
+        serde_json::Value::String(text) => *text = secrets.mask(text),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(|item| mask_strings(item, secrets)),
+        serde_json::Value::Object(entries) => entries.values_mut().for_each(|entry| mask_strings(entry, secrets)),
+        _ => {}
+    }
+}
+
+/// Renders a stack with new secrets, masked for read-only tokens.
+///
+/// Every field of the body is optional; the profile and admin e-mail
+/// default to those of the server configuration. `Accept: application/json` returns
+/// the compose model as JSON instead of YAML.
+#[utoipa::path(
+    post,
+    path = "/api/v1/stacks/render",
+    tag = "stacks",
+    request_body(content = StackOptions, content_type = "application/json"),
+    responses(
+        (status = 200, description = "The docker-compose file", content(
+            (String = "application/yaml"),
+            (Object = "application/json")
+        )),
+        (status = 400, description = "The body is not valid JSON", body = ApiError),
+        (status = 405, description = "The method is not `POST`", body = ApiError),
+        (status = 406, description = "`Accept` lists neither YAML nor JSON", body = ApiError),
+        (status = 415, description = "The body is not `application/json`", body = ApiError),
+        (status = 422, description = "The body does not describe a valid stack", body = ApiError)
+    )
+)]
+async fn render_stack(
+    caller: Caller,
+    defaults: Defaults,
+    metrics: Counters,
+    request: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let format = Format::negotiate(&request)?;
+    let options = stack_body(&defaults, json_body(&request, &body)?)?;
+    let mut secrets = Secrets::default();
+    let compose = catalog::stack(&options, &mut secrets)?;
+    count_render(&metrics, options.site.profile, &compose);
+    Ok(respond(&caller, &compose, &secrets, format))
+}
+
+/// The body of `POST /api/v1/projects`.
+#[derive(Deserialize, ToSchema)]
+pub struct ProjectRequest {
+    /// Up to 64 lowercase letters, digits, `-` and `_`.
+    pub name: String,
+    #[serde(default)]
+    pub stack: StackOptions,
+}
+
+type Projects = web::Data<Mutex<Store>>;
+
+fn store(projects: &Projects) -> Result<MutexGuard<'_, Store>, ApiError> {
+    projects
+        .lock()
+        .map_err(|_| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "the project store is unavailable"))
+}
+
+/// Lists the saved projects.
+#[utoipa::path(
+    get,
+    path = "/api/v1/projects",
+    tag = "projects",
+    responses((status = 200, description = "The projects, by name", body = Vec<Summary>))
+)]
+async fn list_projects(caller: Caller, projects: Projects) -> Result<HttpResponse, ApiError> {
+    let list: Vec<Summary> = store(&projects)?
+        .list()?
+        .into_iter()
+        .map(|summary| summarize(&caller, summary))
+        .collect();
+    Ok(HttpResponse::Ok().json(list))
+}
+
+/// Saves a stack definition as a project and generates its secrets.
+#[utoipa::path(
+    post,
+    path = "/api/v1/projects",
+    tag = "projects",
+    request_body(content = ProjectRequest, content_type = "application/json"),
+    responses(
+        (status = 201, description = "The created project", body = Summary),
+        (status = 403, description = "The token is read-only", body = ApiError),
+        (status = 409, description = "A project has this name", body = ApiError),
+        (status = 415, description = "The body is not `application/json`", body = ApiError),
+        (status = 422, description = "Invalid name or stack", body = ApiError)
+    )
+)]
+async fn create_project(
+    caller: Caller,
+    projects: Projects,
+    defaults: Defaults,
+    request: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&caller, Role::Admin)?;
+    let mut body: serde_json::Value = json_body(&request, &body)?;
+    let stack = body
+        .as_object_mut()
+        .and_then(|body| body.remove("stack"))
+        .unwrap_or_else(|| serde_json::json!({}));
+    let ProjectRequest { name, .. } = serde_json::from_value(body).map_err(unprocessable)?;
+    let stack = stack_body(&defaults, stack)?;
+    let project = store(&projects)?.create(&name, stack)?;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("/api/v1/projects/{}", project.name)))
+        .json(project.summary()))
+}
+
+/// Shows a project, without its generated secrets.
+#[utoipa::path(
+    get,
+    path = "/api/v1/projects/{name}",
+    tag = "projects",
+    params(("name" = String, Path, description = "Project name")),
+    responses(
+        (status = 200, description = "The project", body = Summary),
+        (status = 404, description = "No project has this name", body = ApiError)
+    )
+)]
+async fn show_project(caller: Caller, projects: Projects, name: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let summary = store(&projects)?.get(&name)?.summary();
+    Ok(HttpResponse::Ok().json(summarize(&caller, summary)))
+}
+
+/// Replaces the stack definition of a project, keeping its secrets.
+#[utoipa::path(
+    put,
+    path = "/api/v1/projects/{name}",
+    tag = "projects",
+    params(("name" = String, Path, description = "Project name")),
+    request_body(content = StackOptions, content_type = "application/json"),
+    responses(
+        (status = 200, description = "The updated project", body = Summary),
+        (status = 403, description = "The token is read-only", body = ApiError),
+        (status = 404, description = "No project has this name", body = ApiError),
+        (status = 415, description = "The body is not `application/json`", body = ApiError),
+        (status = 422, description = "Invalid stack", body = ApiError)
+    )
+)]
+async fn update_project(
+    caller: Caller,
+    projects: Projects,
+    defaults: Defaults,
+    name: web::Path<String>,
+    request: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&caller, Role::Admin)?;
+    let stack = stack_body(&defaults, json_body(&request, &body)?)?;
+    Ok(HttpResponse::Ok().json(store(&projects)?.update(&name, stack)?.summary()))
+}
+
+/// Deletes a project and its secrets.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/projects/{name}",
+    tag = "projects",
+    params(("name" = String, Path, description = "Project name")),
+    responses(
+        (status = 204, description = "The project is deleted"),
+        (status = 403, description = "The token is read-only", body = ApiError),
+        (status = 404, description = "No project has this name", body = ApiError)
+    )
+)]
+async fn delete_project(caller: Caller, projects: Projects, name: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    authorize(&caller, Role::Admin)?;
+    store(&projects)?.delete(&name)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Renders the stack of a project with its saved secrets.
+///
+/// Admin tokens record the render as a revision. Read-only tokens get a
+/// preview with the secrets masked, and nothing is recorded.
+#[utoipa::path(
+    post,
+    path = "/api/v1/projects/{name}/render",
+    tag = "projects",
+    params(("name" = String, Path, description = "Project name")),
+    responses(
+        (status = 200, description = "The docker-compose file", content(
+            (String = "application/yaml"),
+            (Object = "application/json")
+        )),
+        (status = 404, description = "No project has this name", body = ApiError),
+        (status = 406, description = "`Accept` lists neither YAML nor JSON", body = ApiError)
+    )
+)]
+async fn render_project(
+    caller: Caller,
+    projects: Projects,
+    metrics: Counters,
+    name: web::Path<String>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let format = Format::negotiate(&request)?;
+    let (profile, compose, secrets) = match caller.role {
+        Role::Admin => {
+            let (revision, compose) = store(&projects)?.render(&name)?;
+            (revision.stack.site.profile, compose, revision.secrets)
+        }
+        Role::ReadOnly => {
+            let (project, compose) = store(&projects)?.preview(&name)?;
+            (project.stack.site.profile, compose, project.secrets)
+        }
+    };
+    count_render(&metrics, profile, &compose);
+    Ok(respond(&caller, &compose, &secrets, format))
+}
+
+/// Lists the revisions of a project, oldest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/projects/{name}/revisions",
+    tag = "revisions",
+    params(("name" = String, Path, description = "Project name")),
+    responses(
+        (status = 200, description = "The revisions", body = Vec<RevisionSummary>),
+        (status = 404, description = "No project has this name", body = ApiError)
+    )
+)]
+async fn list_revisions(_: Caller, projects: Projects, name: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(store(&projects)?.revisions(&name)?))
+}
+
+/// Returns the compose file of a revision as it was rendered, with the
+/// secrets masked for read-only tokens.
+#[utoipa::path(
+    get,
+    path = "/api/v1/projects/{name}/revisions/{number}",
+    tag = "revisions",
+    params(
+        ("name" = String, Path, description = "Project name"),
+        ("number" = u32, Path, description = "Revision number")
+    ),
+    responses(
+        (status = 200, description = "The recorded docker-compose file", content(
+            (String = "application/yaml"),
+            (Object = "application/json")
+        )),
+        (status = 404, description = "No such project or revision", body = ApiError),
+        (status = 406, description = "JSON of a revision the current woors renders differently", body = ApiError)
+    )
+)]
+async fn show_revision(
+    caller: Caller,
+    projects: Projects,
+    path: web::Path<(String, u32)>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let format = Format::negotiate(&request)?;
+    let (name, number) = path.into_inner();
+    let store = store(&projects)?;
+    let revision = store.revision(&name, number)?;
+    if format == Format::Yaml {
+        return Ok(HttpResponse::Ok().content_type(YAML).body(reveal(&caller, revision.compose, &revision.secrets)));
+    }
+    // Only the compose file is recorded: the model is rendered again, and
+    // only served while it still renders to the recorded file.
+    let (revision, compose) = store.rerender(&name, number)?;
+    if !revision.renders(&compose) {
+        return Err(ApiError::new(
+            StatusCode::NOT_ACCEPTABLE,
+            &format!("revision {} was rendered by woors {}; only its YAML is kept", number, revision.version),
+        ));
+    }
+    Ok(respond(&caller, &compose, &revision.secrets, format))
+}
+
+/// Renders a revision again with the current woors, without recording it.
+/// The secrets are masked for read-only tokens.
+#[utoipa::path(
+    post,
+    path = "/api/v1/projects/{name}/revisions/{number}/render",
+    tag = "revisions",
+    params(
+        ("name" = String, Path, description = "Project name"),
+        ("number" = u32, Path, description = "Revision number")
+    ),
+    responses(
+        (status = 200, description = "The docker-compose file", content(
+            (String = "application/yaml"),
+            (Object = "application/json")
+        )),
+        (status = 404, description = "No such project or revision", body = ApiError)
+    )
+)]
+async fn rerender_revision(
+    caller: Caller,
+    projects: Projects,
+    metrics: Counters,
+    path: web::Path<(String, u32)>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let format = Format::negotiate(&request)?;
+    let (name, number) = path.into_inner();
+    let (revision, compose) = store(&projects)?.rerender(&name, number)?;
+    count_render(&metrics, revision.stack.site.profile, &compose);
+    Ok(respond(&caller, &compose, &revision.secrets, format))
+}
+
+/// Makes a revision the project definition again, as a new revision.
+#[utoipa::path(
+    post,
+    path = "/api/v1/projects/{name}/revisions/{number}/rollback",
+    tag = "revisions",
+    params(
+        ("name" = String, Path, description = "Project name"),
+        ("number" = u32, Path, description = "Revision number")
+    ),
+    responses(
+        (status = 200, description = "The new revision", body = RevisionSummary),
+        (status = 403, description = "The token is read-only", body = ApiError),
+        (status = 404, description = "No such project or revision", body = ApiError)
+    )
+)]
+async fn rollback_revision(
+    caller: Caller,
+    projects: Projects,
+    path: web::Path<(String, u32)>,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&caller, Role::Admin)?;
+    let (name, number) = path.into_inner();
+    Ok(HttpResponse::Ok().json(store(&projects)?.rollback(&name, number)?.summary()))
+}
+
+/// The revisions compared by a diff.
+#[derive(Deserialize, IntoParams)]
+pub struct DiffQuery {
+    /// The older revision
+    pub from: u32,
+    /// The newer revision; defaults to the latest one
+    pub to: Option<u32>,
+}
+
+/// A unified diff of the compose files of two revisions, with the secrets
+/// masked for read-only tokens.
+#[utoipa::path(
+    get,
+    path = "/api/v1/projects/{name}/diff",
+    tag = "revisions",
+    params(("name" = String, Path, description = "Project name"), DiffQuery),
+    responses(
+        (status = 200, description = "The diff; empty when the revisions are the same", body = String, content_type = "text/x-diff"),
+        (status = 404, description = "No such project or revision", body = ApiError)
+    )
+)]
+async fn diff_revisions(
+    caller: Caller,
+    projects: Projects,
+    name: web::Path<String>,
+    query: web::Query<DiffQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let store = store(&projects)?;
+    let to = match query.to {
+        Some(to) => to,
+        None => store.revisions(&name)?.last().map_or(query.from, |revision| revision.number),
+    };
+    let mut diff = store.diff(&name, query.from, to)?;
+    for number in [query.from, to] {
+        diff = reveal(&caller, diff, &store.revision(&name, number)?.secrets);
+    }
+    Ok(HttpResponse::Ok().content_type("text/x-diff").body(diff))
+}
+
+/// The body of the health endpoints.
+#[derive(Serialize, ToSchema)]
+pub struct Health {
+    /// `ok`
+    pub status: String,
+}
+
+/// Lists the catalog services, built-in and from templates, that stacks
+/// can select.
+#[utoipa::path(
+    get,
+    path = "/api/v1/catalog",
+    tag = "catalog",
+    responses((status = 200, description = "The catalog services", body = Vec<Entry>))
+)]
+async fn list_catalog(_: Caller, defaults: Defaults) -> HttpResponse {
+    HttpResponse::Ok().json(catalog::entries(&defaults))
+}
+
+/// Describes a catalog service: its image, ports, volumes, variables,
+/// dependencies, profiles and resources.
+#[utoipa::path(
+    get,
+    path = "/api/v1/catalog/{name}",
+    tag = "catalog",
+    params(("name" = String, Path, description = "Service name")),
+    responses(
+        (status = 200, description = "The service", body = Entry),
+        (status = 404, description = "No catalog service has this name", body = ApiError)
+    )
+)]
+async fn show_catalog(_: Caller, defaults: Defaults, name: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(catalog::entry(&defaults, &name)?))
+}
+
+/// Liveness: the server answers requests.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "operations",
+    security(()),
+    responses((status = 200, description = "The server is up", body = Health))
+)]
+async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(Health { status: "ok".to_string() })
+}
+
+/// Readiness: the project store answers too.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "operations",
+    security(()),
+    responses(
+        (status = 200, description = "The server can serve requests", body = Health),
+        (status = 503, description = "The project store is unavailable", body = ApiError)
+    )
+)]
+async fn readyz(projects: Projects, tokens: Auth) -> Result<HttpResponse, ApiError> {
+    let unavailable = |err: io::Error| {
+        ApiError::new(StatusCode::SERVICE_UNAVAILABLE, &format!("the project store is unavailable: {}", err))
+    };
+    let poisoned = || unavailable(io::Error::other("a request failed while using it"));
+    projects.lock().map_err(|_| poisoned())?.check().map_err(unavailable)?;
+    tokens.lock().map_err(|_| poisoned())?.is_empty().map_err(unavailable)?;
+    Ok(HttpResponse::Ok().json(Health { status: "ok".to_string() }))
+}
+
+/// Request, latency, render and validation failure counters in the
+/// Prometheus text format.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "operations",
+    security(()),
+    responses((status = 200, description = "The metrics", body = String, content_type = "text/plain; version=0.0.4"))
+)]
+async fn metrics(metrics: Counters) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(metrics.render())
+}
+
+/// Header of the request IDs. A valid ID sent by a proxy is kept, so its
+/// logs and ours can be matched.
+const REQUEST_ID: &str = "x-request-id";
+
+fn request_id(request: &ServiceRequest) -> String {
+    let sent = request
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 128 && id.bytes().all(|byte| byte.is_ascii_graphic()));
+    match sent {
+        Some(id) => id.to_string(),
+        None => {
+            let mut bytes = [0u8; 16];
+            OsRng.fill_bytes(&mut bytes);
+            encode_hex(&bytes)
+        }
+    }
+}
+
+/// Routes polled by probes and scrapers, only logged at the debug level.
+const PROBES: &[&str] = &["/healthz", "/readyz", "/metrics"];
+
+/// Logs every request as a JSON line, counts it in the metrics and gives
+/// its response an `X-Request-Id`.
+async fn observe(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started = Instant::now();
+    let id = request_id(&request);
+    let method = request.method().to_string();
+    let path = request.path().to_string();
+    let remote = request.connection_info().realip_remote_addr().unwrap_or_default().to_string();
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let metrics = request.app_data::<Counters>().cloned();
+    let logger = request.app_data::<web::Data<Logger>>().map(|logger| *logger.get_ref());
+
+    let mut result = next.call(request).await;
+
+    let (status, route) = match &result {
+        Ok(response) => (response.status(), response.request().match_pattern()),
+        Err(err) => (err.as_response_error().status_code(), None),
+    };
+    // Patterns rather than paths keep the number of series bounded.
+    let route = route.unwrap_or_else(|| "unmatched".to_string());
+    let seconds = started.elapsed().as_secs_f64();
+    if let Some(metrics) = metrics {
+        metrics.observe_request(&method, &route, status.as_u16(), seconds);
+    }
+    if let Some(logger) = logger {
+        let level = if status.is_server_error() {
+            LogLevel::Error
+        } else if PROBES.contains(&route.as_str()) {
+            LogLevel::Debug
+        } else {
+            LogLevel::Info
+        };
+        logger.log(
+            level,
+            "request",
+            json!({
+                "request_id": id,
+                "method": method,
+                "path": path,
+                "route": route,
+                "status": status.as_u16(),
+                "duration_ms": seconds * 1000.0,
+                "remote": remote,
+                "user_agent": user_agent,
+            }),
+        );
+    }
+    if let (Ok(response), Ok(value)) = (&mut result, header::HeaderValue::from_str(&id)) {
+        response
+            .headers_mut()
+            .insert(header::HeaderName::from_static(REQUEST_ID), value);
+    }
+    result
+}
+
+/// The response of a resource for the methods it does not support.
+pub fn method_not_allowed(allow: &'static str) -> HttpResponse {
+    let mut response =
+        ApiError::new(StatusCode::METHOD_NOT_ALLOWED, &format!("supported methods: {}", allow)).error_response();
+    response
+        .headers_mut()
+        .insert(header::ALLOW, header::HeaderValue::from_static(allow));
+    response
+}
+
+async fn not_found(request: HttpRequest) -> HttpResponse {
+    ApiError::new(StatusCode::NOT_FOUND, &format!("no resource at `{}`", request.path())).error_response()
+}
+
+/// The routes of `/api/v1`.
+fn api_v1(config: &mut web::ServiceConfig) {
+    config.service(
+        web::resource("/stacks/render")
+            .route(web::post().to(render_stack))
+            .default_service(web::to(|| async { method_not_allowed("POST") })),
+    )
+    .service(
+        web::resource("/projects")
+            .route(web::get().to(list_projects))
+            .route(web::post().to(create_project))
+            .default_service(web::to(|| async { method_not_allowed("GET, POST") })),
+    )
+    .service(
+        web::resource("/projects/{name}")
+            .route(web::get().to(show_project))
+            .route(web::put().to(update_project))
+            .route(web::delete().to(delete_project))
+            .default_service(web::to(|| async { method_not_allowed("GET, PUT, DELETE") })),
+    )
+    .service(
+        web::resource("/projects/{name}/render")
+            .route(web::post().to(render_project))
+            .default_service(web::to(|| async { method_not_allowed("POST") })),
+    )
+    .service(
+        web::resource("/projects/{name}/revisions")
+            .route(web::get().to(list_revisions))
+            .default_service(web::to(|| async { method_not_allowed("GET") })),
+    )
+    .service(
+        web::resource("/projects/{name}/revisions/{number}")
+            .route(web::get().to(show_revision))
+            .default_service(web::to(|| async { method_not_allowed("GET") })),
+    )
+    .service(
+        web::resource("/projects/{name}/revisions/{number}/render")
+            .route(web::post().to(rerender_revision))
+            .default_service(web::to(|| async { method_not_allowed("POST") })),
+    )
+    .service(
+        web::resource("/projects/{name}/revisions/{number}/rollback")
+            .route(web::post().to(rollback_revision))
+            .default_service(web::to(|| async { method_not_allowed("POST") })),
+    )
+    .service(
+        web::resource("/projects/{name}/diff")
+            .route(web::get().to(diff_revisions))
+            .default_service(web::to(|| async { method_not_allowed("GET") })),
+    )
+    .service(
+        web::resource("/catalog")
+            .route(web::get().to(list_catalog))
+            .default_service(web::to(|| async { method_not_allowed("GET") })),
+    )
+    .service(
+        web::resource("/catalog/{name}")
+            .route(web::get().to(show_catalog))
+            .default_service(web::to(|| async { method_not_allowed("GET") })),
+    );
+}
+
+/// Serves the API, its OpenAPI document and the Swagger UI at `/docs`.
+/// Projects and API tokens are kept in the project store of the data
+/// directory.
+pub async fn serve(config: Config) -> std::io::Result<()> {
+    let database = config.database();
+    let projects = web::Data::new(Mutex::new(Store::open(&database)?));
+    let tokens = Tokens::open(&database)?;
+    let logger = Logger { level: config.log_level };
+    if tokens.is_empty()? {
+        logger.log(
+            LogLevel::Warn,
+            "no API token yet; every request is refused until `woors token create` makes one",
+            json!({}),
+        );
+    }
+    let tokens = web::Data::new(Mutex::new(tokens));
+    let defaults = web::Data::new(config.stack_defaults());
+    let metrics = web::Data::new(Metrics::default());
+    let tls = config.tls().map(|(cert, key)| tls_config(cert, key)).transpose()?;
+    logger.log(
+        LogLevel::Info,
+        "listening",
+        json!({"address": config.address, "port": config.port, "tls": tls.is_some()}),
+    );
+    let logger = web::Data::new(logger);
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(from_fn(observe))
+            .app_data(projects.clone())
+            .app_data(tokens.clone())
+            .app_data(defaults.clone())
+            .app_data(metrics.clone())
+            .app_data(logger.clone())
+            .service(web::scope("/api/v1").configure(api_v1).default_service(web::to(not_found)))
+            .service(web::resource("/dc").to(docker_compose))
+            .service(
+                web::resource("/healthz")
+                    .route(web::get().to(healthz))
+                    .default_service(web::to(|| async { method_not_allowed("GET") })),
+            )
+            .service(
+                web::resource("/readyz")
+                    .route(web::get().to(readyz))
+                    .default_service(web::to(|| async { method_not_allowed("GET") })),
+            )
+            .service(
+                web::resource("/metrics")
+                    .route(web::get().to(self::metrics))
+                    .default_service(web::to(|| async { method_not_allowed("GET") })),
+            )
+            .service(SwaggerUi::new("/docs/{_:.*}").url("/openapi.json", ApiDoc::openapi()))
+            .service(web::redirect("/docs", "/docs/"))
+    });
+    let server = match tls {
+        Some(tls) => server.bind_rustls_0_23(config.bind(), tls)?,
+        None => server.bind(config.bind())?,
+    };
+    server.run().await
+}
+
+/// The TLS settings of the server, from the PEM files of the certificate
+/// chain and its private key.
+fn tls_config(cert: &Path, key: &Path) -> io::Result<rustls::ServerConfig> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let chain = rustls_pemfile::certs(&mut BufReader::new(File::open(cert)?)).collect::<io::Result<Vec<_>>>()?;
+    if chain.is_empty() {
+        return Err(invalid(format!("tls_cert: no certificate in `{}`", cert.display())));
+    }
+    let private_key = rustls_pemfile::private_key(&mut BufReader::new(File::open(key)?))?
+        .ok_or_else(|| invalid(format!("tls_key: no private key in `{}`", key.display())))?;
+    rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|err| invalid(format!("tls: {}", err)))?
+        .with_no_client_auth()
+        .with_single_cert(chain, private_key)
+        .map_err(|err| invalid(format!("tls_key: {}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn negotiate(accept: Option<&str>) -> Result<Format, u16> {
+        let mut request = TestRequest::default();
+        if let Some(accept) = accept {
+            request = request.insert_header((header::ACCEPT, accept));
+        }
+        Format::negotiate(&request.to_http_request()).map_err(|err| err.status)
+    }
+
+    #[test]
+    fn negotiate_defaults_to_yaml() {
+        assert_eq!(negotiate(None), Ok(Format::Yaml));
+        assert_eq!(negotiate(Some("")), Ok(Format::Yaml));
+        assert_eq!(negotiate(Some("*/*")), Ok(Format::Yaml));
+        assert_eq!(negotiate(Some("application/x-yaml")), Ok(Format::Yaml));
+    }
+
+    #[test]
+    fn negotiate_picks_the_highest_quality() {
+        assert_eq!(negotiate(Some("Application/JSON")), Ok(Format::Json));
+        assert_eq!(negotiate(Some("text/yaml;q=0.1, application/json")), Ok(Format::Json));
+        assert_eq!(negotiate(Some("application/json;q=0.5, application/*;q=0.8")), Ok(Format::Yaml));
+        // The first listed among equals.
+        assert_eq!(negotiate(Some("text/html, application/json, text/yaml")), Ok(Format::Json));
+    }
+
+    #[test]
+    fn negotiate_skips_refused_types() {
+        assert_eq!(negotiate(Some("application/yaml;q=0, */*")), Ok(Format::Json));
+        assert_eq!(negotiate(Some("application/json; q=0, text/yaml;q=0.2")), Ok(Format::Yaml));
+        assert_eq!(negotiate(Some("application/json;q=0, text/*;q=0")), Err(406));
+    }
+
+    #[test]
+    fn negotiate_rejects_unsupported_types() {
+        assert_eq!(negotiate(Some("text/html")), Err(406));
+        assert_eq!(negotiate(Some("text/html, image/png")), Err(406));
+    }
+
+    #[test]
+    fn tls_config_needs_a_certificate() {
+        let err = tls_config(Path::new("Cargo.toml"), Path::new("Cargo.toml")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("no certificate in `Cargo.toml`"));
+    }
+}
@@ -0,0 +1,125 @@
+use std::io;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// The deployment profile of a site.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, ValueEnum, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    #[default]
+    Dev,
+    Prod,
+}
+
+/// The website a stack is generated for.
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct Site {
+    pub title: String,
+    /// Host name, optionally with a scheme: `shop.com` or `https://shop.com`.
+    pub url: String,
+    pub profile: Profile,
+}
+
+impl Default for Site {
+    fn default() -> Self {
+        Site {
+            title: "woors".to_string(),
+            url: "example.com".to_string(),
+            profile: Profile::Dev,
+        }
+    }
+}
+
+impl Profile {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Profile::Dev => "dev",
+            Profile::Prod => "prod",
+        }
+    }
+}
+
+impl Site {
+    /// Checks that the URL is a host name, with an optional `http://` or
+    /// `https://` scheme, port and path. It ends up in `wp-config.php` and
+    /// the Traefik rules, so quotes and other punctuation are refused.
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let rest = match self.url.split_once("://") {
+            Some(("http" | "https", rest)) => rest,
+            Some((scheme, _)) => return Err(invalid(format!("site url: unsupported scheme `{}`", scheme))),
+            None => self.url.as_str(),
+        };
+        let allowed = |c: char| c.is_ascii_alphanumeric() || "-._~:/%".contains(c);
+        if self.host().is_empty() || !rest.chars().all(allowed) {
+            return Err(invalid(format!("site url: `{}` is not a valid http(s) URL", self.url)));
+        }
+        Ok(())
+    }
+
+    /// The public URL of the site. Production sites default to https.
+    pub fn home_url(&self) -> String {
+        if self.url.contains("://") {
+            return self.url.trim_end_matches('/').to_string();
+        }
+        let scheme = match self.profile {
+            Profile::Dev => "http",
+            Profile::Prod => "https",
+        };
+        format!("{}://{}", scheme, self.url.trim_end_matches('/'))
+    }
+
+    /// The host name of the site, without scheme or path.
+    pub fn host(&self) -> &str {
+        let url = self.url.split_once("://").map_or(self.url.as_str(), |(_, rest)| rest);
+        url.split('/').next().unwrap_or(url)
+    }
+
+    /// The site title as a lowercase identifier (`My Shop!` -> `my_shop`),
+    /// usable as a database or user name.
+    pub fn slug(&self) -> String {
+        let mut slug = String::new();
+        for c in self.title.chars() {
+            if c.is_ascii_alphanumeric() {
+                slug.push(c.to_ascii_lowercase());
+            } else if !slug.is_empty() && !slug.ends_with('_') {
+                slug.push('_');
+            }
+        }
+        let slug = slug.trim_end_matches('_');
+        if slug.is_empty() {
+            "wordpress".to_string()
+        } else {
+            slug.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Site {
+        Site {
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn validate_accepts_http_urls() {
+        for valid in ["shop.com", "https://shop.com", "http://localhost:8080/shop/", "shop-1.example.com"] {
+            assert!(url(valid).validate().is_ok(), "{}", valid);
+        }
+    }
+
+    #[test]
+    fn validate_rejects_quotes_and_other_schemes() {
+        for invalid in ["", "https://", "shop.com');phpinfo();//", "ftp://shop.com", "shop.com`)", "shop com"] {
+            assert!(url(invalid).validate().is_err(), "{}", invalid);
+        }
+    }
+}
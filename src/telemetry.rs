@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Map, Value};
+
+use crate::config::LogLevel;
+use crate::site::Profile;
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Writes log lines as JSON objects on stderr, one per line, for Promtail
+/// and `docker logs`.
+#[derive(Clone, Copy)]
+pub struct Logger {
+    pub level: LogLevel,
+}
+
+impl Logger {
+    /// Logs `message` with `fields` when `level` is enabled.
+    pub fn log(&self, level: LogLevel, message: &str, fields: Value) {
+        if level > self.level {
+            return;
+        }
+        let mut line = Map::new();
+        line.insert("time".to_string(), json!(timestamp(SystemTime::now())));
+        line.insert("level".to_string(), json!(level));
+        line.insert("message".to_string(), json!(message));
+        if let Value::Object(fields) = fields {
+            line.extend(fields);
+        }
+        eprintln!("{}", Value::Object(line));
+    }
+}
+
+/// RFC 3339 UTC time with milliseconds, e.g. `2024-10-19T08:30:00.000Z`.
+fn timestamp(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = elapsed.as_secs();
+    let (days, time_of_day) = ((seconds / 86_400) as i64, seconds % 86_400);
+    // Civil date from days since 1970-01-01, after Howard Hinnant.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3_600,
+        time_of_day % 3_600 / 60,
+        time_of_day % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket of [`BUCKETS`], not cumulated.
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = BUCKETS.iter().position(|bound| value <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    /// By method, route and status.
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// By route.
+    durations: BTreeMap<String, Histogram>,
+    /// By profile and service.
+    renders: BTreeMap<(&'static str, String), u64>,
+    /// Requests refused for their content, by route and status.
+    validation_failures: BTreeMap<(String, u16), u64>,
+}
+
+/// The counters of `/metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    registry: Mutex<Registry>,
+}
+
+/// Whether a status means the request itself was invalid.
+fn is_validation_failure(status: u16) -> bool {
+    matches!(status, 400 | 415 | 422)
+}
+
+/// Escapes a label value of the text format.
+fn label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl Metrics {
+    /// Counts a request to `route`, the pattern of the resource it matched.
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        let Ok(mut registry) = self.registry.lock() else {
+            return;
+        };
+        *registry
+            .requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default() += 1;
+        registry.durations.entry(route.to_string()).or_default().observe(seconds);
+        if is_validation_failure(status) {
+            *registry.validation_failures.entry((route.to_string(), status)).or_default() += 1;
+        }
+    }
+
+    /// Counts a rendered stack once for every service in it.
+    pub fn count_render<'a>(&self, profile: Profile, services: impl IntoIterator<Item = &'a str>) {
+        let Ok(mut registry) = self.registry.lock() else {
+            return;
+        };
+        for service in services {
+            *registry.renders.entry((profile.as_str(), service.to_string())).or_default() += 1;
+        }
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let Ok(registry) = self.registry.lock() else {
+            return String::new();
+        };
+        let mut text = String::new();
+        // Writing to a String cannot fail.
+        let _ = render(&registry, &mut text);
+        text
+    }
+}
+
+fn render(registry: &Registry, text: &mut String) -> std::fmt::Result {
+    writeln!(text, "# HELP woors_http_requests_total HTTP requests, by method, route and status.")?;
+    writeln!(text, "# TYPE woors_http_requests_total counter")?;
+    for ((method, route, status), count) in &registry.requests {
+        writeln!(
+            text,
+            "woors_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+            label(method),
+            label(route),
+            status,
+            count
+        )?;
+    }
+
+    writeln!(text, "# HELP woors_http_request_duration_seconds Time to answer HTTP requests, by route.")?;
+    writeln!(text, "# TYPE woors_http_request_duration_seconds histogram")?;
+    for (route, histogram) in &registry.durations {
+        let route = label(route);
+        let mut cumulated = 0;
+        for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+            cumulated += count;
+            writeln!(
+                text,
+                "woors_http_request_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}",
+                route, bound, cumulated
+            )?;
+        }
+        writeln!(
+            text,
+            "woors_http_request_duration_seconds_bucket{{route=\"{}\",le=\"+Inf\"}} {}",
+            route, histogram.count
+        )?;
+        writeln!(text, "woors_http_request_duration_seconds_sum{{route=\"{}\"}} {}", route, histogram.sum)?;
+        writeln!(text, "woors_http_request_duration_seconds_count{{route=\"{}\"}} {}", route, histogram.count)?;
+    }
+
+    writeln!(text, "# HELP woors_renders_total Services rendered into compose files, by profile and service.")?;
+    writeln!(text, "# TYPE woors_renders_total counter")?;
+    for ((profile, service), count) in &registry.renders {
+        writeln!(
+            text,
+            "woors_renders_total{{profile=\"{}\",service=\"{}\"}} {}",
+            profile,
+            label(service),
+            count
+        )?;
+    }
+
+    writeln!(
+        text,
+        "# HELP woors_validation_failures_total Requests refused for their content, by route and status."
+    )?;
+    writeln!(text, "# TYPE woors_validation_failures_total counter")?;
+    for ((route, status), count) in &registry.validation_failures {
+        writeln!(
+            text,
+            "woors_validation_failures_total{{route=\"{}\",status=\"{}\"}} {}",
+            label(route),
+            status,
+            count
+        )?;
+    }
+    Ok(())
+}
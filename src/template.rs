@@ -0,0 +1,455 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::backup::Target;
+use crate::catalog::{self, Preset, StackOptions};
+use crate::compose::{
+    Command, Dependency, Environment, Healthcheck, Label, Mount, MountKind, NetworkAttachment, Policy, Port,
+    Resources, Service,
+};
+use crate::image::Image;
+use crate::resources::parse_size;
+use crate::secrets::Secrets;
+use crate::site::Profile;
+
+/// Directory of the templates in the data directory, unless configured.
+pub const DIRECTORY: &str = "templates";
+
+/// Networks of the stack a template can attach to.
+const NETWORKS: [&str; 2] = ["web", "backend"];
+
+/// Dependency standing for the database server, whatever its engine.
+const DATABASE: &str = "database";
+
+/// Templates installed at startup.
+static TEMPLATES: OnceLock<Vec<Template>> = OnceLock::new();
+
+/// A catalog service described by a YAML or TOML file rather than built
+/// into woors. Its name is the file name without the extension.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Template {
+    #[serde(skip)]
+    pub name: String,
+    pub description: String,
+    /// Image reference, e.g. `matomo:5.1.2-apache`.
+    pub image: String,
+    /// Command run in place of the image one.
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Published ports, `host:container`.
+    #[serde(default)]
+    pub ports: Vec<String>,
+    /// `source:target`, optionally followed by `:ro`. A source starting
+    /// with `.` or `/` is a host path, any other one a named volume.
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    /// Variables of the container. Values may hold placeholders.
+    #[serde(default)]
+    pub environment: BTreeMap<String, String>,
+    /// Catalog services started first; `database` stands for the database
+    /// server of the stack.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    #[serde(default = "default_networks")]
+    pub networks: Vec<String>,
+    /// Routes a host name to the service through Traefik.
+    #[serde(default)]
+    pub traefik: Option<Traefik>,
+    /// Profiles the service is part of; all of them when empty.
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub resources: TemplateResources,
+    /// Shell command telling whether the service is healthy.
+    #[serde(default)]
+    pub healthcheck: Option<String>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Traefik {
+    /// Host name, e.g. `stats.{{site_host}}`.
+    pub host: String,
+    /// Port the service listens on in its container.
+    pub port: u16,
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateResources {
+    pub cpus: Option<f64>,
+    /// Memory limit, e.g. `512M`.
+    pub memory: Option<String>,
+}
+
+fn default_networks() -> Vec<String> {
+    vec!["web".to_string()]
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Replaces the `{{name}}` placeholders of `text`:
+///
+/// - `site_title`, `site_url`, `site_host` and `site_profile`;
+/// - `database_host`, the database server of the stack;
+/// - `secret:<name>`, a password generated for the service, or the secret
+///   `<name>` of another service when it contains a dot, e.g.
+///   `secret:database.root_password`.
+fn render(text: &str, service: &str, options: &StackOptions, secrets: &mut Secrets) -> Result<String, String> {
+    let mut rendered = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find("}}").ok_or_else(|| format!("unclosed placeholder in `{}`", text))?;
+        let name = after[..end].trim();
+        let value = match name {
+            "site_title" => options.site.title.clone(),
+            "site_url" => options.site.home_url(),
+            "site_host" => options.site.host().to_string(),
+            "site_profile" => options.site.profile.as_str().to_string(),
+            "database_host" => options.database.service().to_string(),
+            _ => match name.strip_prefix("secret:") {
+                Some(secret) if secret.contains('.') => secrets.password(secret),
+                Some(secret) if !secret.is_empty() => secrets.password(&format!("{}.{}", service, secret)),
+                _ => return Err(format!("unknown placeholder `{{{{{}}}}}`", name)),
+            },
+        };
+        rendered.push_str(&value);
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+fn port(value: &str) -> Option<Port> {
+    let (host, container) = value.split_once(':')?;
+    let number = |value: &str| value.parse::<u16>().is_ok();
+    (number(host) && number(container)).then(|| Port {
+        host: host.to_string(),
+        container: container.to_string(),
+    })
+}
+
+fn mount(value: &str) -> Option<Mount> {
+    let (source, target) = value.split_once(':')?;
+    let (target, read_only) = match target.rsplit_once(':') {
+        Some((target, "ro")) => (target, true),
+        Some((target, "rw")) => (target, false),
+        Some(_) => return None,
+        None => (target, false),
+    };
+    if source.is_empty() || !target.starts_with('/') {
+        return None;
+    }
+    let kind = if source.starts_with('.') || source.starts_with('/') {
+        MountKind::Bind
+    } else {
+        MountKind::Volume
+    };
+    Some(Mount {
+        kind,
+        source: source.to_string(),
+        target: target.to_string(),
+        read_only,
+    })
+}
+
+impl Template {
+    /// Whether the service is part of stacks of `profile`.
+    pub fn supports(&self, profile: Profile) -> bool {
+        self.profiles.is_empty() || self.profiles.contains(&profile)
+    }
+
+    /// The placeholders are only known to be valid once the template is
+    /// loaded, so a failure to render means a bug.
+    pub fn service(&self, options: &StackOptions, secrets: &mut Secrets) -> Service {
+        self.try_service(options, secrets)
+            .unwrap_or_else(|err| panic!("template `{}`: {}", self.name, err))
+    }
+
+    fn try_service(&self, options: &StackOptions, secrets: &mut Secrets) -> Result<Service, String> {
+        let mut environments = vec![];
+        for (name, value) in &self.environment {
+            environments.push(Environment::new(name, &render(value, &self.name, options, secrets)?));
+        }
+        let command = match &self.command {
+            Some(command) => render(command, &self.name, options, secrets)?,
+            None => String::new(),
+        };
+        let mut labels = vec![Label {
+            key: "com.example.description".to_string(),
+            value: self.description.clone(),
+        }];
+        if let Some(traefik) = &self.traefik {
+            let host = render(&traefik.host, &self.name, options, secrets)?;
+            let router = format!("traefik.http.routers.{}", self.name);
+            let values = [
+                ("traefik.enable".to_string(), "true".to_string()),
+                (format!("{}.rule", router), format!("Host(`{}`)", host)),
+                (format!("{}.entrypoints", router), "web".to_string()),
+                (format!("{}.tls", router), "true".to_string()),
+                (format!("{}.tls.certresolver", router), "letsencrypt".to_string()),
+                (
+                    format!("traefik.http.services.{}.loadbalancer.server.port", self.name),
+                    traefik.port.to_string(),
+                ),
+            ];
+            labels.extend(values.into_iter().map(|(key, value)| Label { key, value }));
+        }
+        let depends_on = self
+            .depends_on
+            .iter()
+            .map(|name| Dependency {
+                service: match name.as_str() {
+                    DATABASE => options.database.service().to_string(),
+                    _ => name.clone(),
+                },
+                condition: None,
+            })
+            .collect();
+        Ok(Service {
+            image: Image::parse(&self.image).ok_or_else(|| format!("invalid image `{}`", self.image))?,
+            container_name: self.name.clone(),
+            ports: self.ports.iter().filter_map(|value| port(value)).collect(),
+            volumes: self.volumes.iter().filter_map(|value| mount(value)).collect(),
+            networks: self
+                .networks
+                .iter()
+                .map(|network| NetworkAttachment {
+                    network: network.clone(),
+                    aliases: vec![],
+                    ipv4_address: None,
+                })
+                .collect(),
+            depends_on,
+            restart: Policy {
+                condition: "always".to_string(),
+            },
+            environments,
+            command: Command { value: command },
+            labels,
+            resources: Resources {
+                cpus: self.resources.cpus,
+                memory: self.resources.memory.as_deref().and_then(parse_size),
+                ..Default::default()
+            },
+            healthcheck: self.healthcheck.as_ref().map(|test| Healthcheck {
+                test: vec!["CMD-SHELL".to_string(), test.clone()],
+                interval: "30s".to_string(),
+                timeout: "5s".to_string(),
+                retries: 3,
+                start_period: "10s".to_string(),
+            }),
+            ..Default::default()
+        })
+    }
+
+    /// Checks what deserializing cannot, but for the dependencies, which
+    /// depend on the other templates.
+    fn validate(&self) -> Result<(), String> {
+        let valid_name = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_';
+        if self.name.is_empty() || !self.name.chars().all(valid_name) {
+            return Err("the file name must be lowercase letters, digits, `-` and `_`".to_string());
+        }
+        if let Some(port) = self.ports.iter().find(|value| port(value).is_none()) {
+            return Err(format!("invalid port `{}`, expected `host:container`", port));
+        }
+        if let Some(volume) = self.volumes.iter().find(|value| mount(value).is_none()) {
+            return Err(format!("invalid volume `{}`, expected `source:/target[:ro]`", volume));
+        }
+        if let Some(network) = self.networks.iter().find(|network| !NETWORKS.contains(&network.as_str())) {
+            return Err(format!("unknown network `{}`, expected one of {}", network, NETWORKS.join(", ")));
+        }
+        if self.traefik.is_some() && !self.networks.iter().any(|network| network == "web") {
+            return Err("`traefik` needs the `web` network".to_string());
+        }
+        if let Some(memory) = self.resources.memory.as_ref().filter(|memory| parse_size(memory).is_none()) {
+            return Err(format!("invalid memory size `{}`", memory));
+        }
+        // Rendering once reports the unknown placeholders and the invalid
+        // image up front.
+        self.try_service(&StackOptions::default(), &mut Secrets::default()).map(|_| ())
+    }
+}
+
+/// Names of the services woors builds itself, with every preset and the
+/// jobs they come with.
+fn built_in() -> io::Result<Vec<String>> {
+    let mut options = StackOptions {
+        presets: Preset::value_variants().to_vec(),
+        vault_secrets: true,
+        ..Default::default()
+    };
+    options.backup.target = Target::Minio;
+    options.services = catalog::services(&options, &mut Secrets::default())
+        .into_iter()
+        .map(|service| service.container_name)
+        .collect();
+    let compose = catalog::stack(&options, &mut Secrets::default())?;
+    Ok(compose.services.into_iter().map(|service| service.container_name).collect())
+}
+
+fn parse(path: &Path, content: &str) -> Result<Template, String> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => toml::from_str(content).map_err(|err| err.message().to_string()),
+        _ => serde_yaml::from_str(content).map_err(|err| err.to_string()),
+    }
+}
+
+/// Reads the `*.yaml`, `*.yml` and `*.toml` templates of `directory`, in
+/// name order. A missing directory holds no templates.
+pub fn load(directory: &Path) -> io::Result<Vec<Template>> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(io::Error::new(err.kind(), format!("{}: {}", directory.display(), err))),
+    };
+    let mut paths = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        if path.is_file() && matches!(extension, Some("yaml" | "yml" | "toml")) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let built_in = built_in()?;
+    let mut templates: Vec<Template> = vec![];
+    for path in &paths {
+        let error = |message: String| invalid(format!("{}: {}", path.display(), message));
+        let mut template = parse(path, &fs::read_to_string(path)?).map_err(error)?;
+        template.name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+            .to_string();
+        template.validate().map_err(error)?;
+        if built_in.contains(&template.name) {
+            return Err(error(format!("`{}` is a built-in service", template.name)));
+        }
+        if templates.iter().any(|other| other.name == template.name) {
+            return Err(error(format!("`{}` is defined by another file too", template.name)));
+        }
+        templates.push(template);
+    }
+    for (template, path) in templates.iter().zip(&paths) {
+        let known = |name: &String| {
+            name == DATABASE || built_in.contains(name) || templates.iter().any(|other| &other.name == name)
+        };
+        if let Some(name) = template.depends_on.iter().find(|name| !known(name)) {
+            return Err(invalid(format!("{}: unknown dependency `{}`", path.display(), name)));
+        }
+    }
+    Ok(templates)
+}
+
+/// Makes `templates` part of the catalog for the rest of the process.
+/// Only the first call has an effect.
+pub fn install(templates: Vec<Template>) {
+    let _ = TEMPLATES.set(templates);
+}
+
+/// The installed templates.
+pub fn installed() -> &'static [Template] {
+    TEMPLATES.get().map_or(&[], Vec::as_slice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Engine;
+    use crate::site::Site;
+
+    fn options() -> StackOptions {
+        StackOptions {
+            site: Site {
+                title: "My Shop".to_string(),
+                url: "shop.example.com".to_string(),
+                profile: Profile::Prod,
+            },
+            database: Engine::Mariadb,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn render_replaces_site_placeholders() {
+        let mut secrets = Secrets::default();
+        let rendered = render(
+            "{{site_title}} at {{ site_url }} ({{site_host}}, {{site_profile}}) on {{database_host}}",
+            "matomo",
+            &options(),
+            &mut secrets,
+        );
+        assert_eq!(
+            rendered.as_deref(),
+            Ok("My Shop at https://shop.example.com (shop.example.com, prod) on mariadb")
+        );
+        assert_eq!(render("no placeholders", "matomo", &options(), &mut secrets).as_deref(), Ok("no placeholders"));
+    }
+
+    #[test]
+    fn render_generates_secrets_once() {
+        let mut secrets = Secrets::default();
+        let root = secrets.password("mariadb.root_password");
+        let text = "{{secret:password}} {{secret:password}} {{secret:mariadb.root_password}}";
+        let rendered = render(text, "matomo", &options(), &mut secrets).unwrap();
+        let password = secrets.get("matomo.password").unwrap();
+        assert_eq!(rendered, format!("{} {} {}", password, password, root));
+    }
+
+    #[test]
+    fn render_rejects_unknown_and_unclosed_placeholders() {
+        let mut secrets = Secrets::default();
+        assert!(render("{{site_name}}", "matomo", &options(), &mut secrets).is_err());
+        assert!(render("{{secret:}}", "matomo", &options(), &mut secrets).is_err());
+        assert!(render("{{site_url", "matomo", &options(), &mut secrets).is_err());
+    }
+
+    #[test]
+    fn port_needs_two_port_numbers() {
+        let port = port("8080:80").unwrap();
+        assert_eq!((port.host.as_str(), port.container.as_str()), ("8080", "80"));
+        assert!(super::port("80").is_none());
+        assert!(super::port("http:80").is_none());
+        assert!(super::port("8080:70000").is_none());
+        assert!(super::port("127.0.0.1:8080:80").is_none());
+    }
+
+    #[test]
+    fn mount_tells_volumes_from_bind_mounts() {
+        let volume = mount("matomo:/var/www/html").unwrap();
+        assert!(matches!(volume.kind, MountKind::Volume));
+        assert_eq!(
+            (volume.source.as_str(), volume.target.as_str(), volume.read_only),
+            ("matomo", "/var/www/html", false)
+        );
+
+        let bind = mount("./config:/etc/matomo:ro").unwrap();
+        assert!(matches!(bind.kind, MountKind::Bind));
+        assert_eq!((bind.source.as_str(), bind.target.as_str(), bind.read_only), ("./config", "/etc/matomo", true));
+
+        let absolute = mount("/srv/data:/data:rw").unwrap();
+        assert!(matches!(absolute.kind, MountKind::Bind));
+        assert!(!absolute.read_only);
+    }
+
+    #[test]
+    fn mount_rejects_invalid_specs() {
+        assert!(mount("matomo").is_none());
+        assert!(mount(":/data").is_none());
+        assert!(mount("matomo:data").is_none());
+        assert!(mount("matomo:/data:z").is_none());
+    }
+}
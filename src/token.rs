@@ -0,0 +1,250 @@
+use std::io;
+use std::path::Path;
+
+use clap::ValueEnum;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::project::{encode_hex, now, storage_error};
+
+/// Prefix of the API tokens, so they are easy to recognize in a leak.
+const PREFIX: &str = "woors_";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS tokens (
+    name TEXT PRIMARY KEY,
+    hash TEXT NOT NULL UNIQUE,
+    role TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+";
+
+/// What the bearer of a token may do. Admins can do everything read-only
+/// tokens can.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default, ValueEnum, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    /// Lists projects and previews renders, with the secrets masked
+    #[default]
+    ReadOnly,
+    /// Also reveals secrets and creates, changes and deletes projects
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::ReadOnly => "read-only",
+            Role::Admin => "admin",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Role> {
+        match value {
+            "read-only" => Some(Role::ReadOnly),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// A token as `woors token list` shows it; the token itself is only known
+/// to its bearer.
+pub struct TokenSummary {
+    pub name: String,
+    pub role: Role,
+    /// Unix time, in seconds.
+    pub created_at: u64,
+}
+
+/// The bearer of a valid token.
+#[derive(Clone, Debug)]
+pub struct Caller {
+    /// The name the token was created with.
+    pub name: String,
+    pub role: Role,
+}
+
+/// API tokens, kept as SHA-256 hashes in the project store.
+pub struct Tokens {
+    connection: Connection,
+}
+
+/// Tokens are random, so a fast hash does not make them guessable.
+fn hash(token: &str) -> String {
+    encode_hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn not_found(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("no token named `{}`", name))
+}
+
+impl Tokens {
+    /// Opens the tokens of the project store at `path`, creating it when
+    /// missing.
+    pub fn open(path: &Path) -> io::Result<Tokens> {
+        let connection = Connection::open(path).map_err(storage_error)?;
+        connection.execute_batch(SCHEMA).map_err(storage_error)?;
+        Ok(Tokens { connection })
+    }
+
+    /// Creates a token and returns it. Only its hash is saved, so it cannot
+    /// be shown again.
+    pub fn create(&self, name: &str, role: Role) -> io::Result<String> {
+        if name.trim().is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the token name is empty"));
+        }
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token = format!("{}{}", PREFIX, encode_hex(&bytes));
+        let inserted = self
+            .connection
+            .execute(
+                "INSERT INTO tokens (name, hash, role, created_at) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (name) DO NOTHING",
+                params![name, hash(&token), role.as_str(), now()],
+            )
+            .map_err(storage_error)?;
+        if inserted == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("a token named `{}` already exists", name),
+            ));
+        }
+        Ok(token)
+    }
+
+    pub fn revoke(&self, name: &str) -> io::Result<()> {
+        let deleted = self
+            .connection
+            .execute("DELETE FROM tokens WHERE name = ?1", params![name])
+            .map_err(storage_error)?;
+        if deleted == 0 {
+            return Err(not_found(name));
+        }
+        Ok(())
+    }
+
+    /// Every token, by name.
+    pub fn list(&self) -> io::Result<Vec<TokenSummary>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT name, role, created_at FROM tokens ORDER BY name")
+            .map_err(storage_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get(2)?))
+            })
+            .map_err(storage_error)?;
+        let mut tokens = vec![];
+        for row in rows {
+            let (name, role, created_at) = row.map_err(storage_error)?;
+            tokens.push(TokenSummary {
+                name,
+                // Unknown roles grant nothing more than read-only access.
+                role: Role::parse(&role).unwrap_or_default(),
+                created_at,
+            });
+        }
+        Ok(tokens)
+    }
+
+    /// Whether no token was created yet.
+    pub fn is_empty(&self) -> io::Result<bool> {
+        let count: u64 = self
+            .connection
+            .query_row("SELECT COUNT(*) FROM tokens", [], |row| row.get(0))
+            .map_err(storage_error)?;
+        Ok(count == 0)
+    }
+
+    /// The bearer of `token`, if it is a token of the store.
+    pub fn authenticate(&self, token: &str) -> io::Result<Option<Caller>> {
+        let row = self
+            .connection
+            .query_row(
+                "SELECT name, role FROM tokens WHERE hash = ?1",
+                params![hash(token)],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()
+            .map_err(storage_error)?;
+        Ok(row.map(|(name, role)| Caller {
+            name,
+            role: Role::parse(&role).unwrap_or_default(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens() -> Tokens {
+        Tokens::open(Path::new(":memory:")).unwrap()
+    }
+
+    #[test]
+    fn created_tokens_authenticate_with_their_role() {
+        let tokens = tokens();
+        assert!(tokens.is_empty().unwrap());
+        let admin = tokens.create("ci", Role::Admin).unwrap();
+        let reader = tokens.create("dashboard", Role::ReadOnly).unwrap();
+        assert!(admin.starts_with(PREFIX));
+        assert_ne!(admin, reader);
+
+        let caller = tokens.authenticate(&admin).unwrap().unwrap();
+        assert_eq!((caller.name.as_str(), caller.role), ("ci", Role::Admin));
+        let caller = tokens.authenticate(&reader).unwrap().unwrap();
+        assert_eq!((caller.name.as_str(), caller.role), ("dashboard", Role::ReadOnly));
+        assert!(tokens.authenticate("woors_unknown").unwrap().is_none());
+    }
+
+    #[test]
+    fn only_hashes_are_stored() {
+        let tokens = tokens();
+        let token = tokens.create("ci", Role::Admin).unwrap();
+        let stored: String = tokens
+            .connection
+            .query_row("SELECT hash FROM tokens WHERE name = 'ci'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, hash(&token));
+        assert_ne!(stored, token);
+    }
+
+    #[test]
+    fn names_are_unique_and_not_empty() {
+        let tokens = tokens();
+        tokens.create("ci", Role::ReadOnly).unwrap();
+        assert_eq!(tokens.create("ci", Role::Admin).err().unwrap().kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(tokens.create(" ", Role::Admin).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn revoked_tokens_no_longer_authenticate() {
+        let tokens = tokens();
+        let token = tokens.create("ci", Role::Admin).unwrap();
+        tokens.revoke("ci").unwrap();
+        assert!(tokens.authenticate(&token).unwrap().is_none());
+        assert!(tokens.is_empty().unwrap());
+        assert_eq!(tokens.revoke("ci").err().unwrap().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn admins_can_do_what_read_only_tokens_can() {
+        assert!(Role::Admin > Role::ReadOnly);
+        for role in [Role::ReadOnly, Role::Admin] {
+            assert_eq!(Role::parse(role.as_str()), Some(role));
+        }
+        // A role this version does not know grants read-only access.
+        let tokens = tokens();
+        let token = tokens.create("ci", Role::Admin).unwrap();
+        tokens.connection.execute("UPDATE tokens SET role = 'owner'", []).unwrap();
+        assert_eq!(tokens.authenticate(&token).unwrap().unwrap().role, Role::ReadOnly);
+        assert_eq!(tokens.list().unwrap()[0].role, Role::ReadOnly);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::compose::{
+    Command, Healthcheck, Label, Mount, MountKind, NetworkAttachment, Policy, Port, Resources, Service,
+};
+use crate::image::catalog_image;
+use crate::resources::MIB;
+use crate::site::Site;
+
+/// Port of the `metrics` entrypoint, serving `/metrics` and `/ping`. It is
+/// not published, Prometheus scrapes it over the `web` network.
+pub const METRICS_PORT: &str = "8080";
+
+/// Where the certificates of the `letsencrypt` resolver are kept.
+const ACME_DIR: &str = "/letsencrypt";
+
+/// The reverse proxy of the services labelled `traefik.enable`, like
+/// `woors` and the templates with a `traefik.host`. The `web` entrypoint on
+/// port 443 is the one their routers use, port 80 redirects to it, and
+/// certificates come from the `letsencrypt` resolver. It publishes the same
+/// ports as `nginx`, so the two are never part of the same stack.
+pub fn service(site: &Site) -> Service {
+    let metrics = format!("--entrypoints.metrics.address=:{}", METRICS_PORT);
+    let flags = [
+        "--providers.docker=true".to_string(),
+        "--providers.docker.exposedbydefault=false".to_string(),
+        "--entrypoints.http.address=:80".to_string(),
+        "--entrypoints.http.http.redirections.entrypoint.to=web".to_string(),
+        "--entrypoints.http.http.redirections.entrypoint.scheme=https".to_string(),
+        "--entrypoints.web.address=:443".to_string(),
+        metrics.clone(),
+        "--certificatesresolvers.letsencrypt.acme.tlschallenge=true".to_string(),
+        format!("--certificatesresolvers.letsencrypt.acme.email=admin@{}", site.host()),
+        format!("--certificatesresolvers.letsencrypt.acme.storage={}/acme.json", ACME_DIR),
+        "--metrics.prometheus=true".to_string(),
+        "--metrics.prometheus.entrypoint=metrics".to_string(),
+        "--ping=true".to_string(),
+        "--ping.entrypoint=metrics".to_string(),
+    ];
+    Service {
+        image: catalog_image("traefik"),
+        container_name: "traefik".to_string(),
+        ports: vec![
+            Port {
+                host: "80".to_string(),
+                container: "80".to_string()
+            },
+            Port {
+                host: "443".to_string(),
+                container: "443".to_string()
+            }
+        ],
+        volumes: vec![
+            Mount {
+                kind: MountKind::Bind,
+                source: "/var/run/docker.sock".to_string(),
+                target: "/var/run/docker.sock".to_string(),
+                read_only: true
+            },
+            Mount {
+                kind: MountKind::Volume,
+                source: "traefik".to_string(),
+                target: ACME_DIR.to_string(),
+                read_only: false
+            }
+        ],
+        networks: vec![
+            NetworkAttachment {
+                network: "web".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        environments: vec![],
+        restart: Policy {
+            condition: "always".to_string()
+        },
+        command: Command {
+            value: flags.join(" ")
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "Traefik Proxy".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.5),
+            memory: Some(128 * MIB),
+            reservation_memory: Some(32 * MIB),
+            ..Default::default()
+        },
+        // `traefik healthcheck` reads the static configuration from its own
+        // flags, so it is told where the ping endpoint is.
+        healthcheck: Some(Healthcheck {
+            test: vec![
+                "CMD".to_string(),
+                "traefik".to_string(),
+                "healthcheck".to_string(),
+                "--ping".to_string(),
+                "--ping.entrypoint=metrics".to_string(),
+                metrics
+            ],
+            interval: "10s".to_string(),
+            timeout: "5s".to_string(),
+            retries: 3,
+            start_period: "10s".to_string()
+        }),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_are_served_on_their_own_entrypoint() {
+        let service = service(&Site::default());
+        let command = &service.command.value;
+        assert!(command.contains("--metrics.prometheus=true"));
+        assert!(command.contains("--metrics.prometheus.entrypoint=metrics"));
+        assert!(command.contains("--entrypoints.metrics.address=:8080"));
+        assert!(command.contains("--certificatesresolvers.letsencrypt.acme.email=admin@example.com"));
+        assert!(!service.ports.iter().any(|port| port.host == METRICS_PORT));
+    }
+}
@@ -0,0 +1,540 @@
+use crate::compose::{
+    Command, ConfigFile, Dependency, Environment, Healthcheck, Label, Mount, MountKind, NetworkAttachment, Policy,
+    Resources, Service,
+};
+use crate::image::catalog_image;
+use crate::resources::MIB;
+use crate::secrets::Secrets;
+use crate::site::{Profile, Site};
+
+/// Address of the server inside the stack.
+pub const ADDRESS: &str = "http://vault:8200";
+
+/// Where `vault-agent` renders the secrets, one file per secret.
+const SECRETS_DIR: &str = "/run/secrets";
+
+/// KV v2 path the stack secrets are stored under.
+const KV_PATH: &str = "secret/woors";
+
+/// Variables of the official images that also accept a `<NAME>_FILE`
+/// variant reading the value from a file. Secrets passed through any other
+/// variable stay in the environment.
+const FILE_VARIABLES: &[&str] = &[
+    "MYSQL_ROOT_PASSWORD",
+    "MYSQL_PASSWORD",
+    "MARIADB_ROOT_PASSWORD",
+    "MARIADB_PASSWORD",
+    "WORDPRESS_DB_PASSWORD",
+    "WORDPRESS_AUTH_KEY",
+    "WORDPRESS_SECURE_AUTH_KEY",
+    "WORDPRESS_LOGGED_IN_KEY",
+    "WORDPRESS_NONCE_KEY",
+    "WORDPRESS_AUTH_SALT",
+    "WORDPRESS_SECURE_AUTH_SALT",
+    "WORDPRESS_LOGGED_IN_SALT",
+    "WORDPRESS_NONCE_SALT",
+];
+
+/// The root token of the dev mode server.
+fn root_token(secrets: &mut Secrets) -> String {
+    secrets.password("vault.root_token")
+}
+
+/// The Vault server, only reachable from the `backend` network. The dev
+/// profile runs an in-memory dev mode server with a generated root token;
+/// prod stores its data on the `vault` volume and is initialized and
+/// unsealed by `vault-init`.
+pub fn service(site: &Site, secrets: &mut Secrets) -> Service {
+    let mut environments = vec![Environment::new("VAULT_ADDR", "http://127.0.0.1:8200")];
+    let (command, volumes, configs) = match site.profile {
+        Profile::Dev => {
+            environments.push(Environment::new("VAULT_DEV_ROOT_TOKEN_ID", &root_token(secrets)));
+            environments.push(Environment::new("VAULT_DEV_LISTEN_ADDRESS", "0.0.0.0:8200"));
+            ("server -dev".to_string(), vec![], vec![])
+        }
+        Profile::Prod => {
+            let volumes = vec![Mount {
+                kind: MountKind::Volume,
+                source: "vault".to_string(),
+                target: "/vault/file".to_string(),
+                read_only: false,
+            }];
+            ("server".to_string(), volumes, vec![server_config()])
+        }
+    };
+    Service {
+        image: catalog_image("vault"),
+        container_name: "vault".to_string(),
+        ports: vec![],
+        volumes,
+        networks: vec![
+            NetworkAttachment {
+                network: "backend".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        environments,
+        restart: Policy {
+            condition: "always".to_string()
+        },
+        command: Command {
+            value: command
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "Vault Server".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.5),
+            memory: Some(256 * MIB),
+            reservation_memory: Some(64 * MIB),
+            ..Default::default()
+        },
+        // A sealed server exits with 2, so prod only turns healthy once
+        // `vault-init` has unsealed it.
+        healthcheck: Some(Healthcheck {
+            test: vec!["CMD".to_string(), "vault".to_string(), "status".to_string()],
+            interval: "10s".to_string(),
+            timeout: "5s".to_string(),
+            retries: 5,
+            start_period: "10s".to_string()
+        }),
+        configs,
+        ..Default::default()
+    }
+}
+
+/// File storage without TLS: the listener is only reachable from the
+/// `backend` network. `disable_mlock` spares the container the `IPC_LOCK` capability.
+fn server_config() -> ConfigFile {
+    let content = [
+        "ui = true",
+        "disable_mlock = true",
+        "api_addr = \"http://vault:8200\"",
+        "storage \"file\" {",
+        "  path = \"/vault/file\"",
+        "}",
+        "listener \"tcp\" {",
+        "  address = \"0.0.0.0:8200\"",
+        "  tls_disable = true",
+        "}",
+    ];
+    ConfigFile {
+        title: "vault_hcl".to_string(),
+        target: "/vault/config/vault.hcl".to_string(),
+        content: content.join("\n") + "\n",
+    }
+}
+
+/// Adds the Vault jobs to the stack: `vault-init` unseals the prod server,
+/// and with `store_secrets` the secrets of the other services are moved to
+/// Vault KV and rendered into files by `vault-agent`.
+pub fn wire(services: &mut Vec<Service>, site: &Site, store_secrets: bool, secrets: &mut Secrets) {
+    let mut stored: Vec<String> = vec![];
+    if store_secrets {
+        let movable = movable(services, secrets);
+        for service in services.iter_mut() {
+            for name in inject(service, secrets, &movable) {
+                if !stored.contains(&name) {
+                    stored.push(name);
+                }
+            }
+        }
+    }
+    if site.profile == Profile::Prod || !stored.is_empty() {
+        services.push(init(site, &stored, secrets));
+    }
+    if !stored.is_empty() {
+        let names: Vec<&str> = stored.iter().map(String::as_str).collect();
+        services.push(agent(&names));
+    }
+}
+
+/// Whether a variable holds exactly a secret that its service can read
+/// from a file instead.
+fn is_file_variable(environment: &Environment, value: &str) -> bool {
+    FILE_VARIABLES.contains(&environment.title.as_str()) && environment.value == value
+}
+
+/// The secrets only passed through `*_FILE` capable variables. `vault-init`
+/// generates these in Vault, so the stack must not need their value
+/// anywhere else: a secret also used in a command, a config file or another
+/// variable stays in the compose file.
+fn movable(services: &[Service], secrets: &Secrets) -> Vec<String> {
+    let mut names = vec![];
+    for (name, value) in secrets.iter() {
+        let mut injectable = false;
+        let mut elsewhere = false;
+        for service in services {
+            for environment in &service.environments {
+                if is_file_variable(environment, value) {
+                    injectable = true;
+                } else if environment.value.contains(value) {
+                    elsewhere = true;
+                }
+            }
+            let texts = [&service.command.value]
+                .into_iter()
+                .chain(&service.entrypoint)
+                .chain(service.configs.iter().map(|config| &config.content))
+                .chain(service.labels.iter().map(|label| &label.value))
+                .chain(service.healthcheck.iter().flat_map(|healthcheck| &healthcheck.test));
+            elsewhere = elsewhere || texts.into_iter().any(|text| text.contains(value));
+        }
+        if injectable && !elsewhere {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// Replaces the variables of `service` holding a `movable` secret by the
+/// `_FILE` variant pointing under [`SECRETS_DIR`], and returns the names of
+/// those secrets.
+fn inject(service: &mut Service, secrets: &Secrets, movable: &[String]) -> Vec<String> {
+    let mut names = vec![];
+    for environment in service.environments.iter_mut() {
+        let Some((name, _)) = secrets
+            .iter()
+            .find(|(name, value)| movable.iter().any(|movable| movable == name) && is_file_variable(environment, value))
+        else {
+            continue;
+        };
+        environment.title = format!("{}_FILE", environment.title);
+        environment.value = format!("{}/{}", SECRETS_DIR, name);
+        names.push(name.to_string());
+    }
+    if !names.is_empty() {
+        service.volumes.push(Mount {
+            kind: MountKind::Volume,
+            source: "vault-secrets".to_string(),
+            target: SECRETS_DIR.to_string(),
+            read_only: true,
+        });
+        service.depends_on.push(Dependency {
+            service: "vault-agent".to_string(),
+            condition: Some("service_completed_successfully".to_string()),
+        });
+    }
+    names
+}
+
+/// The one-shot `vault-init` job. In prod it initializes the server with a
+/// single unseal key on first run and unseals it on every run; the key and
+/// root token are kept on the `vault-keys` volume, to be moved somewhere
+/// safer once the stack is up. It then generates the `stored` secrets
+/// missing from KV and issues a short-lived read-only token for
+/// `vault-agent`.
+fn init(site: &Site, stored: &[String], secrets: &mut Secrets) -> Service {
+    let mut script = vec![
+        "set -e".to_string(),
+        format!("export VAULT_ADDR={}", ADDRESS),
+        // `vault status` exits with 1 while the server is unreachable and
+        // with 2 while it is sealed.
+        "until vault status >/dev/null 2>&1; [ $? -ne 1 ]; do sleep 2; done".to_string(),
+    ];
+    let mut environments = vec![];
+    let mut volumes = vec![];
+    match site.profile {
+        Profile::Dev => environments.push(Environment::new("VAULT_TOKEN", &root_token(secrets))),
+        Profile::Prod => {
+            script.extend([
+                "[ -f /vault/keys/init.txt ] || vault operator init -key-shares=1 -key-threshold=1 > /vault/keys/init.txt"
+                    .to_string(),
+                "vault status >/dev/null 2>&1 || vault operator unseal \"$(awk '/Unseal Key 1:/ { print $4 }' /vault/keys/init.txt)\""
+                    .to_string(),
+                "export VAULT_TOKEN=\"$(awk '/Initial Root Token:/ { print $4 }' /vault/keys/init.txt)\"".to_string(),
+                "vault secrets list | grep -q '^secret/' || vault secrets enable -path=secret kv-v2".to_string(),
+            ]);
+            volumes.push(Mount {
+                kind: MountKind::Volume,
+                source: "vault-keys".to_string(),
+                target: "/vault/keys".to_string(),
+                read_only: false,
+            });
+        }
+    }
+    let mut configs = vec![];
+    if !stored.is_empty() {
+        for name in stored {
+            // The values never leave the stack. A secret Vault lost, as the
+            // dev mode server does on restart, is taken back from the file
+            // `vault-agent` rendered, which the services were set up with.
+            script.push(format!(
+                "vault kv get {path}/{name} >/dev/null 2>&1 || {{ if [ -s {dir}/{name} ]; then cat {dir}/{name}; \
+                 else tr -dc A-Za-z0-9 </dev/urandom | head -c {length}; fi | vault kv put {path}/{name} value=- >/dev/null; }}",
+                path = KV_PATH,
+                dir = SECRETS_DIR,
+                name = name,
+                length = secrets.get(name).map_or(32, str::len)
+            ));
+        }
+        script.extend([
+            "vault policy write woors-agent /vault/config/agent-policy.hcl".to_string(),
+            "vault token create -policy=woors-agent -ttl=15m -field=token > /vault/auth/token".to_string(),
+        ]);
+        volumes.push(Mount {
+            kind: MountKind::Volume,
+            source: "vault-auth".to_string(),
+            target: "/vault/auth".to_string(),
+            read_only: false,
+        });
+        volumes.push(Mount {
+            kind: MountKind::Volume,
+            source: "vault-secrets".to_string(),
+            target: SECRETS_DIR.to_string(),
+            read_only: true,
+        });
+        configs.push(agent_policy());
+    }
+
+    Service {
+        image: catalog_image("vault"),
+        container_name: "vault-init".to_string(),
+        ports: vec![],
+        volumes,
+        networks: vec![
+            NetworkAttachment {
+                network: "backend".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        depends_on: vec![
+            Dependency {
+                service: "vault".to_string(),
+                condition: Some("service_started".to_string())
+            }
+        ],
+        restart: Policy {
+            condition: "no".to_string()
+        },
+        environments,
+        command: Command {
+            value: "".to_string()
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "Vault Bootstrap".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.25),
+            memory: Some(64 * MIB),
+            reservation_memory: Some(16 * MIB),
+            ..Default::default()
+        },
+        entrypoint: vec!["/bin/sh".to_string(), "-c".to_string(), script.join("\n")],
+        configs,
+        ..Default::default()
+    }
+}
+
+/// Read access to the stack secrets, and nothing else.
+fn agent_policy() -> ConfigFile {
+    ConfigFile {
+        title: "vault_agent_policy".to_string(),
+        target: "/vault/config/agent-policy.hcl".to_string(),
+        content: "path \"secret/data/woors/*\" {\n  capabilities = [\"read\"]\n}\n".to_string(),
+    }
+}
+
+/// The one-shot `vault-agent` init container: it authenticates with the
+/// token issued by `vault-init`, renders every stored secret into
+/// [`SECRETS_DIR`] and exits.
+fn agent(names: &[&str]) -> Service {
+    Service {
+        image: catalog_image("vault"),
+        container_name: "vault-agent".to_string(),
+        ports: vec![],
+        volumes: vec![
+            Mount {
+                kind: MountKind::Volume,
+                source: "vault-auth".to_string(),
+                target: "/vault/auth".to_string(),
+                read_only: true
+            },
+            Mount {
+                kind: MountKind::Volume,
+                source: "vault-secrets".to_string(),
+                target: SECRETS_DIR.to_string(),
+                read_only: false
+            }
+        ],
+        networks: vec![
+            NetworkAttachment {
+                network: "backend".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        depends_on: vec![
+            Dependency {
+                service: "vault-init".to_string(),
+                condition: Some("service_completed_successfully".to_string())
+            }
+        ],
+        restart: Policy {
+            condition: "no".to_string()
+        },
+        environments: vec![],
+        command: Command {
+            value: "".to_string()
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "Vault Agent".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.25),
+            memory: Some(64 * MIB),
+            reservation_memory: Some(16 * MIB),
+            ..Default::default()
+        },
+        // Bypasses the image entrypoint, which would drop to the `vault`
+        // user and could not write to the fresh volume.
+        entrypoint: vec![
+            "vault".to_string(),
+            "agent".to_string(),
+            "-config=/vault/config/agent.hcl".to_string(),
+        ],
+        configs: vec![agent_config(names)],
+        ..Default::default()
+    }
+}
+
+/// Renders each secret once, then exits (`exit_after_auth`).
+fn agent_config(names: &[&str]) -> ConfigFile {
+    let mut lines = vec![
+        "exit_after_auth = true".to_string(),
+        "vault {".to_string(),
+        format!("  address = \"{}\"", ADDRESS),
+        "}".to_string(),
+        "auto_auth {".to_string(),
+        "  method \"token_file\" {".to_string(),
+        "    config = {".to_string(),
+        "      token_file_path = \"/vault/auth/token\"".to_string(),
+        "    }".to_string(),
+        "  }".to_string(),
+        "}".to_string(),
+    ];
+    for name in names {
+        lines.extend([
+            "template {".to_string(),
+            format!(
+                "  contents = \"{{{{ with secret \\\"secret/data/woors/{}\\\" }}}}{{{{ .Data.data.value }}}}{{{{ end }}}}\"",
+                name
+            ),
+            format!("  destination = \"{}/{}\"", SECRETS_DIR, name),
+            "  perms = \"0444\"".to_string(),
+            "}".to_string(),
+        ]);
+    }
+    ConfigFile {
+        title: "vault_agent_hcl".to_string(),
+        target: "/vault/config/agent.hcl".to_string(),
+        content: lines.join("\n") + "\n",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(name: &str, environments: Vec<Environment>, command: &str) -> Service {
+        Service {
+            container_name: name.to_string(),
+            environments,
+            command: Command {
+                value: command.to_string(),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn secrets() -> Secrets {
+        let mut secrets = Secrets::default();
+        secrets.insert("mysql.password", "mysql-password-value");
+        secrets.insert("mysql.root_password", "mysql-root-password");
+        secrets.insert("redis.password", "redis-password-value");
+        secrets
+    }
+
+    #[test]
+    fn movable_only_keeps_secrets_read_from_files() {
+        let services = vec![
+            service(
+                "mysql",
+                vec![
+                    Environment::new("MYSQL_PASSWORD", "mysql-password-value"),
+                    Environment::new("MYSQL_ROOT_PASSWORD", "mysql-root-password"),
+                ],
+                "",
+            ),
+            // Also needed in a command, so it has to stay in the compose file.
+            service("backup", vec![], "mysqldump -pmysql-root-password"),
+            // Not a `_FILE` capable variable.
+            service("redis", vec![Environment::new("REDIS_PASSWORD", "redis-password-value")], ""),
+        ];
+        assert_eq!(movable(&services, &secrets()), vec!["mysql.password".to_string()]);
+    }
+
+    #[test]
+    fn inject_reads_movable_secrets_from_files() {
+        let mut mysql = service(
+            "mysql",
+            vec![
+                Environment::new("MYSQL_PASSWORD", "mysql-password-value"),
+                Environment::new("MYSQL_ROOT_PASSWORD", "mysql-root-password"),
+            ],
+            "",
+        );
+        let names = inject(&mut mysql, &secrets(), &["mysql.password".to_string()]);
+        assert_eq!(names, vec!["mysql.password".to_string()]);
+        let environments: Vec<(&str, &str)> = mysql
+            .environments
+            .iter()
+            .map(|environment| (environment.title.as_str(), environment.value.as_str()))
+            .collect();
+        assert_eq!(
+            environments,
+            vec![
+                ("MYSQL_PASSWORD_FILE", "/run/secrets/mysql.password"),
+                ("MYSQL_ROOT_PASSWORD", "mysql-root-password"),
+            ]
+        );
+        assert!(mysql
+            .volumes
+            .iter()
+            .any(|mount| mount.source == "vault-secrets" && mount.target == SECRETS_DIR && mount.read_only));
+        assert_eq!(mysql.depends_on[0].service, "vault-agent");
+    }
+
+    #[test]
+    fn inject_leaves_services_without_movable_secrets() {
+        let mut redis = service("redis", vec![Environment::new("REDIS_PASSWORD", "redis-password-value")], "");
+        assert!(inject(&mut redis, &secrets(), &["mysql.password".to_string()]).is_empty());
+        assert_eq!(redis.environments[0].title, "REDIS_PASSWORD");
+        assert!(redis.volumes.is_empty());
+        assert!(redis.depends_on.is_empty());
+    }
+
+    #[test]
+    fn init_keeps_secret_values_out_of_the_stack() {
+        let site = Site {
+            profile: Profile::Prod,
+            ..Default::default()
+        };
+        let init = init(&site, &["mysql.password".to_string()], &mut secrets());
+        let script = &init.entrypoint[2];
+        assert!(!script.contains("mysql-password-value"));
+        assert!(script.contains("head -c 20"));
+        assert!(init.environments.is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::compose::Environment;
+use crate::secrets::Secrets;
+use crate::site::Site;
+use crate::wpcli::shell_quote;
+
+/// Store settings of the `woocommerce` preset.
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct Shop {
+    /// ISO 3166 country code, optionally with a state: `BE`, `US:CA`.
+    pub country: String,
+    /// ISO 4217 currency code.
+    pub currency: String,
+    /// Extra plugins installed and activated after WooCommerce.
+    pub plugins: Vec<String>,
+    /// Themes to install; the first one is activated.
+    pub themes: Vec<String>,
+    /// Defaults to `admin@<site host>`.
+    pub admin_email: Option<String>,
+}
+
+impl Default for Shop {
+    fn default() -> Self {
+        Shop {
+            country: "US".to_string(),
+            currency: "USD".to_string(),
+            plugins: vec![],
+            themes: vec![],
+            admin_email: None,
+        }
+    }
+}
+
+/// The WP-CLI environment of the preset: the generated admin account.
+pub fn environment(site: &Site, shop: &Shop, secrets: &mut Secrets) -> Vec<Environment> {
+    let admin_user = format!("admin_{}", secrets.token("woocommerce.admin_user", 6));
+    let admin_password = secrets.password("woocommerce.admin_password");
+    let admin_email = shop.admin_email.clone().unwrap_or_else(|| format!("admin@{}", site.host()));
+    vec![
+        Environment::new("WOOCOMMERCE_ADMIN_USER", &admin_user),
+        Environment::new("WOOCOMMERCE_ADMIN_PASSWORD", &admin_password),
+        Environment::new("WOOCOMMERCE_ADMIN_EMAIL", &admin_email),
+    ]
+}
+
+/// WP-CLI steps installing WordPress and setting up the store. Credentials
+/// come from the environment so they do not show up in the process list.
+pub fn steps(site: &Site, shop: &Shop) -> Vec<String> {
+    let mut lines = vec![
+        format!(
+            "wp core is-installed || wp core install --url={} --title={} \
+             --admin_user=\"$WOOCOMMERCE_ADMIN_USER\" --admin_password=\"$WOOCOMMERCE_ADMIN_PASSWORD\" \
+             --admin_email=\"$WOOCOMMERCE_ADMIN_EMAIL\" --skip-email",
+            shell_quote(&site.home_url()),
+            shell_quote(&site.title)
+        ),
+        "wp plugin is-installed woocommerce || wp plugin install woocommerce".to_string(),
+        "wp plugin activate woocommerce".to_string(),
+    ];
+    for plugin in &shop.plugins {
+        lines.push(format!("wp plugin install {} --activate", shell_quote(plugin)));
+    }
+    for (index, theme) in shop.themes.iter().enumerate() {
+        let activate = if index == 0 { " --activate" } else { "" };
+        lines.push(format!("wp theme install {}{}", shell_quote(theme), activate));
+    }
+    lines.push(format!("wp option update woocommerce_default_country {}", shell_quote(&shop.country)));
+    lines.push(format!("wp option update woocommerce_currency {}", shell_quote(&shop.currency)));
+    lines
+}
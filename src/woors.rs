@@ -0,0 +1,100 @@
+use crate::compose::{
+    Build, Command, Environment, Healthcheck, Label, Mount, MountKind, NetworkAttachment, Policy, Port, Resources, Service,
+};
+use crate::image::catalog_image;
+use crate::resources::MIB;
+use crate::site::{Profile, Site};
+
+/// Data directory of the image, holding the project store and its key.
+const DATA_DIR: &str = "/var/lib/woors";
+
+/// Port `woors serve` listens on in the image.
+const PORT: &str = "8888";
+
+fn label(key: &str, value: &str) -> Label {
+    Label {
+        key: key.to_string(),
+        value: value.to_string(),
+    }
+}
+
+/// The woors server itself. Unless the image comes from a registry, it is
+/// built from the `Dockerfile` of a woors checkout the compose file is
+/// generated in. Projects are kept on the `woors` volume. Traefik routes
+/// `woors.<host>` to it; the dev profile also publishes the port.
+pub fn service(site: &Site) -> Service {
+    let image = catalog_image("woors");
+    let build = image.registry.is_empty().then(|| Build {
+        context: ".".to_string()
+    });
+    let ports = match site.profile {
+        Profile::Dev => vec![Port {
+            host: PORT.to_string(),
+            container: PORT.to_string(),
+        }],
+        Profile::Prod => vec![],
+    };
+    let router = "traefik.http.routers.woors";
+    Service {
+        image,
+        build,
+        container_name: "woors".to_string(),
+        ports,
+        volumes: vec![
+            Mount {
+                kind: MountKind::Volume,
+                source: "woors".to_string(),
+                target: DATA_DIR.to_string(),
+                read_only: false
+            }
+        ],
+        networks: vec![
+            NetworkAttachment {
+                network: "web".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        environments: vec![
+            Environment::new("WOORS_DATA_DIR", DATA_DIR),
+            Environment::new("WOORS_PORT", PORT),
+            Environment::new("WOORS_PROFILE", site.profile.as_str()),
+            Environment::new("WOORS_LOG_LEVEL", "info"),
+        ],
+        restart: Policy {
+            condition: "always".to_string()
+        },
+        command: Command {
+            value: "serve".to_string()
+        },
+        labels: vec![
+            label("com.example.description", "Woors Server"),
+            label("traefik.enable", "true"),
+            label(&format!("{}.rule", router), &format!("Host(`woors.{}`)", site.host())),
+            label(&format!("{}.entrypoints", router), "web"),
+            label(&format!("{}.tls", router), "true"),
+            label(&format!("{}.tls.certresolver", router), "letsencrypt"),
+            label("traefik.http.services.woors.loadbalancer.server.port", PORT),
+            // Traefik stops routing to a server whose store is unreachable.
+            label("traefik.http.services.woors.loadbalancer.healthcheck.path", "/readyz"),
+            label("traefik.http.services.woors.loadbalancer.healthcheck.interval", "10s"),
+        ],
+        resources: Resources {
+            cpus: Some(0.25),
+            memory: Some(128 * MIB),
+            reservation_memory: Some(32 * MIB),
+            ..Default::default()
+        },
+        healthcheck: Some(Healthcheck {
+            test: vec![
+                "CMD-SHELL".to_string(),
+                format!("wget -q -O /dev/null http://127.0.0.1:{}/healthz || exit 1", PORT)
+            ],
+            interval: "30s".to_string(),
+            timeout: "5s".to_string(),
+            retries: 3,
+            start_period: "5s".to_string()
+        }),
+        ..Default::default()
+    }
+}
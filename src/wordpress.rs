@@ -0,0 +1,143 @@
+use crate::compose::Environment;
+use crate::database::Engine;
+use crate::secrets::Secrets;
+use crate::site::{Profile, Site};
+
+/// Longest user name MySQL and MariaDB accept.
+const MAX_USER_LENGTH: usize = 32;
+
+/// Longest database name MySQL and MariaDB accept.
+const MAX_DATABASE_LENGTH: usize = 64;
+
+/// Databases and users of the server itself, which the entrypoints of the
+/// official images refuse to create for a site.
+const RESERVED_NAMES: [&str; 6] = ["root", "mysql", "sys", "information_schema", "performance_schema", "mariadb"];
+
+/// The keys and salts of `wp-config.php`, by environment variable of the
+/// official image.
+const KEYS: [&str; 8] = [
+    "WORDPRESS_AUTH_KEY",
+    "WORDPRESS_SECURE_AUTH_KEY",
+    "WORDPRESS_LOGGED_IN_KEY",
+    "WORDPRESS_NONCE_KEY",
+    "WORDPRESS_AUTH_SALT",
+    "WORDPRESS_SECURE_AUTH_SALT",
+    "WORDPRESS_LOGGED_IN_SALT",
+    "WORDPRESS_NONCE_SALT",
+];
+
+/// The dedicated database of a site, shared by WordPress and the database
+/// server.
+pub struct Database {
+    pub name: String,
+    pub user: String,
+    pub password: String,
+}
+
+pub fn database(site: &Site, secrets: &mut Secrets) -> Database {
+    let mut name = site.slug();
+    name.truncate(MAX_DATABASE_LENGTH);
+    if RESERVED_NAMES.contains(&name.as_str()) {
+        name.push_str("_wp");
+    }
+    let mut user = name.clone();
+    user.truncate(MAX_USER_LENGTH);
+    Database {
+        name,
+        user,
+        password: secrets.password("wordpress.db_password"),
+    }
+}
+
+/// The environment of the `wordpress` service, apart from
+/// `WORDPRESS_CONFIG_EXTRA` which depends on the other selected services.
+pub fn environment(site: &Site, engine: Engine, secrets: &mut Secrets) -> Vec<Environment> {
+    let database = database(site, secrets);
+    let mut environments = vec![
+        Environment::new("WORDPRESS_DB_HOST", &engine.address()),
+        Environment::new("WORDPRESS_DB_NAME", &database.name),
+        Environment::new("WORDPRESS_DB_USER", &database.user),
+        Environment::new("WORDPRESS_DB_PASSWORD", &database.password),
+        Environment::new("WORDPRESS_TABLE_PREFIX", &table_prefix(secrets)),
+    ];
+    for key in KEYS {
+        let value = secrets.salt(&format!("wordpress.{}", key.trim_start_matches("WORDPRESS_").to_lowercase()));
+        environments.push(Environment::new(key, &value));
+    }
+    if site.profile == Profile::Dev {
+        environments.push(Environment::new("WORDPRESS_DEBUG", "1"));
+    }
+    environments
+}
+
+/// `value` as a single-quoted PHP string.
+fn php_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// PHP appended to `wp-config.php` through `WORDPRESS_CONFIG_EXTRA`;
+/// `extra` holds the constants of the integrated services.
+pub fn config_extra(site: &Site, extra: Vec<String>) -> String {
+    let home = php_string(&site.home_url());
+    let mut lines = vec![
+        format!("define('WP_HOME', {});", home),
+        format!("define('WP_SITEURL', {});", home),
+    ];
+    lines.extend(extra);
+    if site.profile == Profile::Prod {
+        lines.push("define('FORCE_SSL_ADMIN', true);".to_string());
+        lines.push("define('DISALLOW_FILE_EDIT', true);".to_string());
+    }
+    lines.join("\n")
+}
+
+/// A random `wp_xxxxx_` prefix, so tables are not at their well-known names.
+fn table_prefix(secrets: &mut Secrets) -> String {
+    format!("wp_{}_", secrets.token("wordpress.table_prefix", 5))
+}
+
+/// Makes the stack use an existing `wp_xxxxx_` table prefix, e.g. to import
+/// the database of another stack. Returns `false` for other prefixes.
+pub fn reuse_table_prefix(secrets: &mut Secrets, prefix: &str) -> bool {
+    let Some(token) = prefix.strip_prefix("wp_").and_then(|rest| rest.strip_suffix('_')) else {
+        return false;
+    };
+    if token.is_empty() || !token.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()) {
+        return false;
+    }
+    secrets.insert("wordpress.table_prefix", token);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site(title: &str, url: &str) -> Site {
+        Site {
+            title: title.to_string(),
+            url: url.to_string(),
+            profile: Profile::Dev,
+        }
+    }
+
+    #[test]
+    fn database_names_fit_the_server_limits() {
+        let title = "a".repeat(80);
+        let database = database(&site(&title, "shop.com"), &mut Secrets::default());
+        assert_eq!(database.name.len(), MAX_DATABASE_LENGTH);
+        assert_eq!(database.user.len(), MAX_USER_LENGTH);
+    }
+
+    #[test]
+    fn reserved_names_are_suffixed() {
+        let database = database(&site("MySQL", "shop.com"), &mut Secrets::default());
+        assert_eq!((database.name.as_str(), database.user.as_str()), ("mysql_wp", "mysql_wp"));
+    }
+
+    #[test]
+    fn config_extra_escapes_php_strings() {
+        let config = config_extra(&site("shop", "http://shop.com/it's\\"), vec![]);
+        assert!(config.starts_with("define('WP_HOME', 'http://shop.com/it\\'s\\\\');\n"));
+    }
+}
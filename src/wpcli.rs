@@ -0,0 +1,102 @@
+use crate::compose::{
+    Command, Dependency, Environment, Label, Mount, MountKind, NetworkAttachment, Policy, Resources, Service,
+};
+use crate::database::Engine;
+use crate::image::catalog_image;
+use crate::resources::MIB;
+
+/// `www-data` in the Debian based `wordpress` image. The alpine based CLI
+/// image uses another uid, which could not write to the shared volume.
+const WWW_DATA: &str = "33:33";
+
+/// Blocks until WordPress has been installed, by a preset or by hand
+/// through the web installer.
+pub const WAIT_FOR_INSTALL: &str = "until wp core is-installed; do sleep 10; done";
+
+/// The one-shot `wpcli` service running `steps` against the site.
+///
+/// It shares the volume and `wp-config.php` environment of `wordpress`, and
+/// starts once WordPress is healthy. Steps should be idempotent, so
+/// re-running the stack leaves an installed site alone.
+pub fn service(wordpress: &Service, database: Engine, environments: Vec<Environment>, steps: Vec<String>) -> Service {
+    let mut script = vec!["set -e".to_string()];
+    script.extend(steps);
+
+    let mut all_environments = wordpress.environments.clone();
+    all_environments.push(Environment::new("WP_CLI_CACHE_DIR", "/tmp/wp-cli-cache"));
+    all_environments.extend(environments);
+
+    Service {
+        image: catalog_image("wpcli"),
+        container_name: "wpcli".to_string(),
+        ports: vec![],
+        volumes: vec![
+            Mount {
+                kind: MountKind::Volume,
+                source: "wordpress".to_string(),
+                target: "/var/www/html".to_string(),
+                read_only: false
+            }
+        ],
+        networks: vec![
+            NetworkAttachment {
+                network: "web".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            },
+            NetworkAttachment {
+                network: "backend".to_string(),
+                aliases: vec![],
+                ipv4_address: None
+            }
+        ],
+        depends_on: vec![
+            Dependency {
+                service: database.service().to_string(),
+                condition: Some("service_healthy".to_string())
+            },
+            Dependency {
+                service: "wordpress".to_string(),
+                condition: Some("service_healthy".to_string())
+            }
+        ],
+        restart: Policy {
+            condition: "no".to_string()
+        },
+        environments: all_environments,
+        command: Command {
+            value: "".to_string()
+        },
+        labels: vec![
+            Label {
+                key: "com.example.description".to_string(),
+                value: "WP-CLI Jobs".to_string()
+            }
+        ],
+        resources: Resources {
+            cpus: Some(0.5),
+            memory: Some(256 * MIB),
+            reservation_memory: Some(64 * MIB),
+            ..Default::default()
+        },
+        user: Some(WWW_DATA.to_string()),
+        entrypoint: vec!["/bin/sh".to_string(), "-c".to_string(), script.join("\n")],
+        ..Default::default()
+    }
+}
+
+/// Replaces `from` by `to` in every table of the site, e.g. the URL of the
+/// stack a database was imported from. Running it again finds nothing to
+/// replace.
+pub fn search_replace(from: &str, to: &str) -> String {
+    format!(
+        "wp search-replace {} {} --all-tables-with-prefix --skip-columns=guid --report-changed-only",
+        shell_quote(from),
+        shell_quote(to)
+    )
+}
+
+/// Quotes `value` for a POSIX shell.
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
@@ -0,0 +1,77 @@
+// A mock of the WooCommerce Store API serving `products.json`, plus the
+// client itself configured to use the mock.
+import { createServer } from "node:http";
+import { readFile } from "node:fs/promises";
+import { extname, join } from "node:path";
+import { fileURLToPath, pathToFileURL } from "node:url";
+
+const API_PATH = "/wp-json/wc/store/v1";
+const ROOT = fileURLToPath(new URL("..", import.meta.url));
+const TYPES = { ".html": "text/html", ".js": "text/javascript", ".css": "text/css" };
+
+function send(response, status, type, body) {
+  response.writeHead(status, { "Content-Type": type, "Access-Control-Allow-Origin": "*" });
+  response.end(body);
+}
+
+// Errors have the shape of the WordPress REST API.
+function error(response, status, code, message) {
+  send(response, status, "application/json", JSON.stringify({ code, message, data: { status } }));
+}
+
+async function api(response, path, query) {
+  const products = JSON.parse(await readFile(join(ROOT, "mock", "products.json"), "utf8"));
+  if (path === "/products") {
+    const page = Number(query.get("page") ?? 1);
+    const perPage = Number(query.get("per_page") ?? 10);
+    const body = JSON.stringify(products.slice((page - 1) * perPage, page * perPage));
+    return send(response, 200, "application/json", body);
+  }
+  const match = path.match(/^\/products\/(\d+)$/);
+  const product = match && products.find((candidate) => candidate.id === Number(match[1]));
+  if (product) {
+    return send(response, 200, "application/json", JSON.stringify(product));
+  }
+  return error(response, 404, "rest_no_route", "No route was found matching the URL and request method.");
+}
+
+/** Starts the mock on `port` (0 picks a free one). */
+export function startMock(port = 0) {
+  const server = createServer(async (request, response) => {
+    const url = new URL(request.url, "http://localhost");
+    try {
+      if (url.pathname.startsWith(API_PATH)) {
+        return await api(response, url.pathname.slice(API_PATH.length), url.searchParams);
+      }
+      const { port } = server.address();
+      if (url.pathname === "/config.js") {
+        const origin = `http://localhost:${port}`;
+        const config = [
+          `export const SITE_TITLE = "Mock store";`,
+          `export const SITE_URL = "${origin}";`,
+          `export const API_URL = "${origin}${API_PATH}";`,
+        ];
+        return send(response, 200, TYPES[".js"], config.join("\n") + "\n");
+      }
+      const file = url.pathname === "/" ? "index.html" : url.pathname.slice(1);
+      if (file.includes("..")) {
+        return error(response, 404, "not_found", "Not found.");
+      }
+      const body = await readFile(join(ROOT, "src", file));
+      return send(response, 200, TYPES[extname(file)] ?? "application/octet-stream", body);
+    } catch {
+      return error(response, 404, "not_found", "Not found.");
+    }
+  });
+  return new Promise((resolve) => {
+    server.listen(port, () => {
+      const url = `http://localhost:${server.address().port}`;
+      resolve({ url, apiUrl: `${url}${API_PATH}`, close: () => server.close() });
+    });
+  });
+}
+
+if (import.meta.url === pathToFileURL(process.argv[1]).href) {
+  const mock = await startMock(Number(process.env.PORT ?? 8787));
+  console.log(`mock store on ${mock.url}`);
+}
@@ -0,0 +1,19 @@
+// Client of the WooCommerce Store API.
+
+/** Fetches one page of the products of the store. */
+export async function fetchProducts(apiUrl, { page = 1, perPage = 20, fetch = globalThis.fetch } = {}) {
+  const response = await fetch(`${apiUrl}/products?page=${page}&per_page=${perPage}`, {
+    headers: { Accept: "application/json" },
+  });
+  if (!response.ok) {
+    throw new Error(`GET /products failed with ${response.status}`);
+  }
+  return response.json();
+}
+
+/** Formats the `prices` object of a Store API product, e.g. `$19.99`. */
+export function formatPrice(prices) {
+  const unit = prices.currency_minor_unit;
+  const value = (Number(prices.price) / 10 ** unit).toFixed(unit);
+  return `${prices.currency_prefix}${value}${prices.currency_suffix}`;
+}
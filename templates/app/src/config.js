@@ -0,0 +1,5 @@
+// Generated by `woors app`.
+export const SITE_TITLE = {{site_title}};
+export const SITE_URL = {{site_url}};
+// WooCommerce Store API, which lists products without credentials.
+export const API_URL = {{api_url}};
@@ -0,0 +1,36 @@
+import { API_URL, SITE_TITLE, SITE_URL } from "./config.js";
+import { fetchProducts, formatPrice } from "./api.js";
+
+document.title = SITE_TITLE;
+document.getElementById("title").textContent = SITE_TITLE;
+document.getElementById("site").href = SITE_URL;
+
+const status = document.getElementById("status");
+const list = document.getElementById("products");
+
+try {
+  const products = await fetchProducts(API_URL);
+  status.textContent = products.length === 0 ? "No products yet." : "";
+  for (const product of products) {
+    const item = document.createElement("li");
+    const link = document.createElement("a");
+    link.href = product.permalink;
+    link.target = "_blank";
+    link.rel = "noopener";
+    if (product.images.length > 0) {
+      const image = document.createElement("img");
+      image.src = product.images[0].thumbnail;
+      image.alt = product.images[0].alt;
+      link.append(image);
+    }
+    const name = document.createElement("h2");
+    name.textContent = product.name;
+    const price = document.createElement("p");
+    price.textContent = formatPrice(product.prices);
+    link.append(name, price);
+    item.append(link);
+    list.append(item);
+  }
+} catch (error) {
+  status.textContent = `Could not load the products: ${error.message}`;
+}
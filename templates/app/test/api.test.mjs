@@ -0,0 +1,40 @@
+import assert from "node:assert/strict";
+import test from "node:test";
+
+import { startMock } from "../mock/server.mjs";
+import { fetchProducts, formatPrice } from "../src/api.js";
+
+test("lists the products of the store", async (t) => {
+  const mock = await startMock();
+  t.after(mock.close);
+
+  const products = await fetchProducts(mock.apiUrl);
+  assert.deepEqual(
+    products.map((product) => product.name),
+    ["Hoodie", "Beanie"],
+  );
+});
+
+test("pages through the products", async (t) => {
+  const mock = await startMock();
+  t.after(mock.close);
+
+  const products = await fetchProducts(mock.apiUrl, { page: 2, perPage: 1 });
+  assert.deepEqual(
+    products.map((product) => product.id),
+    [2],
+  );
+});
+
+test("reports API errors", async (t) => {
+  const mock = await startMock();
+  t.after(mock.close);
+
+  await assert.rejects(fetchProducts(`${mock.url}/missing`), /404/);
+});
+
+test("formats Store API prices", () => {
+  const prices = { price: "1800", currency_minor_unit: 2, currency_prefix: "$", currency_suffix: "" };
+  assert.equal(formatPrice(prices), "$18.00");
+  assert.equal(formatPrice({ ...prices, price: "1800", currency_minor_unit: 0, currency_prefix: "", currency_suffix: " kr" }), "1800 kr");
+});